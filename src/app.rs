@@ -1,78 +1,205 @@
 pub mod events;
+mod input;
+mod navigation;
 
-use termion::input::TermRead;
-
+use std::collections::VecDeque;
 use std::sync::mpsc;
+use std::rc::Rc;
 
 use crate::frontend::screen::*;
 use crate::frontend::repo_selection_handler::RepoSelectionHandler;
-use crate::frontend::main_screen_handler::MainScreenHandler;
-use crate::backend::task::*;
+use crate::frontend::main_screen_handler::{MainScreenHandler, Review};
+use crate::frontend::keymap::{Action, Keymap, KeymapResult};
+use crate::frontend::help_overlay::HelpOverlay;
+use crate::frontend::status_line::{StatusHints, StatusLine};
 use crate::backend::gh::GhClient;
+use crate::backend::cache::Cache;
+use crate::backend::diff::DiffSource;
+use crate::backend::task::TaskManager;
+use crate::config::Config;
 use crate::error::Error;
 
 use tui::backend::Backend;
+use tui::layout::{Constraint, Direction, Layout};
 use tui::Terminal;
+use termion::event::Key;
 
 use events::AppEvent;
+use input::{InputReader, SignalListener};
+use navigation::ScreenStack;
 
 pub struct App<'a, B: Backend>  {
     terminal: &'a mut Terminal<B>,
     gh_client: GhClient,
+    cache: Cache,
+    offline: bool,
+    diff_source: DiffSource,
+    config: Rc<Config>,
+    keymap: Keymap,
+    task_manager: TaskManager,
     event_listener: mpsc::Receiver<AppEvent>,
     sender: mpsc::Sender<AppEvent>,
 }
 
 impl<'a, B: Backend> App<'a, B> {
 
-    pub fn new(terminal: &'a mut Terminal<B>, gh_client: GhClient) -> Self {
+    pub fn new(terminal: &'a mut Terminal<B>, gh_client: GhClient, cache: Cache, offline: bool, diff_source: DiffSource, config: Rc<Config>) -> Self {
         let (sender, event_listener) = mpsc::channel::<AppEvent>();
-        App {terminal, gh_client, event_listener, sender}
+        let keymap = Keymap::new(&config.keys);
+        let task_manager = TaskManager::new(sender.clone());
+        App {terminal, gh_client, cache, offline, diff_source, config, keymap, task_manager, event_listener, sender}
     }
 
     pub fn run(mut self, pr_number: Option<u32>) -> Result<(), Error> {
 
-        let mut task_manager = TaskManager::new(self.sender.clone());
-        let mut current_screen_handler : Box<dyn ScreenHandler<B>> = if let Some(pr_number) = pr_number {
-            Box::new(MainScreenHandler::new(pr_number, self.sender.clone(), &self.gh_client))
+        let mut screens = ScreenStack::new();
+        let screen_id = screens.reserve_id();
+        let task_manager = self.task_manager.for_screen(screen_id);
+        // A range of commits can be reviewed without a pull request
+        let has_range = matches!(self.diff_source, DiffSource::Range(_));
+        let first_screen : Box<dyn ScreenHandler<B>> = if pr_number.is_some() || has_range {
+            let review = Review {number: pr_number, diff_source: self.diff_source.clone()};
+            Box::new(MainScreenHandler::new(review, self.sender.clone(), task_manager, &self.gh_client, self.cache.clone(), self.offline, Rc::clone(&self.config)))
         } else {
-            Box::new(RepoSelectionHandler::new(self.sender.clone(), &mut task_manager, &mut self.gh_client))
+            Box::new(RepoSelectionHandler::new(self.sender.clone(), task_manager, &self.gh_client, self.cache.clone(), self.offline, Rc::clone(&self.config)))
         };
+        screens.push(screen_id, first_screen);
         self.sender.send(AppEvent::ScreenRepaint).unwrap();
 
-        let input_sender = self.sender.clone();
-        std::thread::spawn(move || {
-            for key in std::io::stdin().keys() {
-                if let Some(key) = key.ok() {
-                    input_sender.send(AppEvent::Input(key)).unwrap();
-                }
-            }
-        });
+        let mut input_reader = InputReader::start(self.sender.clone());
+        let mut signal_listener = SignalListener::start(self.sender.clone())?;
 
+        let mut show_help = false;
+        let mut status_line = StatusLine::new();
+        // Pasted keys handled one by one, before the events that came after them
+        let mut replayed = VecDeque::new();
         loop {
-            if let Some(evt) = self.event_listener.recv().ok() {
+            if let Some(evt) = replayed.pop_front().or_else(|| self.event_listener.recv().ok()) {
                 match evt {
                     AppEvent::RepoChosen(number) => {
-                        current_screen_handler = Box::new(MainScreenHandler::new(number, self.sender.clone(), &self.gh_client));
+                        let screen_id = screens.reserve_id();
+                        let task_manager = self.task_manager.for_screen(screen_id);
+                        let review = Review {number: Some(number), diff_source: self.diff_source.clone()};
+                        let screen = MainScreenHandler::new(review, self.sender.clone(), task_manager, &self.gh_client, self.cache.clone(), self.offline, Rc::clone(&self.config));
+                        screens.push(screen_id, Box::new(screen));
                         self.sender.send(AppEvent::ScreenRepaint).unwrap();
                     },
 
+                    // Outside of the text input the keys are bound to actions, so they aren't pasted
+                    AppEvent::Paste(text) => {
+                        let current_screen_handler = screens.current();
+                        if current_screen_handler.keymap_context().is_text_input() && !show_help {
+                            self.keymap.reset();
+                            current_screen_handler.process_paste(&text);
+                        } else {
+                            replayed.extend(text.chars().map(|c| AppEvent::Input(Key::Char(c))));
+                        }
+                    },
+
                     AppEvent::Input(key) => {
-                        if current_screen_handler.validate_input(key) {
-                            current_screen_handler.process_input(key);
-                        } else if key == termion::event::Key::Char('q') {
-                            break;
+                        if status_line.clear_notice() {
+                            self.sender.send(AppEvent::ScreenRepaint).unwrap();
+                        }
+                        let current_screen_handler = screens.current();
+                        let context = current_screen_handler.keymap_context();
+                        let result = self.keymap.feed(context, key);
+                        if let KeymapResult::Unbound = result {
+                            if context.is_text_input() && !show_help {
+                                current_screen_handler.process_text_input(key);
+                            }
+                        } else if let KeymapResult::Action(action, count) = result {
+                            if show_help {
+                                show_help = !matches!(action, Action::Help | Action::Back | Action::Quit);
+                                self.sender.send(AppEvent::ScreenRepaint).unwrap();
+                            } else if action == Action::Back && status_line.has_errors() {
+                                status_line.dismiss_error();
+                                self.sender.send(AppEvent::ScreenRepaint).unwrap();
+                            } else if current_screen_handler.validate_input(action) {
+                                if action == Action::Refresh {
+                                    status_line.clear_errors();
+                                    self.sender.send(AppEvent::ScreenRepaint).unwrap();
+                                }
+                                current_screen_handler.process_input(action, count);
+                            } else if action == Action::Back && screens.pop() {
+                                self.sender.send(AppEvent::ScreenRepaint).unwrap();
+                            } else if action == Action::Help {
+                                show_help = true;
+                                self.sender.send(AppEvent::ScreenRepaint).unwrap();
+                            } else if action == Action::Quit {
+                                break;
+                            }
+                        }
+                    },
+
+                    AppEvent::ScreenRepaint => {
+                        let keymap = &self.keymap;
+                        let theme = &self.config.theme;
+                        let current_screen_handler = screens.current();
+                        let context = current_screen_handler.keymap_context();
+                        let hints = [Action::Help, Action::Refresh, Action::Back].map(|a| keymap.key_hint(context, a));
+                        let hidden = Action::ALL.iter().copied().filter(|a| current_screen_handler.hides_action(*a)).collect();
+                        self.terminal.draw(|f| {
+                            let layout = Layout::default()
+                                .direction(Direction::Vertical)
+                                .constraints([Constraint::Min(0), Constraint::Length(1)])
+                                .split(f.size());
+                            current_screen_handler.draw(f, layout[0]);
+                            let hints = StatusHints { help: &hints[0], retry: &hints[1], dismiss: &hints[2] };
+                            f.render_widget(status_line.widget(theme, hints), layout[1]);
+                            if show_help {
+                                f.render_widget(HelpOverlay::new(keymap, context, theme).hide(hidden), f.size());
+                            }
+                        }).unwrap();
+                    },
+                    AppEvent::TaskCompleted(screen, task) => screens.deliver(screen, task),
+
+                    AppEvent::TaskProgress(progress) => {
+                        status_line.update_task(progress);
+                        self.sender.send(AppEvent::ScreenRepaint).unwrap();
+                    },
+
+                    AppEvent::Tick => {
+                        if status_line.tick() {
+                            self.sender.send(AppEvent::ScreenRepaint).unwrap();
                         }
                     },
 
-                    AppEvent::ScreenRepaint => { self.terminal.draw(|f| current_screen_handler.draw(f)).unwrap(); },
-                    AppEvent::TaskCompleted => current_screen_handler.update(),
+                    AppEvent::Error(error) => {
+                        crate::logs::error!("Reported to the user", error = error);
+                        status_line.push_error(error);
+                        self.sender.send(AppEvent::ScreenRepaint).unwrap();
+                    },
+
+                    AppEvent::Notice(notice) => {
+                        status_line.set_notice(notice);
+                        self.sender.send(AppEvent::ScreenRepaint).unwrap();
+                    },
 
-                    AppEvent::Error(message) => crate::logs::log(&format!("ERROR: {}", message)), //TODO handle the error
+                    // The input thread would take the keys meant for the program
+                    AppEvent::RunInTerminal(mut cmd) => {
+                        input_reader.stop();
+                        let result = crate::terminal::run_in_terminal(&mut cmd);
+                        input_reader = InputReader::start(self.sender.clone());
+                        self.keymap.reset();
+                        self.terminal.clear()?;
+                        screens.current().terminal_command_finished(result);
+                        self.sender.send(AppEvent::ScreenRepaint).unwrap();
+                    },
+
+                    AppEvent::Quit => break,
                 }
             }
         }
 
+        input_reader.stop();
+        signal_listener.stop();
         Ok(())
     }
 }
+
+// Tasks writing to the repository, e.g. a checkout, are let finish
+impl<'a, B: Backend> Drop for App<'a, B> {
+    fn drop(&mut self) {
+        self.task_manager.shut_down();
+    }
+}
@@ -1,7 +1,16 @@
 pub enum AppEvent {
     RepoChosen (u32),
-    Error(String),
+    Error(crate::error::Error),
+    // Outcome worth telling about, shown until the next key
+    Notice(String),
     ScreenRepaint,
     Input(termion::event::Key),
-    TaskCompleted,
+    // Text pasted into the terminal, the new lines in it don't submit anything
+    Paste(String),
+    TaskCompleted(crate::frontend::screen::ScreenId, crate::backend::task::TaskId),
+    TaskProgress(crate::backend::task::TaskProgress),
+    Tick,
+    // Runs a program, e.g. an editor, in place of the UI until it exits
+    RunInTerminal(std::process::Command),
+    Quit,
 }
@@ -0,0 +1,167 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyModifiers};
+use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM};
+use signal_hook::iterator::Signals;
+use termion::event::Key;
+
+use super::events::AppEvent;
+use crate::error::Error;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+// Reads the terminal events on a separate thread. Unlike blocking on stdin,
+// polling lets the thread notice it has been stopped and exit, so it can be joined.
+pub struct InputReader {
+    is_running: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl InputReader {
+    pub fn start(sender: mpsc::Sender<AppEvent>) -> Self {
+        let is_running = Arc::new(AtomicBool::new(true));
+        let thread_is_running = Arc::clone(&is_running);
+        let thread = thread::Builder::new()
+            .name("input".to_string())
+            .spawn(move || {
+                // Event that ended a paste, it's handled on its own
+                let mut next_event = None;
+                while thread_is_running.load(Ordering::Relaxed) {
+                    if next_event.is_none() {
+                        match event::poll(POLL_INTERVAL) {
+                            Ok(true) => (),
+                            Ok(false) => {
+                                if sender.send(AppEvent::Tick).is_err() {
+                                    break;
+                                }
+                                continue;
+                            },
+                            Err(_) => break,
+                        }
+                    }
+
+                    let app_event = match next_event.take().map_or_else(event::read, Ok) {
+                        Ok(Event::Key(key_event)) => match convert_key(key_event) {
+                            Some(Key::Char(c)) => {
+                                let (text, event) = read_paste(c);
+                                next_event = event;
+                                if text.chars().count() > 1 {
+                                    Some(AppEvent::Paste(text))
+                                } else {
+                                    Some(AppEvent::Input(Key::Char(c)))
+                                }
+                            },
+                            key => key.map(AppEvent::Input),
+                        },
+                        Ok(Event::Resize(_, _)) => Some(AppEvent::ScreenRepaint),
+                        Ok(_) => None,
+                        Err(_) => break,
+                    };
+
+                    if let Some(app_event) = app_event {
+                        if sender.send(app_event).is_err() {
+                            break;
+                        }
+                    }
+                }
+            })
+            .expect("Failed to start the input thread");
+
+        InputReader { is_running, thread: Some(thread) }
+    }
+
+    pub fn stop(&mut self) {
+        self.is_running.store(false, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for InputReader {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+// crossterm doesn't support the bracketed paste, so characters that are already waiting
+// to be read along with the first one are taken as pasted. Keys typed by hand come one by one
+fn read_paste(first: char) -> (String, Option<Event>) {
+    let mut text = first.to_string();
+    while let Ok(true) = event::poll(Duration::ZERO) {
+        match event::read() {
+            Ok(Event::Key(key_event)) => match convert_key(key_event) {
+                Some(Key::Char(c)) => text.push(c),
+                _ => return (text, Some(Event::Key(key_event))),
+            },
+            Ok(event) => return (text, Some(event)),
+            Err(_) => break,
+        }
+    }
+    (text, None)
+}
+
+fn convert_key(key_event: KeyEvent) -> Option<Key> {
+    let key = match key_event.code {
+        KeyCode::Char(c) if key_event.modifiers.contains(KeyModifiers::CONTROL) => Key::Ctrl(c),
+        KeyCode::Char(c) if key_event.modifiers.contains(KeyModifiers::ALT) => Key::Alt(c),
+        KeyCode::Char(c) => Key::Char(c),
+        KeyCode::Enter => Key::Char('\n'),
+        KeyCode::Tab => Key::Char('\t'),
+        KeyCode::BackTab => Key::BackTab,
+        KeyCode::Backspace => Key::Backspace,
+        KeyCode::Delete => Key::Delete,
+        KeyCode::Insert => Key::Insert,
+        KeyCode::Esc => Key::Esc,
+        KeyCode::Left => Key::Left,
+        KeyCode::Right => Key::Right,
+        KeyCode::Up => Key::Up,
+        KeyCode::Down => Key::Down,
+        KeyCode::Home => Key::Home,
+        KeyCode::End => Key::End,
+        KeyCode::PageUp => Key::PageUp,
+        KeyCode::PageDown => Key::PageDown,
+        KeyCode::F(n) => Key::F(n),
+        KeyCode::Null => return None,
+    };
+    Some(key)
+}
+
+// Turns the termination signals into a regular quit, so the terminal is
+// restored the same way as when the user quits
+pub struct SignalListener {
+    handle: signal_hook::iterator::Handle,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl SignalListener {
+    pub fn start(sender: mpsc::Sender<AppEvent>) -> Result<Self, Error> {
+        let mut signals = Signals::new([SIGINT, SIGTERM, SIGHUP])?;
+        let handle = signals.handle();
+        let thread = thread::Builder::new()
+            .name("signals".to_string())
+            .spawn(move || {
+                if signals.forever().next().is_some() {
+                    let _ = sender.send(AppEvent::Quit);
+                }
+            })?;
+
+        Ok(SignalListener { handle, thread: Some(thread) })
+    }
+
+    pub fn stop(&mut self) {
+        self.handle.close();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for SignalListener {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
@@ -0,0 +1,67 @@
+use std::collections::HashMap;
+
+use tui::backend::Backend;
+
+use crate::backend::task::TaskId;
+use crate::frontend::screen::{ScreenHandler, ScreenId};
+
+// Screens the user has navigated through, the last one is shown.
+// Task completions for the screens in the background are kept until
+// the user gets back to them.
+pub struct ScreenStack<'a, B: Backend> {
+    screens: Vec<(ScreenId, Box<dyn ScreenHandler<B> + 'a>)>,
+    pending: HashMap<ScreenId, Vec<TaskId>>,
+    next_id: ScreenId,
+}
+
+impl<'a, B: Backend> ScreenStack<'a, B> {
+    pub fn new() -> Self {
+        ScreenStack { screens: vec![], pending: HashMap::new(), next_id: 1 }
+    }
+
+    // Id for the next screen, so it can be given to its task manager before it's pushed
+    pub fn reserve_id(&mut self) -> ScreenId {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    pub fn push(&mut self, id: ScreenId, screen: Box<dyn ScreenHandler<B> + 'a>) {
+        self.screens.push((id, screen));
+    }
+
+    // The first screen is never popped
+    pub fn pop(&mut self) -> bool {
+        if self.screens.len() < 2 {
+            return false;
+        }
+
+        if let Some((id, _)) = self.screens.pop() {
+            self.pending.remove(&id);
+        }
+        self.flush_pending();
+        true
+    }
+
+    pub fn current(&mut self) -> &mut (dyn ScreenHandler<B> + 'a) {
+        self.screens.last_mut().expect("Screen stack is empty").1.as_mut()
+    }
+
+    pub fn deliver(&mut self, screen: ScreenId, task: TaskId) {
+        match self.screens.iter().position(|(id, _)| *id == screen) {
+            Some(index) if index + 1 == self.screens.len() => self.current().update(task),
+            Some(_) => self.pending.entry(screen).or_default().push(task),
+            None => crate::logs::debug!("Dropped the result for a closed screen", screen = screen, task = task),
+        }
+    }
+
+    fn flush_pending(&mut self) {
+        let id = match self.screens.last() {
+            Some((id, _)) => *id,
+            None => return,
+        };
+        for task in self.pending.remove(&id).unwrap_or_default() {
+            self.current().update(task);
+        }
+    }
+}
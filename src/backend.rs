@@ -2,3 +2,7 @@ pub mod pr;
 pub mod gh;
 pub mod task;
 pub mod diff;
+pub mod cache;
+pub mod editor;
+pub mod browser;
+pub mod git;
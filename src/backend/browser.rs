@@ -0,0 +1,22 @@
+use std::process::{Command, Stdio};
+
+use crate::error::Error;
+
+// $BROWSER, otherwise the desktop's handler for the URL. The browser is left running
+pub fn open(url: &str) -> Result<(), Error> {
+    let browser = std::env::var("BROWSER").ok()
+        .filter(|value| !value.trim().is_empty())
+        .unwrap_or_else(|| if cfg!(target_os = "macos") { "open" } else { "xdg-open" }.to_string());
+    let mut words = browser.split_whitespace();
+    let program = words.next().unwrap_or("xdg-open").to_string();
+    let mut child = Command::new(&program)
+        .args(words)
+        .arg(url)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|error| Error::Other(format!("Failed to run {}: {}", program, error)))?;
+    std::thread::spawn(move || child.wait());
+    Ok(())
+}
@@ -0,0 +1,167 @@
+use std::fs;
+use std::path::PathBuf;
+use chrono::{DateTime, Local};
+use json::JsonValue;
+
+const PR_LIST_FILE: &str = "pr_list.json";
+const CONVERSATION_FILE: &str = "conversation.json";
+const DIFF_FILE: &str = "diff.patch";
+const HEAD_FILE: &str = "HEAD";
+const REVIEWED_HEAD_FILE: &str = "REVIEWED_HEAD";
+
+#[derive(Clone)]
+pub struct Cache {
+    root: Option<PathBuf>,
+}
+
+pub struct CacheEntry<T> {
+    pub data: T,
+    pub timestamp: DateTime<Local>,
+}
+
+impl Cache {
+    // GitHub ignores the case of the names, so does the cache
+    pub fn new(repo_owner: &str, repo_name: &str) -> Self {
+        let root = xdg::BaseDirectories::with_prefix("ghterm")
+            .map(|dirs| dirs.get_cache_home().join(repo_owner.to_lowercase()).join(repo_name.to_lowercase()))
+            .ok();
+
+        Cache { root }
+    }
+
+    pub fn load_pr_list(&self) -> Option<CacheEntry<JsonValue>> {
+        let path = self.root.as_ref()?.join(PR_LIST_FILE);
+        read_entry(path).and_then(parse_json_entry)
+    }
+
+    pub fn store_pr_list(&self, json: &JsonValue) {
+        if let Some(root) = self.root.as_ref() {
+            self.write(root.clone(), PR_LIST_FILE, &json.dump());
+        }
+    }
+
+    pub fn load_conversation(&self, number: u32) -> Option<CacheEntry<JsonValue>> {
+        let path = self.head_dir(number)?.join(CONVERSATION_FILE);
+        read_entry(path).and_then(parse_json_entry)
+    }
+
+    pub fn store_conversation(&self, number: u32, head_sha: &str, json: &JsonValue) {
+        if let Some(dir) = self.switch_head(number, head_sha) {
+            self.write(dir, CONVERSATION_FILE, &json.dump());
+        }
+    }
+
+    pub fn load_diff(&self, number: u32) -> Option<CacheEntry<String>> {
+        let path = self.head_dir(number)?.join(DIFF_FILE);
+        read_entry(path)
+    }
+
+    pub fn store_diff(&self, number: u32, head_sha: &str, diff: &str) {
+        if let Some(dir) = self.switch_head(number, head_sha) {
+            self.write(dir, DIFF_FILE, diff);
+        }
+    }
+
+    // Head the viewer last commented on from ghterm. It outlives the entries of the heads
+    pub fn load_reviewed_head(&self, number: u32) -> Option<CacheEntry<String>> {
+        let path = self.pr_dir(number)?.join(REVIEWED_HEAD_FILE);
+        read_entry(path)
+    }
+
+    pub fn store_reviewed_head(&self, number: u32, head_sha: &str) {
+        if let Some(dir) = self.pr_dir(number) {
+            self.write(dir, REVIEWED_HEAD_FILE, head_sha);
+        }
+    }
+
+    fn pr_dir(&self, number: u32) -> Option<PathBuf> {
+        self.root.as_ref().map(|r| r.join(format!("pr_{}", number)))
+    }
+
+    fn head_dir(&self, number: u32) -> Option<PathBuf> {
+        let pr_dir = self.pr_dir(number)?;
+        let head_sha = fs::read_to_string(pr_dir.join(HEAD_FILE)).ok()?;
+        Some(pr_dir.join(head_sha.trim()))
+    }
+
+    // Points the PR at a new head commit. Entries cached for older heads are
+    // removed since they can't be shown as the current state of the PR anymore.
+    fn switch_head(&self, number: u32, head_sha: &str) -> Option<PathBuf> {
+        let pr_dir = self.pr_dir(number)?;
+        let head_dir = pr_dir.join(head_sha);
+        let current_head = fs::read_to_string(pr_dir.join(HEAD_FILE)).ok();
+        if current_head.as_deref().map(|s| s.trim()) == Some(head_sha) {
+            return Some(head_dir);
+        }
+
+        if let Ok(entries) = fs::read_dir(&pr_dir) {
+            entries.filter_map(|e| e.ok())
+                .filter(|e| e.path().is_dir())
+                .for_each(|e| { let _ = fs::remove_dir_all(e.path()); });
+        }
+
+        self.write(pr_dir, HEAD_FILE, head_sha);
+        Some(head_dir)
+    }
+
+    fn write(&self, dir: PathBuf, file_name: &str, contents: &str) {
+        let result = fs::create_dir_all(&dir)
+            .and_then(|_| fs::write(dir.join(file_name), contents));
+        if let Err(e) = result {
+            crate::logs::warning!("Failed to write to the cache", file = file_name, error = e);
+        }
+    }
+}
+
+fn read_entry(path: PathBuf) -> Option<CacheEntry<String>> {
+    let timestamp = fs::metadata(&path).and_then(|m| m.modified()).ok()?;
+    let data = fs::read_to_string(&path).ok()?;
+    Some(CacheEntry { data, timestamp: DateTime::from(timestamp) })
+}
+
+fn parse_json_entry(entry: CacheEntry<String>) -> Option<CacheEntry<JsonValue>> {
+    match json::parse(&entry.data) {
+        Ok(data) => Some(CacheEntry { data, timestamp: entry.timestamp }),
+        Err(e) => {
+            crate::logs::warning!("Cache entry is corrupted", error = e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn temp_cache(name: &str) -> Cache {
+        let root = std::env::temp_dir().join(format!("ghterm_cache_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        Cache { root: Some(root) }
+    }
+
+    #[test]
+    fn entries_follow_head() {
+        let cache = temp_cache("head");
+        cache.store_diff(1, "aaa", "old diff");
+        cache.store_conversation(1, "aaa", &json::object!{ "old": true });
+        assert_eq!(cache.load_diff(1).unwrap().data, "old diff");
+
+        cache.store_diff(1, "bbb", "new diff");
+        assert_eq!(cache.load_diff(1).unwrap().data, "new diff");
+        assert!(cache.load_conversation(1).is_none());
+        assert!(cache.load_diff(2).is_none());
+
+        cache.store_reviewed_head(1, "bbb");
+        cache.store_diff(1, "ccc", "newer diff");
+        assert_eq!(cache.load_reviewed_head(1).unwrap().data, "bbb");
+    }
+
+    #[test]
+    fn pr_list_roundtrip() {
+        let cache = temp_cache("list");
+        assert!(cache.load_pr_list().is_none());
+        cache.store_pr_list(&json::array![1, 2, 3]);
+        assert_eq!(cache.load_pr_list().unwrap().data, json::array![1, 2, 3]);
+    }
+}
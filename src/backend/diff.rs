@@ -7,6 +7,17 @@ pub struct DiffRequest {
     cmd: Command,
 }
 
+// Where the diff of the reviewed changes comes from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiffSource {
+    // `gh pr diff`, which the API truncates for large pull requests
+    Api,
+    // `git diff base...head` of the pull request in the local clone
+    Local,
+    // Anything `git diff` takes, e.g. `main...feature` or `HEAD~3`
+    Range(String),
+}
+
 #[derive(Debug)]
 pub enum DiffSide { Left, Right }
 
@@ -23,6 +34,7 @@ pub struct Range(usize, usize);
 
 pub struct FileDiffRef {
     pub start_line: usize,
+    pub end_line: usize,
     pub header_range: Range,
     pub hunks: Vec<HunkDiffRef>,
 }
@@ -54,11 +66,11 @@ impl DiffRequest {
     pub fn execute(&mut self) -> Result<String, Error> {
         let output = self.cmd.output().map_err(|e| Error::Other(e.to_string()))?;
         if !output.status.success() {
-            return Err(Error::Other(String::from_utf8(output.stderr).unwrap()));
+            return Err(Error::Other(String::from_utf8_lossy(&output.stderr).to_string()));
         }
 
-        let raw_diff = String::from_utf8(output.stdout).unwrap();
-        crate::logs::log(&raw_diff);
+        let raw_diff = String::from_utf8_lossy(&output.stdout).to_string();
+        crate::logs::debug!("Fetched the diff", diff = raw_diff);
         Ok(raw_diff)
     }
 }
@@ -71,7 +83,7 @@ impl CodeRange {
 
 impl FileDiffRef {
     pub fn new(start_line: usize) -> Self {
-        FileDiffRef { start_line, header_range: Range(0, 0), hunks: vec![] }
+        FileDiffRef { start_line, end_line: start_line, header_range: Range(0, 0), hunks: vec![] }
     }
 }
 
@@ -85,6 +97,15 @@ impl DiffReadingState {
     pub fn new() -> Self {
         DiffReadingState { line_index: 0, current_file_name: String::new(), current_file: FileDiffRef::new(0), current_hunk: None }
     }
+
+    fn finish(mut self, end_line: usize, files: &mut HashMap<String, FileDiffRef>) {
+        if let Some(mut current_hunk) = self.current_hunk.take() {
+            current_hunk.changelist_range.1 = end_line;
+            self.current_file.hunks.push(current_hunk);
+        }
+        self.current_file.end_line = end_line;
+        files.insert(self.current_file_name, self.current_file);
+    }
 }
 
 impl ChangeList {
@@ -92,18 +113,13 @@ impl ChangeList {
         let mut files = HashMap::new();
         let mut reading_state : Option<DiffReadingState> = None;
         let file_name_regex = Regex::new(r"^diff --git a/(.+) b/(.+)$").unwrap();
-        let hunk_regex = Regex::new(r"^@@ -(\d+,\d+) \+(\d+,\d+) @@").unwrap();
+        let hunk_regex = Regex::new(r"^@@ -(\d+(?:,\d+)?) \+(\d+(?:,\d+)?) @@").unwrap();
         
         for (line_index, line) in diff.lines().enumerate() {
 
             if let Some(captures) = file_name_regex.captures(line) {
-                if let Some(mut reading_state) = reading_state {
-                    if let Some(mut current_hunk) = reading_state.current_hunk.take() {
-                        current_hunk.changelist_range.1 = line_index - 1;
-                        reading_state.current_file.hunks.push(current_hunk);
-                    }
-
-                    files.insert(reading_state.current_file_name, reading_state.current_file);
+                if let Some(reading_state) = reading_state {
+                    reading_state.finish(line_index - 1, &mut files);
                 }
 
                 let mut new_state = DiffReadingState::new();
@@ -138,9 +154,36 @@ impl ChangeList {
             }
         }
 
+        if let Some(reading_state) = reading_state {
+            reading_state.finish(diff.lines().count() - 1, &mut files);
+        }
+
         ChangeList { raw: diff, files }
     }
 
+    pub fn file_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.files.keys().map(|name| name.as_str()).collect();
+        names.sort_unstable();
+        names
+    }
+
+    // Whole diff of the file, header included
+    pub fn file_diff(&self, file_name: &str) -> &str {
+        let file = match self.files.get(file_name) {
+            Some(file) => file,
+            None => return "",
+        };
+
+        let bytes_before = self.raw.lines()
+            .take(file.start_line)
+            .fold(0_usize, |acc, val| acc + val.len() + 1);
+        let bytes_after = self.raw.lines()
+            .skip(file.start_line)
+            .take(file.end_line + 1 - file.start_line)
+            .fold(bytes_before, |acc, val| acc + val.len() + 1);
+        &self.raw[bytes_before..bytes_after.min(self.raw.len())]
+    }
+
     pub fn get_hunk(&self, code_range: &CodeRange) -> &str {
         let file_diff_range = self.files.get(&code_range.file_name);
         if file_diff_range.is_none() {
@@ -154,7 +197,7 @@ impl ChangeList {
                 DiffSide::Right => &hunk.range_after,
             };
 
-            if code_range.start_line < hunk_range.0 || code_range.start_line > hunk_range.0 + hunk_range.1 - 1 {
+            if code_range.start_line < hunk_range.0 || code_range.start_line >= hunk_range.0 + hunk_range.1 {
                 continue;
             }
 
@@ -165,20 +208,23 @@ impl ChangeList {
 
             const LINES_PADDING : usize = 4;
 
-            let mut start_line_idx = self.raw.lines()
+            let start_line_idx = self.raw.lines()
                 .enumerate()
                 .skip(hunk.changelist_range.0 + 1)
                 .take(hunk.changelist_range.1 - hunk.changelist_range.0)
                 .filter(|(_, l)| l.starts_with(" ") || l.starts_with(prefix_symbol))
                 .enumerate()
-                .find(|(idx, _)| idx + hunk_range.0 == code_range.start_line)
-                .unwrap().1.0;
+                .find(|(idx, _)| idx + hunk_range.0 == code_range.start_line);
+            let mut start_line_idx = match start_line_idx {
+                Some((_, (line_idx, _))) => line_idx,
+                None => return "",
+            };
 
-            let end_line_idx = start_line_idx + (code_range.end_line - code_range.start_line);
+            let end_line_idx = start_line_idx + code_range.end_line.saturating_sub(code_range.start_line);
 
             //TODO check hunk boudaries
             if end_line_idx - start_line_idx + 1 < LINES_PADDING {
-                start_line_idx -= LINES_PADDING - (end_line_idx - start_line_idx + 1);
+                start_line_idx = start_line_idx.saturating_sub(LINES_PADDING - (end_line_idx - start_line_idx + 1));
             }
 
             let bytes_before = self.raw.lines()
@@ -197,6 +243,68 @@ impl ChangeList {
         ""
     }
 
+    // Whether a hunk covers the line of the new version of the file, any line if it's missing.
+    // Hunks without lines name the line the removed ones were after, the lines on both sides of the gap are touched
+    pub fn touches(&self, file_name: &str, line: Option<usize>) -> bool {
+        let file = match self.files.get(file_name) {
+            Some(file) => file,
+            None => return false,
+        };
+        let line = match line {
+            Some(line) => line,
+            None => return true,
+        };
+        file.hunks.iter().any(|hunk| {
+            let Range(start, length) = hunk.range_after;
+            if length == 0 {
+                line == start || line == start + 1
+            } else {
+                line >= start && line < start + length
+            }
+        })
+    }
+
+    // Where a line of the old version of the file is in the new one. Removed lines
+    // end up at the line that took their place
+    pub fn map_line(&self, file_name: &str, line: usize) -> usize {
+        let file = match self.files.get(file_name) {
+            Some(file) => file,
+            None => return line,
+        };
+
+        // Hunks without lines start after the line they name
+        let start = |range: &Range| if range.1 == 0 { range.0 + 1 } else { range.0 };
+        let mut mapped = line;
+        for hunk in file.hunks.iter() {
+            let (old_start, new_start) = (start(&hunk.range_before), start(&hunk.range_after));
+            if line < old_start {
+                break;
+            }
+            if line >= old_start + hunk.range_before.1 {
+                mapped = line + new_start + hunk.range_after.1 - old_start - hunk.range_before.1;
+                continue;
+            }
+
+            let (mut old, mut new) = (old_start, new_start);
+            let hunk_lines = self.raw.lines()
+                .skip(hunk.changelist_range.0 + 1)
+                .take(hunk.changelist_range.1 - hunk.changelist_range.0);
+            for hunk_line in hunk_lines {
+                match hunk_line.chars().next() {
+                    Some('+') => new += 1,
+                    Some('-') if old == line => return new,
+                    Some('-') => old += 1,
+                    _ if old == line => return new,
+                    _ => {
+                        old += 1;
+                        new += 1;
+                    },
+                }
+            }
+            return new;
+        }
+        mapped
+    }
 }
 
 fn read_file_name(captures: Captures) -> String {
@@ -211,7 +319,8 @@ fn read_hunk_ranges(captures: Captures) -> (Range, Range) {
 
 fn read_hunk_range(range_str: &str) -> Range {
     let mut iter = range_str.split(',');
-    Range(iter.next().unwrap().parse().unwrap(), iter.next().unwrap().parse().unwrap())
+    // The length is left out when it's one line
+    Range(iter.next().unwrap().parse().unwrap(), iter.next().map_or(1, |length| length.parse().unwrap()))
 }
 
 #[cfg(test)]
@@ -224,10 +333,47 @@ mod tests {
         let changelist = load_diff().map_err(|e| e.to_string())?;
         let code_range = CodeRange::new(String::from("Assets/BlindGame/Scripts/CommunityTests/Blind.CommunityTests.asmdef"), DiffSide::Right, 14, 14);
         let hunk = changelist.get_hunk(&code_range);
-        crate::logs::log(&format!("{}", hunk));
+        assert!(!hunk.is_empty());
         Ok(())
     }
 
+    #[test]
+    fn files_are_listed() {
+        let diff = "diff --git a/src/b.rs b/src/b.rs\n--- a/src/b.rs\n+++ b/src/b.rs\n@@ -1,1 +1,1 @@\n-old\n+new\n\
+                    diff --git a/a.rs b/a.rs\n--- a/a.rs\n+++ b/a.rs\n@@ -1,2 +1,1 @@\n same\n-gone\n";
+        let changelist = ChangeList::new(diff.to_string());
+        assert_eq!(changelist.file_names(), vec!["a.rs", "src/b.rs"]);
+        assert!(changelist.file_diff("src/b.rs").starts_with("diff --git a/src/b.rs"));
+        assert!(changelist.file_diff("src/b.rs").ends_with("+new\n"));
+        assert!(changelist.file_diff("a.rs").ends_with("-gone\n"));
+        assert_eq!(changelist.file_diff("missing.rs"), "");
+    }
+
+    #[test]
+    fn lines_are_mapped() {
+        let diff = "diff --git a/a.rs b/a.rs\n--- a/a.rs\n+++ b/a.rs\n\
+                    @@ -2,3 +2,2 @@\n two\n-three\n four\n\
+                    @@ -10,0 +10,2 @@\n+new\n+new\n\
+                    @@ -20 +21 @@\n-old\n+new\n";
+        let changelist = ChangeList::new(diff.to_string());
+        let mapped: Vec<usize> = [1, 2, 3, 4, 9, 10, 11, 20, 21].iter().map(|line| changelist.map_line("a.rs", *line)).collect();
+        assert_eq!(mapped, vec![1, 2, 3, 3, 8, 9, 12, 21, 22]);
+        assert_eq!(changelist.map_line("other.rs", 7), 7);
+    }
+
+    #[test]
+    fn touched_lines() {
+        let diff = "diff --git a/a.rs b/a.rs\n--- a/a.rs\n+++ b/a.rs\n\
+                    @@ -2,3 +2,2 @@\n two\n-three\n four\n\
+                    @@ -9,0 +10,2 @@\n+new\n+new\n\
+                    @@ -20 +19,0 @@\n-gone\n";
+        let changelist = ChangeList::new(diff.to_string());
+        let touched: Vec<usize> = (1..25).filter(|line| changelist.touches("a.rs", Some(*line))).collect();
+        assert_eq!(touched, vec![2, 3, 10, 11, 19, 20]);
+        assert!(changelist.touches("a.rs", None));
+        assert!(!changelist.touches("other.rs", None));
+    }
+
     fn load_diff() -> Result<ChangeList, std::io::Error> {
         let text = std::fs::read_to_string("test/pr_676.diff")?;
         Ok(ChangeList::new(text))
@@ -0,0 +1,111 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::error::Error;
+use super::pr::PrComment;
+
+// Everything from this line on is left out of the comment
+const SCISSORS: &str = "# ------------------------ >8 ------------------------";
+const CONTEXT_PREFIX: &str = "# ";
+
+// $VISUAL, then $EDITOR, then vi. The variables may hold arguments, e.g. `code --wait`
+pub fn editor_command(path: &Path) -> Command {
+    let mut cmd = command(editor().split_whitespace());
+    cmd.arg(path);
+    cmd
+}
+
+// The template is split into words before {line} and {file} are replaced, so paths with spaces stay whole
+pub fn open_file_command(template: Option<&str>, path: &Path, line: usize) -> Command {
+    let template = template.map_or_else(|| format!("{} +{{line}} {{file}}", editor()), str::to_string);
+    let file = path.to_string_lossy();
+    let words: Vec<String> = template.split_whitespace()
+        .map(|word| word.replace("{line}", &line.to_string()).replace("{file}", &file))
+        .collect();
+    command(words.iter().map(String::as_str))
+}
+
+fn editor() -> String {
+    ["VISUAL", "EDITOR"].iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|value| !value.trim().is_empty())
+        .unwrap_or_else(|| "vi".to_string())
+}
+
+fn command<'a>(mut words: impl Iterator<Item = &'a str>) -> Command {
+    let mut cmd = Command::new(words.next().unwrap_or("vi"));
+    cmd.args(words);
+    cmd
+}
+
+// The comment being replied to and the code it's about, for the draft
+pub fn draft_context(comment: Option<&PrComment>, hunk: &str) -> String {
+    let mut context = String::new();
+    if let Some(comment) = comment {
+        context.push_str(&format!("{} wrote:\n{}\n", comment.author_name, comment.body));
+    }
+    if !hunk.is_empty() {
+        if !context.is_empty() {
+            context.push('\n');
+        }
+        context.push_str(hunk);
+    }
+    context
+}
+
+pub fn write_draft(text: &str, context: &str) -> Result<PathBuf, Error> {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.subsec_nanos());
+    let path = std::env::temp_dir().join(format!("ghterm-{}-{}.md", std::process::id(), nanos));
+    fs::write(&path, draft_text(text, context))?;
+    Ok(path)
+}
+
+// The draft is removed once it's read
+pub fn read_draft(path: &Path) -> Result<String, Error> {
+    let content = fs::read_to_string(path);
+    let _ = fs::remove_file(path);
+    Ok(parse_draft(&content?))
+}
+
+fn draft_text(text: &str, context: &str) -> String {
+    let mut draft = format!("{}\n\n{}\n", text, SCISSORS);
+    draft.push_str("# Everything below the line above is ignored, an empty comment isn't sent.\n");
+    for line in context.lines() {
+        draft.push_str(CONTEXT_PREFIX);
+        draft.push_str(line);
+        draft.push('\n');
+    }
+    draft
+}
+
+fn parse_draft(content: &str) -> String {
+    let body = content.lines()
+        .take_while(|line| *line != SCISSORS)
+        .collect::<Vec<_>>()
+        .join("\n");
+    body.trim_end().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn context_is_left_out() {
+        let draft = draft_text("Looks good", "bob wrote:\nWhy?\n\n+ let x = 1;");
+        assert!(draft.lines().any(|line| line == "# + let x = 1;"));
+        assert_eq!(parse_draft(&draft), "Looks good");
+        assert_eq!(parse_draft(&draft_text("", "bob wrote:\nWhy?")), "");
+        assert_eq!(parse_draft("# A heading\n\ntext\n"), "# A heading\n\ntext");
+    }
+
+    #[test]
+    fn open_command_template() {
+        let cmd = open_file_command(Some("code --goto {file}:{line}"), Path::new("/repo/my file.rs"), 12);
+        assert_eq!(cmd.get_program(), "code");
+        assert_eq!(cmd.get_args().collect::<Vec<_>>(), vec!["--goto", "/repo/my file.rs:12"]);
+    }
+}
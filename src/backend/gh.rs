@@ -3,20 +3,27 @@ use std::process::{Command, Stdio};
 use json::{self, JsonValue};
 use std::fs;
 use crate::error::Error;
+use crate::config::Config;
+use std::path::{Path, PathBuf};
 use super::diff::DiffRequest;
+use super::pr::{CommentKind, CommentTarget, ReactionContent};
 
 pub struct GhClient {
     repo_owner: String,
     repo_name: String,
     queries_map: HashMap<String, String>,
+    page_size: u32,
+    pr_list_size: u32,
+    timeline_size: u32,
 }
 
 impl GhClient {
-    pub fn new(repo_owner: String, repo_name: String) -> Result<Self, Error> {
-        let queries_map = GhClient::read_queries()
-            .map_err(|_| Error::Other("Failed to read queries files. Make sure you have installed ghterm correctly".to_string()))?;
+    pub fn new(repo_owner: String, repo_name: String, config: &Config) -> Result<Self, Error> {
+        let queries_dir = config.queries_dir.clone().unwrap_or_else(GhClient::get_requests_directory);
+        let queries_map = GhClient::read_queries(&queries_dir)
+            .map_err(|_| Error::Other(format!("Failed to read queries files from {}. Make sure you have installed ghterm correctly", queries_dir.display())))?;
 
-        Ok(GhClient {repo_owner, repo_name, queries_map})
+        Ok(GhClient {repo_owner, repo_name, queries_map, page_size: config.page_size, pr_list_size: config.pr_list_size, timeline_size: config.timeline_size})
     }
 
     pub fn validate(&self, pr_num: Option<u32>) -> Result<(), Error> {
@@ -46,6 +53,7 @@ impl GhClient {
         let query = self.get_query("pr_list")?;
         let request = GqlQueryBuilder::new()
             .set_repo(self.repo_owner.clone(), self.repo_name.clone())
+            .add_int_param("page_size", self.pr_list_size)
             .set_query(query)
             .build();
         Ok(request)
@@ -56,6 +64,92 @@ impl GhClient {
         let request = GqlQueryBuilder::new()
             .set_repo(self.repo_owner.clone(), self.repo_name.clone())
             .add_int_param("number", number)
+            .add_int_param("page_size", self.page_size)
+            .add_int_param("timeline_size", self.timeline_size)
+            .set_query(query)
+            .build();
+        Ok(request)
+    }
+
+    pub fn references(&self) -> Result<GqlRequest, Error> {
+        let query = self.get_query("references")?;
+        let request = GqlQueryBuilder::new()
+            .set_repo(self.repo_owner.clone(), self.repo_name.clone())
+            .add_int_param("page_size", self.page_size)
+            .set_query(query)
+            .build();
+        Ok(request)
+    }
+
+    pub fn comment_edits(&self, id: &str) -> Result<GqlRequest, Error> {
+        let query = self.get_query("comment_edits")?;
+        let request = GqlQueryBuilder::new()
+            .without_repo()
+            .add_typed_param("id", "ID", id)
+            .add_int_param("page_size", self.page_size)
+            .set_query(query)
+            .build();
+        Ok(request)
+    }
+
+    pub fn set_reaction(&self, subject_id: &str, content: ReactionContent, is_added: bool) -> Result<GqlRequest, Error> {
+        let query = self.get_query(if is_added { "add_reaction" } else { "remove_reaction" })?;
+        let request = GqlQueryBuilder::new()
+            .mutation()
+            .add_typed_param("subject", "ID", subject_id)
+            .add_typed_param("content", "ReactionContent", content.name())
+            .set_query(query)
+            .build();
+        Ok(request)
+    }
+
+    pub fn send_comment(&self, target: &CommentTarget, body: &str) -> Result<GqlRequest, Error> {
+        let (query_name, id_param, id) = match target {
+            CommentTarget::Edit { comment_id, kind: CommentKind::IssueComment } => ("update_issue_comment", "id", comment_id),
+            CommentTarget::Edit { comment_id, kind: CommentKind::ReviewComment } => ("update_review_comment", "id", comment_id),
+            CommentTarget::Edit { comment_id, kind: CommentKind::Review } => ("update_review", "id", comment_id),
+            CommentTarget::Reply { thread_id } => ("add_thread_reply", "subject", thread_id),
+            CommentTarget::NewComment { subject_id } => ("add_comment", "subject", subject_id),
+        };
+        let query = self.get_query(query_name)?;
+        let request = GqlQueryBuilder::new()
+            .mutation()
+            .add_typed_param(id_param, "ID", id)
+            .add_string_param("body", body)
+            .set_query(query)
+            .build();
+        Ok(request)
+    }
+
+    pub fn viewed_files(&self, number: u32) -> Result<PagedGqlRequest, Error> {
+        let query = self.get_query("viewed_files")?;
+        let mut builder = GqlQueryBuilder::new();
+        builder.set_repo(self.repo_owner.clone(), self.repo_name.clone())
+            .add_int_param("number", number)
+            .set_query(query);
+        Ok(PagedGqlRequest { builder, connection: &["data", "repository", "pullRequest", "files"] })
+    }
+
+    pub fn set_file_viewed(&self, pr_id: &str, path: &str, is_viewed: bool) -> Result<GqlRequest, Error> {
+        let query = self.get_query(if is_viewed { "mark_file_as_viewed" } else { "unmark_file_as_viewed" })?;
+        let request = GqlQueryBuilder::new()
+            .mutation()
+            .add_typed_param("subject", "ID", pr_id)
+            .add_string_param("path", path)
+            .set_query(query)
+            .build();
+        Ok(request)
+    }
+
+    pub fn delete_comment(&self, id: &str, kind: CommentKind) -> Result<GqlRequest, Error> {
+        let query = self.get_query(match kind {
+            CommentKind::IssueComment => "delete_issue_comment",
+            CommentKind::ReviewComment => "delete_review_comment",
+            CommentKind::Review => "delete_review",
+        })?;
+        let request = GqlQueryBuilder::new()
+            .mutation()
+            .add_typed_param("id", "ID", id)
             .set_query(query)
             .build();
         Ok(request)
@@ -77,8 +171,8 @@ impl GhClient {
             .ok_or(Error::Other(format!("Query template {} wasn't found", name)))
     }
 
-    fn read_queries() -> Result<HashMap<String, String>, std::io::Error> {
-        let map : HashMap<String, String> = fs::read_dir(GhClient::get_requests_directory())?
+    fn read_queries(directory: &Path) -> Result<HashMap<String, String>, std::io::Error> {
+        let map : HashMap<String, String> = fs::read_dir(directory)?
             .filter_map(|e| e.ok())
             .filter_map(|e| match e.file_type() { 
                 Ok(ft) => if ft.is_file() && e.file_name().to_str().unwrap().ends_with(".gql") {
@@ -97,14 +191,16 @@ impl GhClient {
 
 
     #[cfg(debug_assertions)]
-    fn get_requests_directory() -> String {
-        "data/requests".to_string()
+    fn get_requests_directory() -> PathBuf {
+        PathBuf::from("data/requests")
     }
 
     #[cfg(not(debug_assertions))]
-    fn get_requests_directory() -> String {
-        //TODO use XDG_DATA_HOME
-        String::new()
+    fn get_requests_directory() -> PathBuf {
+        xdg::BaseDirectories::with_prefix("ghterm")
+            .ok()
+            .and_then(|dirs| dirs.find_data_file("requests"))
+            .unwrap_or_default()
     }
 
 }
@@ -116,26 +212,63 @@ pub struct GqlRequest {
 impl GqlRequest {
     pub fn execute(&mut self) -> Result<JsonValue, Error> {
         let output = self.cmd.output().map_err(|e| Error::Other(e.to_string()))?;
-        crate::logs::log(&format!("{:?}", output));
+        crate::logs::debug!("Query finished", status = output.status, response = String::from_utf8_lossy(&output.stdout));
         if !output.status.success() {
-            return Err(Error::Other(String::from_utf8(output.stderr).unwrap()));
+            let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+            crate::logs::warning!("Query failed", status = output.status, stderr = stderr);
+            return Err(Error::Other(stderr));
         }
 
-        let output = String::from_utf8(output.stdout).unwrap();
+        let output = String::from_utf8(output.stdout)
+            .map_err(|e| Error::MalformedResponse(e.to_string()))?;
         json::parse(&output).map_err(|e| {
-            Error::Other(format!("Got malformed json: {}", e.to_string()))
+            Error::MalformedResponse(format!("Got malformed json: {}", e))
         })
     }
 }
 
 unsafe impl Send for GqlRequest {}
 
+// Query of a connection that is too long for one page. `$cursor` is where the page starts
+pub struct PagedGqlRequest {
+    builder: GqlQueryBuilder,
+    // Keys leading to the connection in the response
+    connection: &'static [&'static str],
+}
+
+impl PagedGqlRequest {
+    // Responses of all the pages in order. An error ends the paging, the response with it is the last one
+    pub fn execute(&mut self) -> Result<Vec<JsonValue>, Error> {
+        let mut pages = vec![];
+        let mut cursor: Option<String> = None;
+        loop {
+            let page = self.builder.add_optional_string_param("cursor", cursor.as_deref()).build().execute()?;
+            let page_info = &self.connection.iter().fold(&page, |json, key| &json[*key])["pageInfo"];
+            cursor = match page_info["hasNextPage"].as_bool() {
+                Some(true) => page_info["endCursor"].as_str().map(|s| s.to_string()),
+                _ => None,
+            };
+            pages.push(page);
+            if cursor.is_none() {
+                return Ok(pages);
+            }
+        }
+    }
+}
+
 struct GqlQueryBuilder {
     repo_owner: String,
     repo_name: String,
+    // Queries about nodes that aren't reachable from the repository are made from the root
+    is_repo_scoped: bool,
+    is_mutation: bool,
     query: String,
     string_params: HashMap<String, String>,
+    // Nullable parameters, the ones without a value are null
+    optional_string_params: HashMap<String, Option<String>>,
     int_params: HashMap<String, u32>,
+    // Parameters of other types, like enums, keyed by name with their type and value
+    typed_params: HashMap<String, (String, String)>,
 }
 
 impl GqlQueryBuilder {
@@ -143,9 +276,13 @@ impl GqlQueryBuilder {
         GqlQueryBuilder {
             repo_owner: String::from(":owner"),
             repo_name: String::from(":repo"),
+            is_repo_scoped: true,
+            is_mutation: false,
             query: String::new(),
             string_params: HashMap::new(),
-            int_params: HashMap::new() 
+            optional_string_params: HashMap::new(),
+            int_params: HashMap::new(),
+            typed_params: HashMap::new(),
         }
     }
 
@@ -155,11 +292,32 @@ impl GqlQueryBuilder {
         self
     }
 
+    fn without_repo(&mut self) -> &mut Self {
+        self.is_repo_scoped = false;
+        self
+    }
+
+    // Mutations are made from the root, like the queries without the repository
+    fn mutation(&mut self) -> &mut Self {
+        self.is_mutation = true;
+        self.without_repo()
+    }
+
+    fn add_typed_param(&mut self, param_name: &str, type_name: &str, param_value: &str) -> &mut Self {
+        self.typed_params.insert(String::from(param_name), (String::from(type_name), String::from(param_value)));
+        self
+    }
+
     fn add_string_param(&mut self, param_name: &str, param_value: &str) -> &mut Self {
         self.string_params.insert(String::from(param_name), String::from(param_value));
         self
     }
 
+    fn add_optional_string_param(&mut self, param_name: &str, param_value: Option<&str>) -> &mut Self {
+        self.optional_string_params.insert(String::from(param_name), param_value.map(String::from));
+        self
+    }
+
     fn add_int_param(&mut self, param_name: &str, param_value: u32) -> &mut Self {
         self.int_params.insert(String::from(param_name), param_value);
         self
@@ -173,27 +331,64 @@ impl GqlQueryBuilder {
     fn build(&mut self) -> GqlRequest {
         let mut cmd = Command::new("gh");
         cmd.args(&["api", "graphql"]);
-        cmd.args(&["-F", &format!("owner={}", self.repo_owner)]);
-        cmd.args(&["-F", &format!("name={}", self.repo_name)]);
 
-        let mut query_header = String::from("query=query($name: String!, $owner: String!");
+        let mut params = vec![];
+        if self.is_repo_scoped {
+            cmd.args(["-F", &format!("owner={}", self.repo_owner)]);
+            cmd.args(["-F", &format!("name={}", self.repo_name)]);
+            params.push(String::from("$name: String!"));
+            params.push(String::from("$owner: String!"));
+        }
+        // Raw fields, so text that looks like a number or a file name is sent as it is
         for (param_name, param_value) in self.string_params.iter() {
-            query_header.push_str(&format!(", ${}: String!", param_name));
-            cmd.args(&["-F", &format!("{}={}", param_name, param_value)]);
+            params.push(format!("${}: String!", param_name));
+            cmd.args(["-f", &format!("{}={}", param_name, param_value)]);
+        }
+        for (param_name, param_value) in self.optional_string_params.iter() {
+            params.push(format!("${}: String", param_name));
+            if let Some(param_value) = param_value {
+                cmd.args(["-f", &format!("{}={}", param_name, param_value)]);
+            }
         }
         for (param_name, param_value) in self.int_params.iter() {
-            query_header.push_str(&format!(", ${}: Int!", param_name));
+            params.push(format!("${}: Int!", param_name));
             cmd.args(&["-F", &format!("{}={}", param_name, param_value)]);
         }
-        query_header.push_str(") {\nrepository(owner: $owner, name: $name) {\n");
+        for (param_name, (type_name, param_value)) in self.typed_params.iter() {
+            params.push(format!("${}: {}!", param_name, type_name));
+            cmd.args(["-F", &format!("{}={}", param_name, param_value)]);
+        }
+
+        let operation = if self.is_mutation { "mutation" } else { "query" };
+        let mut query_header = format!("query={}({}) {{\n", operation, params.join(", "));
+        if self.is_repo_scoped {
+            query_header.push_str("repository(owner: $owner, name: $name) {\n");
+        }
         query_header.push_str(&self.query);
-        query_header.push_str("}}");
-        crate::logs::log(&format!("{}", query_header));
+        query_header.push_str(if self.is_repo_scoped { "}}" } else { "}" });
+        crate::logs::trace!("Built a query", owner = self.repo_owner, name = self.repo_name, query = query_header);
         cmd.args(&["-f", &query_header]);
         GqlRequest {cmd}
     }
 }
 
+// Repository gh picks for the current directory from its remotes
+pub fn current_repo() -> Result<(String, String), Error> {
+    let output = Command::new("gh")
+        .args(["repo", "view", "--json", "owner,name"])
+        .output()
+        .map_err(|e| Error::Other(e.to_string()))?;
+    if !output.status.success() {
+        return Err(Error::NotARepo("The current directory".to_string()));
+    }
+    let json = json::parse(&String::from_utf8_lossy(&output.stdout))
+        .map_err(|e| Error::MalformedResponse(e.to_string()))?;
+    match (json["owner"]["login"].as_str(), json["name"].as_str()) {
+        (Some(owner), Some(name)) => Ok((owner.to_string(), name.to_string())),
+        _ => Err(Error::MissingField("owner.login".to_string())),
+    }
+}
+
 pub fn check_health() -> Result<bool, Error> {
    let result = check_gh_installed()? && ensure_authentication()?; 
    Ok(result)
@@ -214,7 +409,7 @@ fn ensure_authentication() -> Result<bool, Error> {
     let config_file = base_dirs.find_config_file("hosts.yml");
     match config_file {
         Some(path) => {
-            let contents = fs::read_to_string(path).unwrap();
+            let contents = fs::read_to_string(path)?;
             if contents.contains("github.com") { 
                 Ok(true)
             } else {
@@ -0,0 +1,357 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::error::Error;
+use super::diff::ChangeList;
+use super::pr::{PrEvent, PrEventKind, PrHead};
+
+use chrono::{DateTime, Local};
+
+// Root of the working tree ghterm runs in
+pub fn toplevel() -> Result<PathBuf, Error> {
+    Repository::discover().map(|repo| repo.root)
+}
+
+// Where a line of a file at a commit is in the working tree. None if the commit isn't available locally
+pub fn working_tree_line(commit: &str, file_name: &str, line: usize) -> Option<usize> {
+    // The file name is relative to the root rather than the current directory
+    let pathspec = format!(":(top){}", file_name);
+    let diff = git(None, &["diff", "--no-color", "--no-ext-diff", commit, "--", &pathspec]).ok()?;
+    Some(ChangeList::new(diff).map_line(file_name, line))
+}
+
+pub fn diff(range: &str) -> Result<String, Error> {
+    git(None, &["diff", "--no-color", "--no-ext-diff", range])
+}
+
+// Commits the diff of the range is made of, oldest first. A single revision is compared
+// with the working tree, so its commits are the ones up to HEAD
+pub fn commits(range: &str) -> Result<Vec<PrEvent>, Error> {
+    let range = if range.contains("..") {
+        range.replace("...", "..")
+    } else {
+        format!("{}..HEAD", range)
+    };
+    let log = git(None, &["log", "--reverse", "--format=%h%x1f%an%x1f%aI%x1f%B%x1e", &range])?;
+    parse_log(&log)
+}
+
+fn parse_log(log: &str) -> Result<Vec<PrEvent>, Error> {
+    let mut commits = vec![];
+    for entry in log.split('\x1e').map(str::trim).filter(|entry| !entry.is_empty()) {
+        let fields: Vec<&str> = entry.splitn(4, '\x1f').collect();
+        if fields.len() < 4 {
+            return Err(Error::MalformedResponse(format!("git log entry `{}`", entry)));
+        }
+        let timestamp = DateTime::parse_from_rfc3339(fields[2])
+            .map_err(|_| Error::MalformedResponse(format!("commit date `{}`", fields[2])))?
+            .with_timezone(&Local);
+        let kind = PrEventKind::Commit { oid: fields[0].to_string(), message: fields[3].trim_end().to_string() };
+        commits.push(PrEvent { actor_name: fields[1].to_string(), timestamp, kind });
+    }
+    Ok(commits)
+}
+
+// GitHub repository of the clone without asking GitHub, for offline mode. Forks usually
+// call their parent `upstream`, so it's preferred over `origin` like gh does
+pub fn github_repo() -> Result<(String, String), Error> {
+    let remotes = git(None, &["config", "--get-regexp", r"^remote\..*\.url$"]).unwrap_or_default();
+    let url_of = |name: &str| remotes.lines()
+        .filter_map(|line| line.split_once(' '))
+        .find(|(key, _)| *key == format!("remote.{}.url", name))
+        .and_then(|(_, url)| repo_of_url(url));
+    url_of("upstream")
+        .or_else(|| url_of("origin"))
+        .or_else(|| remotes.lines().filter_map(|line| line.split_once(' ')).find_map(|(_, url)| repo_of_url(url)))
+        .ok_or_else(|| Error::NotARepo("The current directory".to_string()))
+}
+
+fn repo_of_url(url: &str) -> Option<(String, String)> {
+    let url = strip_url(url);
+    let mut parts = url.split('/');
+    match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(host), Some(owner), Some(name), None) if host.eq_ignore_ascii_case("github.com") && !owner.is_empty() && !name.is_empty() =>
+            Some((owner.to_string(), name.to_string())),
+        _ => None,
+    }
+}
+
+// Local clone, the commands run in its root
+#[derive(Debug, Clone)]
+pub struct Repository {
+    root: PathBuf,
+}
+
+// How the head branch of a pull request gets into the clone
+#[derive(Debug, Clone)]
+pub struct Checkout {
+    // Local branch, named after the owner for forks so it doesn't clash with the branches of the repository
+    pub branch: String,
+    // Worktree to create instead of switching the branch of the clone
+    pub worktree: Option<PathBuf>,
+    remote_branch: String,
+    // Name of a remote with the head repository, otherwise its URL
+    source: String,
+    is_remote: bool,
+}
+
+impl Repository {
+    pub fn discover() -> Result<Self, Error> {
+        let output = git(None, &["rev-parse", "--show-toplevel"])?;
+        Ok(Repository { root: PathBuf::from(output.trim_end()) })
+    }
+
+    pub fn open(root: &Path) -> Self {
+        Repository { root: root.to_path_buf() }
+    }
+
+    // Untracked files don't count, checking out doesn't touch them
+    pub fn is_dirty(&self) -> Result<bool, Error> {
+        let status = self.git(&["status", "--porcelain", "--untracked-files=no"])?;
+        Ok(!status.trim().is_empty())
+    }
+
+    pub fn plan_checkout(&self, number: u32, head: &PrHead, in_worktree: bool) -> Result<Checkout, Error> {
+        let branch = if head.is_fork {
+            format!("{}/{}", head.owner, head.branch)
+        } else {
+            head.branch.clone()
+        };
+        let worktree = if in_worktree {
+            let name = self.root.file_name().map_or("repo".into(), |n| n.to_string_lossy());
+            let path = self.root.with_file_name(format!("{}-pr-{}", name, number));
+            if path.exists() {
+                return Err(Error::Other(format!("{} already exists", path.display())));
+            }
+            Some(path)
+        } else {
+            None
+        };
+        let (source, is_remote) = match self.remote_for(&head.repository_url)? {
+            Some(remote) => (remote, true),
+            None => (head.repository_url.clone(), false),
+        };
+        Ok(Checkout { branch, worktree, remote_branch: head.branch.clone(), source, is_remote })
+    }
+
+    // Returns the fetched commit. Remotes get their tracking branch updated
+    pub fn fetch(&self, checkout: &Checkout) -> Result<String, Error> {
+        let refspec = if checkout.is_remote {
+            format!("+refs/heads/{0}:refs/remotes/{1}/{0}", checkout.remote_branch, checkout.source)
+        } else {
+            format!("refs/heads/{}", checkout.remote_branch)
+        };
+        self.git(&["fetch", "--no-tags", &checkout.source, &refspec])?;
+        Ok(self.git(&["rev-parse", "FETCH_HEAD"])?.trim_end().to_string())
+    }
+
+    // An existing branch is fast-forwarded to the commit, it's an error if it has diverged
+    pub fn check_out(&self, checkout: &Checkout, commit: &str) -> Result<(), Error> {
+        let branch = checkout.branch.as_str();
+        let exists = self.git(&["rev-parse", "--verify", "--quiet", &format!("refs/heads/{}", branch)]).is_ok();
+        let tree = match (&checkout.worktree, exists) {
+            (Some(path), true) => {
+                self.git(&["worktree", "add", &path.to_string_lossy(), branch])?;
+                Repository::open(path)
+            },
+            (Some(path), false) => {
+                self.git(&["worktree", "add", "-b", branch, &path.to_string_lossy(), commit])?;
+                Repository::open(path)
+            },
+            (None, true) => {
+                self.git(&["checkout", branch])?;
+                self.clone()
+            },
+            (None, false) => {
+                self.git(&["checkout", "-b", branch, commit])?;
+                self.clone()
+            },
+        };
+        if exists {
+            tree.git(&["merge", "--ff-only", commit])?;
+        }
+        if checkout.is_remote {
+            let upstream = format!("{}/{}", checkout.source, checkout.remote_branch);
+            tree.git(&["branch", &format!("--set-upstream-to={}", upstream), branch])?;
+        }
+        Ok(())
+    }
+
+    // Changes between two heads of a pull request, even if the older one was force-pushed away.
+    // Commits missing in the clone are fetched from the head repository, GitHub keeps the old ones
+    pub fn interdiff(&self, from: &str, to: &str, head: &PrHead) -> Result<String, Error> {
+        let missing: Vec<&str> = [from, to].iter().copied()
+            .filter(|commit| self.git(&["cat-file", "-e", &format!("{}^{{commit}}", commit)]).is_err())
+            .collect();
+        if !missing.is_empty() {
+            let source = self.remote_for(&head.repository_url)?.unwrap_or_else(|| head.repository_url.clone());
+            let mut args = vec!["fetch", "--no-tags", source.as_str()];
+            args.extend(missing);
+            self.git(&args)?;
+        }
+        self.git(&["diff", "--no-color", "--no-ext-diff", from, to])
+    }
+
+    fn remote_for(&self, url: &str) -> Result<Option<String>, Error> {
+        // Exits with 1 when there are no remotes
+        let remotes = self.git(&["config", "--get-regexp", r"^remote\..*\.url$"]).unwrap_or_default();
+        let url = normalize_url(url);
+        Ok(remotes.lines()
+            .filter_map(|line| line.split_once(' '))
+            .find(|(_, remote_url)| normalize_url(remote_url) == url)
+            .and_then(|(key, _)| key.strip_prefix("remote.")?.strip_suffix(".url").map(str::to_string)))
+    }
+
+    fn git(&self, args: &[&str]) -> Result<String, Error> {
+        git(Some(&self.root), args)
+    }
+}
+
+// The same repository over https and ssh, with and without .git
+fn normalize_url(url: &str) -> String {
+    strip_url(url).to_lowercase()
+}
+
+// Host and path of the URL, in the case it's written in
+fn strip_url(url: &str) -> String {
+    let url = url.trim().trim_end_matches('/');
+    let url = url.strip_suffix(".git").unwrap_or(url);
+    let url = ["https://", "http://", "ssh://", "git://"].iter()
+        .find_map(|scheme| url.strip_prefix(scheme))
+        .unwrap_or(url);
+    let url = url.strip_prefix("git@").unwrap_or(url);
+    match url.split_once(':') {
+        Some((host, path)) if !host.contains('/') && !path.starts_with('/') => format!("{}/{}", host, path),
+        _ => url.to_string(),
+    }
+}
+
+fn git(dir: Option<&Path>, args: &[&str]) -> Result<String, Error> {
+    let mut cmd = Command::new("git");
+    if let Some(dir) = dir {
+        cmd.current_dir(dir);
+    }
+    let output = cmd.args(args).output()
+        .map_err(|e| Error::Other(format!("Failed to run git: {}", e)))?;
+    if !output.status.success() {
+        return Err(Error::Other(String::from_utf8_lossy(&output.stderr).trim_end().to_string()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn run(dir: &Path, args: &[&str]) -> String {
+        let output = Command::new("git")
+            .current_dir(dir)
+            .args(["-c", "user.name=ghterm", "-c", "user.email=ghterm@localhost", "-c", "init.defaultBranch=main"])
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "git {:?}: {}", args, String::from_utf8_lossy(&output.stderr));
+        String::from_utf8_lossy(&output.stdout).trim_end().to_string()
+    }
+
+    // A bare origin with `main` and `feature`, a bare fork with `topic` and a clone of the origin on `main`
+    fn setup(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ghterm-git-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        run(&dir, &["init", "--bare", "origin.git"]);
+        run(&dir, &["clone", "origin.git", "clone"]);
+        let clone = dir.join("clone");
+        std::fs::write(clone.join("file.txt"), "one\n").unwrap();
+        run(&clone, &["add", "file.txt"]);
+        run(&clone, &["commit", "-m", "first"]);
+        run(&clone, &["push", "origin", "HEAD:refs/heads/main", "HEAD:refs/heads/feature"]);
+        run(&dir, &["clone", "--bare", "origin.git", "fork.git"]);
+
+        run(&dir, &["clone", "origin.git", "author"]);
+        let author = dir.join("author");
+        run(&author, &["checkout", "feature"]);
+        std::fs::write(author.join("file.txt"), "two\n").unwrap();
+        run(&author, &["commit", "-am", "second"]);
+        run(&author, &["push", "origin", "feature"]);
+        run(&author, &["push", "../fork.git", "feature:topic"]);
+        dir
+    }
+
+    fn head(dir: &Path, repository: &str, branch: &str, is_fork: bool) -> PrHead {
+        let repository_url = dir.join(repository).to_string_lossy().to_string();
+        PrHead { branch: branch.to_string(), repository_url, owner: "someone".to_string(), is_fork }
+    }
+
+    #[test]
+    fn branch_of_the_repository() {
+        let dir = setup("branch");
+        let repo = Repository::open(&dir.join("clone"));
+        assert!(!repo.is_dirty().unwrap());
+
+        let checkout = repo.plan_checkout(1, &head(&dir, "origin.git/", "feature", false), false).unwrap();
+        assert_eq!((checkout.branch.as_str(), checkout.source.as_str()), ("feature", "origin"));
+        let commit = repo.fetch(&checkout).unwrap();
+        repo.check_out(&checkout, &commit).unwrap();
+        assert_eq!(run(&dir.join("clone"), &["rev-parse", "HEAD"]), commit);
+        assert_eq!(run(&dir.join("clone"), &["rev-parse", "--abbrev-ref", "@{upstream}"]), "origin/feature");
+
+        std::fs::write(dir.join("clone/file.txt"), "changed\n").unwrap();
+        assert!(repo.is_dirty().unwrap());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn fork_in_a_worktree() {
+        let dir = setup("fork");
+        let repo = Repository::open(&dir.join("clone"));
+        let checkout = repo.plan_checkout(7, &head(&dir, "fork.git", "topic", true), true).unwrap();
+        assert_eq!(checkout.branch, "someone/topic");
+        assert_eq!(checkout.worktree, Some(dir.join("clone-pr-7")));
+
+        let commit = repo.fetch(&checkout).unwrap();
+        repo.check_out(&checkout, &commit).unwrap();
+        assert_eq!(run(&dir.join("clone-pr-7"), &["rev-parse", "HEAD"]), commit);
+        assert_eq!(std::fs::read_to_string(dir.join("clone-pr-7/file.txt")).unwrap(), "two\n");
+        // The clone stays on its branch
+        assert_eq!(run(&dir.join("clone"), &["rev-parse", "--abbrev-ref", "HEAD"]), "main");
+        assert!(repo.plan_checkout(7, &head(&dir, "fork.git", "topic", true), true).is_err());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn interdiff_after_force_push() {
+        let dir = setup("interdiff");
+        let author = dir.join("author");
+        let reviewed = run(&author, &["rev-parse", "HEAD"]);
+        std::fs::write(author.join("file.txt"), "three\n").unwrap();
+        run(&author, &["commit", "--amend", "-am", "second, amended"]);
+        run(&author, &["push", "--force", "origin", "feature"]);
+        let pushed = run(&author, &["rev-parse", "HEAD"]);
+
+        let repo = Repository::open(&dir.join("clone"));
+        let diff = repo.interdiff(&reviewed, &pushed, &head(&dir, "origin.git", "feature", false)).unwrap();
+        assert!(diff.contains("-two\n+three"));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn log_is_parsed() {
+        let log = "abc1234\x1fAlice\x1f2021-11-20T11:00:00+01:00\x1fFix\n\nDetails\n\x1e\ndef5678\x1fBob\x1f2021-11-21T11:00:00Z\x1fMore\n\x1e\n";
+        let commits = parse_log(log).unwrap();
+        assert_eq!(commits.len(), 2);
+        assert_eq!(commits[0].actor_name, "Alice");
+        assert!(matches!(&commits[0].kind, PrEventKind::Commit { oid, message } if oid == "abc1234" && message == "Fix\n\nDetails"));
+        assert!(parse_log("abc1234\x1fAlice\x1e").is_err());
+    }
+
+    #[test]
+    fn urls_are_normalized() {
+        assert_eq!(normalize_url("git@github.com:Owner/Repo.git"), "github.com/owner/repo");
+        assert_eq!(normalize_url("https://github.com/owner/repo"), "github.com/owner/repo");
+        assert_eq!(normalize_url("ssh://git@github.com/owner/repo.git/"), "github.com/owner/repo");
+        assert_eq!(repo_of_url("git@github.com:Owner/Repo.git"), Some(("Owner".to_string(), "Repo".to_string())));
+        assert_eq!(repo_of_url("https://gitlab.com/owner/repo"), None);
+    }
+}
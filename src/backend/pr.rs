@@ -1,8 +1,8 @@
 use super::diff::{ CodeRange, DiffSide };
+use crate::error::Error;
 use chrono::{DateTime, Local};
 use json::JsonValue;
 use std::collections::HashMap;
-use std::iter::Extend;
 
 #[derive(Debug)]
 pub struct PrHeader {
@@ -25,11 +25,104 @@ pub struct PrComment {
     pub author_name: String,
     pub body: String,
     pub timestamp: DateTime<Local>,
+    pub last_edited_at: Option<DateTime<Local>>,
+    pub reactions: Vec<PrReaction>,
+    pub kind: CommentKind,
+    pub viewer_did_author: bool,
+    // Permalink, empty in conversations cached before it was fetched
+    pub url: String,
+}
+
+// Comments of each kind are edited and deleted with their own mutations
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommentKind { IssueComment, ReviewComment, Review }
+
+// Where a written comment goes: over an existing one, into a thread or to the conversation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CommentTarget {
+    Edit { comment_id: String, kind: CommentKind },
+    Reply { thread_id: String },
+    NewComment { subject_id: String },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReactionContent { ThumbsUp, ThumbsDown, Laugh, Hooray, Confused, Heart, Rocket, Eyes }
+
+impl ReactionContent {
+    pub const ALL: [ReactionContent; 8] = [
+        ReactionContent::ThumbsUp, ReactionContent::ThumbsDown, ReactionContent::Laugh, ReactionContent::Hooray,
+        ReactionContent::Confused, ReactionContent::Heart, ReactionContent::Rocket, ReactionContent::Eyes,
+    ];
+
+    // Name of the value in the GitHub API
+    pub fn name(&self) -> &'static str {
+        match self {
+            ReactionContent::ThumbsUp => "THUMBS_UP",
+            ReactionContent::ThumbsDown => "THUMBS_DOWN",
+            ReactionContent::Laugh => "LAUGH",
+            ReactionContent::Hooray => "HOORAY",
+            ReactionContent::Confused => "CONFUSED",
+            ReactionContent::Heart => "HEART",
+            ReactionContent::Rocket => "ROCKET",
+            ReactionContent::Eyes => "EYES",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<ReactionContent> {
+        ReactionContent::ALL.iter().copied().find(|c| c.name() == name)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PrReaction {
+    pub content: ReactionContent,
+    pub count: u32,
+    pub viewer_has_reacted: bool,
+}
+
+impl PrComment {
+    pub fn has_reacted(&self, content: ReactionContent) -> bool {
+        self.reactions.iter().any(|r| r.content == content && r.viewer_has_reacted)
+    }
+
+    // Adds or removes the viewer's reaction, reactions nobody has left are dropped
+    pub fn set_reaction(&mut self, content: ReactionContent, is_added: bool) {
+        if self.has_reacted(content) == is_added {
+            return;
+        }
+
+        match self.reactions.iter_mut().find(|r| r.content == content) {
+            Some(reaction) if is_added => reaction.count += 1,
+            Some(reaction) => reaction.count = reaction.count.saturating_sub(1),
+            None if is_added => self.reactions.push(PrReaction { content, count: 1, viewer_has_reacted: true }),
+            None => return,
+        }
+        if let Some(reaction) = self.reactions.iter_mut().find(|r| r.content == content) {
+            reaction.viewer_has_reacted = is_added;
+        }
+        self.reactions.retain(|r| r.count > 0);
+        self.reactions.sort_by_key(|r| ReactionContent::ALL.iter().position(|c| *c == r.content));
+    }
+}
+
+// A version of a comment's body, the history lists them from the newest one
+#[derive(Debug, Clone)]
+pub struct PrCommentEdit {
+    pub editor_name: String,
+    pub timestamp: DateTime<Local>,
+    // Missing for the edits that were deleted from the history
+    pub body: Option<String>,
+    pub is_creation: bool,
 }
 
 #[derive(Debug)]
 pub struct PrConversationThread {
+    pub id: String,
     pub code_range: Option<CodeRange>,
+    // Commit the lines of the code range are in
+    pub original_commit: Option<String>,
+    // Line in the head of the pull request, missing once the thread is outdated
+    pub line: Option<usize>,
     pub comments: Vec<PrComment>,
 }
 
@@ -53,70 +146,207 @@ impl std::fmt::Display for PrReviewVerdict {
     }
 }
 
+// Something that happened to the pull request apart from the discussion
+#[derive(Debug, Clone)]
+pub struct PrEvent {
+    pub actor_name: String,
+    pub timestamp: DateTime<Local>,
+    pub kind: PrEventKind,
+}
+
+#[derive(Debug, Clone)]
+pub enum PrEventKind {
+    Commit { oid: String, message: String },
+    ForcePushed { before: String, after: String },
+    Labeled(String),
+    Unlabeled(String),
+    ReviewRequested(String),
+    Merged { commit: String, base_branch: String },
+    Closed,
+    Reopened,
+}
+
 #[derive(Debug)]
 pub enum ConversationItem {
     Comment(PrComment),
     Review(PrReview),
+    Event(PrEvent),
+}
+
+impl ConversationItem {
+    pub fn timestamp(&self) -> DateTime<Local> {
+        match self {
+            ConversationItem::Comment(c) => c.timestamp,
+            ConversationItem::Review(r) => r.review_comment.timestamp,
+            ConversationItem::Event(e) => e.timestamp,
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct PrConversation {
+    // Missing in the conversations cached before it was fetched
+    pub id: Option<String>,
+    pub head_sha: Option<String>,
+    pub base_sha: Option<String>,
+    pub head: Option<PrHead>,
+    // The viewer's latest review, missing in conversations cached before it was fetched
+    pub last_review: Option<ReviewedHead>,
     pub items: Vec<ConversationItem>,
+    // Earlier events that didn't fit into the timeline
+    pub hidden_events: usize,
+}
+
+// GitHub dismisses the mark of a viewed file once the file changes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ViewedState { Viewed, Unviewed, Dismissed }
+
+// Head of the pull request at the time the viewer reviewed it
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReviewedHead {
+    pub sha: String,
+    pub timestamp: DateTime<Local>,
+}
+
+// Branch the pull request is merged from. Forks have a repository of their own
+#[derive(Debug, Clone)]
+pub struct PrHead {
+    pub branch: String,
+    pub repository_url: String,
+    pub owner: String,
+    pub is_fork: bool,
+}
+
+impl PrConversation {
+    // Commits of a range reviewed without a pull request
+    pub fn from_commits(commits: Vec<PrEvent>) -> Self {
+        let items = commits.into_iter().map(ConversationItem::Event).collect();
+        PrConversation { id: None, head_sha: None, base_sha: None, head: None, last_review: None, items, hidden_events: 0 }
+    }
+
+    // Authors of the comments, each one once
+    pub fn participants(&self) -> Vec<&str> {
+        let mut participants: Vec<&str> = vec![];
+        for item in self.items.iter() {
+            let comments: Vec<&PrComment> = match item {
+                ConversationItem::Comment(c) => vec![c],
+                ConversationItem::Review(r) => std::iter::once(&r.review_comment)
+                    .chain(r.threads.iter().flat_map(|t| t.comments.iter()))
+                    .collect(),
+                ConversationItem::Event(_) => vec![],
+            };
+            for comment in comments {
+                if !participants.contains(&comment.author_name.as_str()) {
+                    participants.push(&comment.author_name);
+                }
+            }
+        }
+        participants
+    }
+
+    pub fn threads(&self) -> impl Iterator<Item = &PrConversationThread> {
+        self.items.iter()
+            .filter_map(|item| match item {
+                ConversationItem::Review(r) => Some(r.threads.iter()),
+                _ => None,
+            })
+            .flatten()
+    }
+
+    // Any comment of the conversation: a review, a comment of its threads or an issue comment
+    pub fn comment_mut(&mut self, id: &str) -> Option<&mut PrComment> {
+        self.items.iter_mut()
+            .flat_map(|item| -> Box<dyn Iterator<Item = &mut PrComment>> {
+                match item {
+                    ConversationItem::Comment(c) => Box::new(std::iter::once(c)),
+                    ConversationItem::Review(r) => Box::new(std::iter::once(&mut r.review_comment)
+                        .chain(r.threads.iter_mut().flat_map(|t| t.comments.iter_mut()))),
+                    ConversationItem::Event(_) => Box::new(std::iter::empty()),
+                }
+            })
+            .find(|c| c.id == id)
+    }
+}
+
+// Users and issues a comment can mention
+#[derive(Debug, Clone, Default)]
+pub struct References {
+    pub users: Vec<String>,
+    pub issues: Vec<IssueReference>,
+}
+
+// Pull requests are issues as well as far as references go
+#[derive(Debug, Clone)]
+pub struct IssueReference {
+    pub number: u32,
+    pub title: String,
 }
 
-pub fn list_prs (json: JsonValue) -> Vec<PrHeader> {
+pub fn list_prs (json: &JsonValue) -> Result<Vec<PrHeader>, Error> {
+    check_errors(json)?;
     let output = json["data"]["repository"]["pullRequests"]["edges"].members();
     let mut prs = Vec::new();
     for pr in output {
-        let number = pr["node"]["number"].as_u32().unwrap();
-        let title = pr["node"]["title"].as_str().unwrap().to_string();
+        let number = get_u32(&pr["node"], "number")?;
+        let title = get_str(&pr["node"], "title")?.to_string();
         prs.push(PrHeader{number, title});
     }
     
-    prs
+    Ok(prs)
 }
 
-pub fn parse_conversation(json: JsonValue) -> PrConversation {
+pub fn head_sha(json: &JsonValue) -> Option<String> {
+    json["data"]["repository"]["pullRequest"]["headRefOid"].as_str().map(|s| s.to_string())
+}
+
+pub fn parse_conversation(json: &JsonValue) -> Result<PrConversation, Error> {
+    check_errors(json)?;
     let threads = json["data"]["repository"]["pullRequest"]["reviewThreads"]["edges"].members();
     let reviews = json["data"]["repository"]["pullRequest"]["reviews"]["edges"].members();
     let comments = json["data"]["repository"]["pullRequest"]["comments"]["edges"].members();
 
     let mut threads_map = HashMap::new();
     for thread in threads {
+        let id = get_str(&thread["node"], "id")?.to_string();
         let file_name = thread["node"]["path"].as_str().map(|s| s.to_string());
-        let side = thread["node"]["diffSide"].as_str().map(|s| { if s == "LEFT" { DiffSide::Left } else { DiffSide::Right } }).unwrap();
-        let end_line = thread["node"]["originalLine"].as_usize().unwrap();
-        let start_line = thread["node"]["originalStartLine"].as_usize().map_or(end_line, |n| n);
-        let code_range = file_name.map(|f| CodeRange::new(f, side, start_line, end_line));
+        let side = if get_str(&thread["node"], "diffSide")? == "LEFT" { DiffSide::Left } else { DiffSide::Right };
+        // Outdated threads don't have a line, these can't be shown in the diff
+        let code_range = thread["node"]["originalLine"].as_usize().and_then(|end_line| {
+            let start_line = thread["node"]["originalStartLine"].as_usize().unwrap_or(end_line);
+            file_name.map(|f| CodeRange::new(f, side, start_line, end_line))
+        });
 
         let thread_comments = thread["node"]["comments"]["edges"].members();
         let mut comments_list = vec![];
+        let line = thread["node"]["line"].as_usize();
         let mut root_comment = String::new();
+        let mut original_commit = None;
         for (index, thread_comment) in thread_comments.enumerate() {
             if index == 0 {
-                root_comment = thread_comment["node"]["id"].as_str().unwrap().to_string(); 
+                root_comment = get_str(&thread_comment["node"], "id")?.to_string(); 
+                original_commit = thread_comment["node"]["originalCommit"]["oid"].as_str().map(|s| s.to_string());
             }
-            comments_list.push(fetch_pr_comment(&thread_comment["node"]));
+            comments_list.push(fetch_pr_comment(&thread_comment["node"], CommentKind::ReviewComment)?);
         }
 
         if root_comment.len() > 0 {
-            threads_map.insert(root_comment, PrConversationThread {code_range, comments: comments_list});
+            threads_map.insert(root_comment, PrConversationThread {id, code_range, original_commit, line, comments: comments_list});
         }
     }
 
     let mut conversation_items : Vec<ConversationItem> = vec![];
     for review in reviews {
-        let verdict = match review["node"]["state"].as_str().unwrap().to_lowercase().as_str() {
+        let verdict = match get_str(&review["node"], "state")?.to_lowercase().as_str() {
             "commented" => PrReviewVerdict::Comment,
             "approved" => PrReviewVerdict::Approve,
             "changes_requested" => PrReviewVerdict::ChangesRequested,
             _ => continue
         };
-        let review_comment = fetch_pr_comment(&review["node"]);
+        let review_comment = fetch_pr_comment(&review["node"], CommentKind::Review)?;
         let mut threads = vec![];
         let review_comments = review["node"]["comments"]["edges"].members();
         for comment in review_comments {
-            if let Some(thread) = threads_map.remove_entry(comment["node"]["id"].as_str().unwrap()) {
+            if let Some(thread) = threads_map.remove_entry(get_str(&comment["node"], "id")?) {
                 threads.push(thread.1);
             }
         }
@@ -127,21 +357,295 @@ pub fn parse_conversation(json: JsonValue) -> PrConversation {
         }
     }
 
-    conversation_items.extend(comments
-                              .map(|v| fetch_pr_comment(&v["node"]))
-                              .map(|c| ConversationItem::Comment(c)));
+    for comment in comments {
+        conversation_items.push(ConversationItem::Comment(fetch_pr_comment(&comment["node"], CommentKind::IssueComment)?));
+    }
+
+    let timeline = &json["data"]["repository"]["pullRequest"]["timelineItems"];
+    let hidden_events = timeline["totalCount"].as_usize().unwrap_or_default().saturating_sub(timeline["edges"].len());
+    for event in timeline["edges"].members() {
+        if let Some(event) = fetch_pr_event(&event["node"])? {
+            conversation_items.push(ConversationItem::Event(event));
+        }
+    }
+
+    // The sort is stable, so the items with equal timestamps keep the order they were fetched in
+    conversation_items.sort_by_key(|item| item.timestamp());
+    let id = json["data"]["repository"]["pullRequest"]["id"].as_str().map(|s| s.to_string());
+    let base_sha = json["data"]["repository"]["pullRequest"]["baseRefOid"].as_str().map(|s| s.to_string());
+    Ok(PrConversation{id, head_sha: head_sha(json), base_sha, head: parse_head(json), last_review: parse_last_review(json), items: conversation_items, hidden_events})
+}
+
+// Files the viewer marked, from all the pages of the files of the pull request
+pub fn parse_viewed_files(pages: &[JsonValue]) -> Result<HashMap<String, ViewedState>, Error> {
+    let mut viewed_files = HashMap::new();
+    for page in pages {
+        check_errors(page)?;
+        for file in page["data"]["repository"]["pullRequest"]["files"]["edges"].members() {
+            let state = match get_str(&file["node"], "viewerViewedState")? {
+                "VIEWED" => ViewedState::Viewed,
+                "DISMISSED" => ViewedState::Dismissed,
+                _ => ViewedState::Unviewed,
+            };
+            viewed_files.insert(get_str(&file["node"], "path")?.to_string(), state);
+        }
+    }
+    Ok(viewed_files)
+}
+
+// Reviews still pending aren't submitted, so they don't count
+fn parse_last_review(json: &JsonValue) -> Option<ReviewedHead> {
+    let review = &json["data"]["repository"]["pullRequest"]["viewerLatestReview"];
+    if review["state"].as_str()? == "PENDING" {
+        return None;
+    }
+    Some(ReviewedHead {
+        sha: review["commit"]["oid"].as_str()?.to_string(),
+        timestamp: get_timestamp(review, "submittedAt").ok()?,
+    })
+}
+
+// Missing if the head repository was deleted, or in conversations cached before it was fetched
+fn parse_head(json: &JsonValue) -> Option<PrHead> {
+    let pr = &json["data"]["repository"]["pullRequest"];
+    Some(PrHead {
+        branch: pr["headRefName"].as_str()?.to_string(),
+        repository_url: pr["headRepository"]["url"].as_str()?.to_string(),
+        owner: pr["headRepositoryOwner"]["login"].as_str()?.to_string(),
+        is_fork: pr["isCrossRepository"].as_bool()?,
+    })
+}
+
+// Event types that aren't known are skipped
+fn fetch_pr_event(node: &JsonValue) -> Result<Option<PrEvent>, Error> {
+    let typename = get_str(node, "__typename")?;
+    let actor_name = node["actor"]["login"].as_str().unwrap_or(GHOST_AUTHOR).to_string();
+    let (actor_name, timestamp, kind) = match typename {
+        "PullRequestCommit" => {
+            let commit = &node["commit"];
+            let author_name = commit["author"]["user"]["login"].as_str()
+                .or_else(|| commit["author"]["name"].as_str())
+                .unwrap_or(GHOST_AUTHOR)
+                .to_string();
+            let kind = PrEventKind::Commit { oid: get_str(commit, "abbreviatedOid")?.to_string(), message: get_str(commit, "message")?.to_string() };
+            (author_name, get_timestamp(commit, "committedDate")?, kind)
+        },
+        "HeadRefForcePushedEvent" => {
+            // The commits are missing when they were garbage collected
+            let oid = |field: &str| node[field]["abbreviatedOid"].as_str().unwrap_or("unknown").to_string();
+            (actor_name, get_timestamp(node, "createdAt")?, PrEventKind::ForcePushed { before: oid("beforeCommit"), after: oid("afterCommit") })
+        },
+        "LabeledEvent" => (actor_name, get_timestamp(node, "createdAt")?, PrEventKind::Labeled(get_str(&node["label"], "name")?.to_string())),
+        "UnlabeledEvent" => (actor_name, get_timestamp(node, "createdAt")?, PrEventKind::Unlabeled(get_str(&node["label"], "name")?.to_string())),
+        "ReviewRequestedEvent" => {
+            let reviewer = &node["requestedReviewer"];
+            let reviewer = reviewer["login"].as_str().or_else(|| reviewer["name"].as_str()).unwrap_or(GHOST_AUTHOR).to_string();
+            (actor_name, get_timestamp(node, "createdAt")?, PrEventKind::ReviewRequested(reviewer))
+        },
+        "MergedEvent" => {
+            let commit = node["commit"]["abbreviatedOid"].as_str().unwrap_or("unknown").to_string();
+            let base_branch = get_str(node, "mergeRefName")?.to_string();
+            (actor_name, get_timestamp(node, "createdAt")?, PrEventKind::Merged { commit, base_branch })
+        },
+        "ClosedEvent" => (actor_name, get_timestamp(node, "createdAt")?, PrEventKind::Closed),
+        "ReopenedEvent" => (actor_name, get_timestamp(node, "createdAt")?, PrEventKind::Reopened),
+        _ => return Ok(None),
+    };
+    Ok(Some(PrEvent { actor_name, timestamp, kind }))
+}
+
+fn fetch_pr_comment(node: &json::JsonValue, kind: CommentKind) -> Result<PrComment, Error> {
+    let id = get_str(node, "id")?.to_string();
+    // Deleted accounts come without an author
+    let author_name = node["author"]["login"].as_str().unwrap_or(GHOST_AUTHOR).to_string();
+    let body = get_str(node, "body")?.to_string();
+    let timestamp = get_timestamp(node, "publishedAt")?;
+    let last_edited_at = match node["lastEditedAt"].is_null() {
+        true => None,
+        false => Some(get_timestamp(node, "lastEditedAt")?),
+    };
+    let reactions = node["reactionGroups"].members()
+        .filter_map(|group| {
+            let content = ReactionContent::from_name(group["content"].as_str()?)?;
+            let count = group["reactors"]["totalCount"].as_u32().filter(|c| *c > 0)?;
+            let viewer_has_reacted = group["viewerHasReacted"].as_bool().unwrap_or(false);
+            Some(PrReaction { content, count, viewer_has_reacted })
+        })
+        .collect();
+    let viewer_did_author = node["viewerDidAuthor"].as_bool().unwrap_or(false);
+    let url = node["url"].as_str().unwrap_or_default().to_string();
+    Ok(PrComment {id, author_name, body, timestamp, last_edited_at, reactions, kind, viewer_did_author, url})
+}
+
+// The newest issues go first
+pub fn parse_references(json: &JsonValue) -> Result<References, Error> {
+    check_errors(json)?;
+    let repository = &json["data"]["repository"];
+    let users = repository["mentionableUsers"]["nodes"].members()
+        .map(|user| get_str(user, "login").map(|login| login.to_string()))
+        .collect::<Result<Vec<_>, Error>>()?;
+    let mut issues = vec![];
+    for issue in repository["issues"]["nodes"].members().chain(repository["pullRequests"]["nodes"].members()) {
+        issues.push(IssueReference { number: get_u32(issue, "number")?, title: get_str(issue, "title")?.to_string() });
+    }
+    issues.sort_by_key(|issue| std::cmp::Reverse(issue.number));
+    Ok(References { users, issues })
+}
+
+// Mutations only report errors, their data isn't used
+pub fn parse_mutation(json: &JsonValue) -> Result<(), Error> {
+    check_errors(json)
+}
+
+pub fn parse_comment_edits(json: &JsonValue) -> Result<Vec<PrCommentEdit>, Error> {
+    check_errors(json)?;
+    let node = &json["data"]["node"];
+    if node.is_null() {
+        return Err(Error::MissingField("node".to_string()));
+    }
+
+    let mut edits = vec![];
+    for edit in node["userContentEdits"]["edges"].members() {
+        let edit = &edit["node"];
+        let editor_name = edit["editor"]["login"].as_str().unwrap_or(GHOST_AUTHOR).to_string();
+        let timestamp = get_timestamp(edit, "editedAt")?;
+        let body = edit["diff"].as_str().map(|s| s.to_string());
+        edits.push(PrCommentEdit { editor_name, timestamp, body, is_creation: false });
+    }
+
+    // The oldest edit is the comment as it was created, unless it was edited before the history was kept
+    if node["includesCreatedEdit"].as_bool().unwrap_or(false) {
+        if let Some(created) = edits.last_mut() {
+            created.is_creation = true;
+        }
+    }
+    Ok(edits)
+}
+
+fn get_timestamp(node: &JsonValue, field: &str) -> Result<DateTime<Local>, Error> {
+    let timestamp = get_str(node, field)?;
+    let timestamp = DateTime::parse_from_rfc3339(timestamp)
+        .map_err(|e| Error::MalformedResponse(format!("{} {}: {}", field, timestamp, e)))?;
+    Ok(timestamp.with_timezone(&Local))
+}
+
+const GHOST_AUTHOR: &str = "ghost";
+
+fn check_errors(json: &JsonValue) -> Result<(), Error> {
+    let messages: Vec<&str> = json["errors"].members().filter_map(|e| e["message"].as_str()).collect();
+    if messages.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::Api(messages.join("\n")))
+    }
+}
 
-    PrConversation{items: conversation_items}
+fn get_str<'a>(node: &'a JsonValue, field: &str) -> Result<&'a str, Error> {
+    node[field].as_str().ok_or_else(|| Error::MissingField(field.to_string()))
 }
 
-fn fetch_pr_comment(node: &json::JsonValue) -> PrComment {
-    let id = node["id"].as_str().unwrap().to_string();
-    let author_name = node["author"]["login"].as_str().unwrap().to_string();
-    let body = node["body"].as_str().unwrap().to_string();
-    let timestamp = node["publishedAt"].as_str().unwrap();
-    let timestamp = DateTime::parse_from_rfc3339(timestamp).unwrap();
-    let timestamp = timestamp.with_timezone(&Local);
-    PrComment {id, author_name, body, timestamp}
+fn get_u32(node: &JsonValue, field: &str) -> Result<u32, Error> {
+    node[field].as_u32().ok_or_else(|| Error::MissingField(field.to_string()))
 }
 
 unsafe impl Send for PrHeader { }
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn malformed_fields_are_errors() {
+        let json = json::parse(r#"{"data": {"repository": {"pullRequests": {"edges": [{"node": {"number": "1", "title": "x"}}]}}}}"#).unwrap();
+        assert!(matches!(list_prs(&json), Err(Error::MissingField(f)) if f == "number"));
+
+        let json = json::parse(r#"{"errors": [{"message": "Something went wrong"}]}"#).unwrap();
+        assert!(matches!(parse_conversation(&json), Err(Error::Api(_))));
+    }
+
+    #[test]
+    fn deleted_author() {
+        let json = json::parse(r#"{"data": {"repository": {"pullRequest": {"comments": {"edges": [
+            {"node": {"id": "c1", "author": null, "body": "hi", "publishedAt": "2021-11-20T10:00:00Z"}}
+        ]}}}}}"#).unwrap();
+        let conversation = parse_conversation(&json).unwrap();
+        assert!(matches!(&conversation.items[0], ConversationItem::Comment(c) if c.author_name == "ghost"));
+    }
+
+    #[test]
+    fn reactions() {
+        let json = json::parse(r#"{"data": {"repository": {"pullRequest": {"comments": {"edges": [
+            {"node": {"id": "c1", "author": {"login": "bob"}, "body": "hi", "publishedAt": "2021-11-20T10:00:00Z", "reactionGroups": [
+                {"content": "THUMBS_UP", "viewerHasReacted": false, "reactors": {"totalCount": 2}},
+                {"content": "HEART", "viewerHasReacted": false, "reactors": {"totalCount": 0}},
+                {"content": "ROCKET", "viewerHasReacted": true, "reactors": {"totalCount": 1}}
+            ]}}
+        ]}}}}}"#).unwrap();
+        let mut conversation = parse_conversation(&json).unwrap();
+        let comment = conversation.comment_mut("c1").unwrap();
+        assert_eq!(comment.reactions.len(), 2);
+
+        comment.set_reaction(ReactionContent::ThumbsUp, true);
+        comment.set_reaction(ReactionContent::ThumbsUp, true);
+        comment.set_reaction(ReactionContent::Rocket, false);
+        comment.set_reaction(ReactionContent::Heart, true);
+        let counts: Vec<_> = comment.reactions.iter().map(|r| (r.content, r.count, r.viewer_has_reacted)).collect();
+        assert_eq!(counts, vec![(ReactionContent::ThumbsUp, 3, true), (ReactionContent::Heart, 1, true)]);
+    }
+
+    #[test]
+    fn edit_history() {
+        let json = json::parse(r#"{"data": {"node": {"includesCreatedEdit": true, "userContentEdits": {"edges": [
+            {"node": {"editedAt": "2021-11-20T12:00:00Z", "editor": {"login": "bob"}, "diff": "fixed typo"}},
+            {"node": {"editedAt": "2021-11-20T10:00:00Z", "editor": {"login": "bob"}, "diff": "fixed tpyo"}}
+        ]}}}}"#).unwrap();
+        let edits = parse_comment_edits(&json).unwrap();
+        assert_eq!(edits.len(), 2);
+        assert!(!edits[0].is_creation && edits[1].is_creation);
+        assert_eq!(edits[0].body.as_deref(), Some("fixed typo"));
+
+        let json = json::parse(r#"{"data": {"node": null}}"#).unwrap();
+        assert!(parse_comment_edits(&json).is_err());
+    }
+
+    #[test]
+    fn items_are_in_time_order() {
+        let json = json::parse(r#"{"data": {"repository": {"pullRequest": {
+            "viewerLatestReview": {"state": "COMMENTED", "submittedAt": "2021-11-20T14:00:00Z", "commit": {"oid": "abc1234"}},
+            "reviews": {"edges": [
+                {"node": {"id": "r1", "state": "APPROVED", "author": {"login": "bob"}, "body": "lgtm", "publishedAt": "2021-11-20T12:00:00Z", "comments": {"edges": []}}}
+            ]},
+            "comments": {"edges": [
+                {"node": {"id": "c1", "author": {"login": "alice"}, "body": "hi", "publishedAt": "2021-11-20T10:00:00Z"}}
+            ]},
+            "timelineItems": {"totalCount": 5, "edges": [
+                {"node": {"__typename": "PullRequestCommit", "commit": {"abbreviatedOid": "abc1234", "message": "Fix", "committedDate": "2021-11-20T11:00:00Z", "author": {"user": null, "name": "Alice"}}}},
+                {"node": {"__typename": "LabeledEvent", "actor": {"login": "bob"}, "createdAt": "2021-11-20T13:00:00Z", "label": {"name": "bug"}}},
+                {"node": {"__typename": "SubscribedEvent", "actor": {"login": "bob"}, "createdAt": "2021-11-20T13:00:00Z"}}
+            ]}
+        }}}}"#).unwrap();
+        let conversation = parse_conversation(&json).unwrap();
+        let items: Vec<&str> = conversation.items.iter()
+            .map(|item| match item {
+                ConversationItem::Comment(_) => "comment",
+                ConversationItem::Review(_) => "review",
+                ConversationItem::Event(PrEvent { kind: PrEventKind::Commit { .. }, actor_name, .. }) if actor_name == "Alice" => "commit",
+                ConversationItem::Event(PrEvent { kind: PrEventKind::Labeled(label), .. }) if label == "bug" => "label",
+                ConversationItem::Event(_) => "other",
+            })
+            .collect();
+        assert_eq!(items, vec!["comment", "commit", "review", "label"]);
+        assert_eq!(conversation.hidden_events, 2);
+        assert_eq!(conversation.last_review.map(|r| r.sha).as_deref(), Some("abc1234"));
+    }
+
+    #[test]
+    fn viewed_files_of_all_pages() {
+        let page = |path: &str, state: &str| json::parse(&format!(r#"{{"data": {{"repository": {{"pullRequest": {{"files": {{"edges": [
+            {{"node": {{"path": "{}", "viewerViewedState": "{}"}}}}
+        ]}}}}}}}}}}"#, path, state)).unwrap();
+        let viewed_files = parse_viewed_files(&[page("a.rs", "VIEWED"), page("b.rs", "DISMISSED")]).unwrap();
+        assert_eq!((viewed_files["a.rs"], viewed_files["b.rs"]), (ViewedState::Viewed, ViewedState::Dismissed));
+    }
+}
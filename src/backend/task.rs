@@ -1,39 +1,376 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::panic::{self, AssertUnwindSafe};
 use std::thread;
-use std::sync::mpsc;
 use crate::app::events::AppEvent;
+use crate::error::Error;
+use crate::frontend::screen::ScreenId;
 
+const WORKER_COUNT: usize = 4;
+const WORKER_NAME: &str = "worker";
+
+pub type TaskId = u64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaskState {
+    Queued,
+    Running,
+    Finished,
+    Failed,
+    Cancelled,
+}
+
+#[derive(Debug, Clone)]
+pub struct TaskProgress {
+    pub id: TaskId,
+    pub label: String,
+    pub state: TaskState,
+}
+
+type Job = Box<dyn FnOnce() + Send>;
+
+// What the subscribers of a task receive when the task panics
+pub trait TaskResult: Clone + Send + 'static {
+    fn failed(message: String) -> Self;
+}
+
+impl<T: Clone + Send + 'static> TaskResult for Result<T, Error> {
+    fn failed(message: String) -> Self {
+        Err(Error::Other(message))
+    }
+}
+
+// Tasks are run by a fixed number of worker threads shared by all the screens.
+// Cloning the manager gives another handle to the same pool. Completions of the
+// tasks are addressed to the screen the manager was made for.
+#[derive(Clone)]
 pub struct TaskManager {
-    sender: mpsc::Sender<AppEvent>,
+    pool: Arc<Pool>,
+    screen: ScreenId,
+}
+
+struct Pool {
+    // Taken away when the pool shuts down
+    jobs: Mutex<Option<mpsc::Sender<Job>>>,
+    workers: Mutex<Vec<thread::JoinHandle<()>>>,
+    app_sender: Mutex<mpsc::Sender<AppEvent>>,
+    next_id: AtomicU64,
+    // Suffix of the keys of the tasks that are never deduplicated
+    next_unique: AtomicU64,
+    in_flight: Mutex<HashMap<String, Arc<dyn Any + Send + Sync>>>,
+}
+
+struct Task<T> {
+    id: TaskId,
+    label: String,
+    subscribers: Mutex<Vec<(ScreenId, mpsc::Sender<T>)>>,
+    handles: AtomicUsize,
+    cancelled: AtomicBool,
 }
 
+// Dropping the last handle of a task cancels it: a queued task is skipped
+// and the result of a running one is thrown away without notifying the app.
 pub struct TaskHandle<T> {
     receiver: mpsc::Receiver<T>,
+    task: Arc<Task<T>>,
 }
 
 impl TaskManager {
     pub fn new(sender: mpsc::Sender<AppEvent>) -> Self {
-        TaskManager{ sender }
+        let (jobs, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let workers = (0..WORKER_COUNT).map(|index| {
+            let receiver = Arc::clone(&receiver);
+            thread::Builder::new()
+                .name(format!("{}-{}", WORKER_NAME, index))
+                .spawn(move || loop {
+                    let job = match receiver.lock() {
+                        Ok(receiver) => receiver.recv(),
+                        Err(_) => break,
+                    };
+                    match job {
+                        Ok(job) => job(),
+                        Err(_) => break,
+                    }
+                })
+                .expect("Failed to start a worker thread")
+        }).collect();
+
+        let pool = Pool {
+            jobs: Mutex::new(Some(jobs)),
+            workers: Mutex::new(workers),
+            app_sender: Mutex::new(sender),
+            next_id: AtomicU64::new(1),
+            next_unique: AtomicU64::new(1),
+            in_flight: Mutex::new(HashMap::new()),
+        };
+        TaskManager { pool: Arc::new(pool), screen: 0 }
     }
 
-    pub fn post<F, T>(&mut self, mut task: F) -> TaskHandle<T> 
-        where F: FnMut() -> T + Send + 'static,
-              T: Send + 'static {
+    pub fn for_screen(&self, screen: ScreenId) -> TaskManager {
+        TaskManager { pool: Arc::clone(&self.pool), screen }
+    }
+
+    // Tasks with the same key are deduplicated: while one is queued or running,
+    // posting another one subscribes to its result instead of starting a new task.
+    pub fn post<F, T>(&self, key: &str, label: &str, task: F) -> TaskHandle<T>
+        where F: FnOnce() -> T + Send + 'static,
+              T: TaskResult {
 
         let (sender, receiver) = mpsc::channel();
-        let app_sender = self.sender.clone();
-        //TODO use a thread pool
-        thread::spawn(move || {
-            let result = (task)();
-            if let Ok(_) = sender.send(result) {
-                app_sender.send(AppEvent::TaskCompleted).unwrap();
-            }
+        let mut in_flight = self.pool.in_flight.lock().unwrap();
+        let existing = in_flight.get(key)
+            .and_then(|task| Arc::clone(task).downcast::<Task<T>>().ok())
+            .filter(|task| !task.cancelled.load(Ordering::SeqCst));
+        if let Some(task) = existing {
+            task.subscribers.lock().unwrap().push((self.screen, sender));
+            task.handles.fetch_add(1, Ordering::SeqCst);
+            crate::logs::debug!("Joined a task in flight", id = task.id, key = key);
+            return TaskHandle { receiver, task };
+        }
+
+        let task_state = Arc::new(Task {
+            id: self.pool.next_id.fetch_add(1, Ordering::SeqCst),
+            label: label.to_string(),
+            subscribers: Mutex::new(vec![(self.screen, sender)]),
+            handles: AtomicUsize::new(1),
+            cancelled: AtomicBool::new(false),
         });
-        TaskHandle{receiver}
+        in_flight.insert(key.to_string(), Arc::clone(&task_state) as Arc<dyn Any + Send + Sync>);
+        drop(in_flight);
+
+        self.pool.report(&task_state, TaskState::Queued);
+        let pool = Arc::clone(&self.pool);
+        let job_task = Arc::clone(&task_state);
+        let key = key.to_string();
+        let job: Job = Box::new(move || pool.run(&key, &job_task, task));
+        let is_sent = self.pool.jobs.lock().unwrap().as_ref().is_some_and(|jobs| jobs.send(job).is_ok());
+        if !is_sent {
+            crate::logs::error!("Task pool is shut down", id = task_state.id);
+        }
+        TaskHandle { receiver, task: task_state }
+    }
+
+    // Writes are never joined with a task in flight: the second one would be reported as
+    // done without being sent. The key only names the task in the logs
+    pub fn post_unique<F, T>(&self, key: &str, label: &str, task: F) -> TaskHandle<T>
+        where F: FnOnce() -> T + Send + 'static,
+              T: TaskResult {
+
+        let key = format!("{}#{}", key, self.pool.next_unique.fetch_add(1, Ordering::SeqCst));
+        self.post(&key, label, task)
+    }
+
+    // Waits for the running tasks, the queued ones are skipped once their handles are dropped.
+    // Tasks posted afterwards never run
+    pub fn shut_down(&self) {
+        drop(self.pool.jobs.lock().unwrap().take());
+        let workers = std::mem::take(&mut *self.pool.workers.lock().unwrap());
+        for worker in workers {
+            let _ = worker.join();
+        }
+    }
+}
+
+pub fn is_worker_thread() -> bool {
+    thread::current().name().is_some_and(|name| name.starts_with(WORKER_NAME))
+}
+
+impl Pool {
+    fn run<F, T>(&self, key: &str, task: &Arc<Task<T>>, job: F)
+        where F: FnOnce() -> T,
+              T: TaskResult {
+
+        if task.cancelled.load(Ordering::SeqCst) {
+            self.finish(key, task);
+            self.report(task, TaskState::Cancelled);
+            return;
+        }
+
+        self.report(task, TaskState::Running);
+        let (result, state) = match panic::catch_unwind(AssertUnwindSafe(job)) {
+            Ok(result) => (result, TaskState::Finished),
+            Err(payload) => {
+                let message = payload.downcast_ref::<&str>().map(|m| m.to_string())
+                    .or_else(|| payload.downcast_ref::<String>().cloned())
+                    .unwrap_or_else(|| "Unknown panic".to_string());
+                crate::logs::error!("Task panicked", id = task.id, label = task.label, message = message);
+                (T::failed(format!("Task `{}` failed: {}", task.label, message)), TaskState::Failed)
+            }
+        };
+
+        // New subscribers can't join once the task leaves the in-flight map,
+        // so nobody misses the result
+        self.finish(key, task);
+        let subscribers = std::mem::take(&mut *task.subscribers.lock().unwrap());
+        let screens: Vec<ScreenId> = subscribers.into_iter()
+            .filter(|(_, subscriber)| subscriber.send(result.clone()).is_ok())
+            .map(|(screen, _)| screen)
+            .collect();
+
+        if screens.is_empty() || task.cancelled.load(Ordering::SeqCst) {
+            self.report(task, TaskState::Cancelled);
+            return;
+        }
+
+        self.report(task, state);
+        for screen in screens {
+            self.send(AppEvent::TaskCompleted(screen, task.id));
+        }
+    }
+
+    fn finish<T: Send + 'static>(&self, key: &str, task: &Arc<Task<T>>) {
+        let mut in_flight = self.in_flight.lock().unwrap();
+        let is_same_task = in_flight.get(key)
+            .and_then(|t| Arc::clone(t).downcast::<Task<T>>().ok())
+            .is_some_and(|t| Arc::ptr_eq(&t, task));
+        if is_same_task {
+            in_flight.remove(key);
+        }
+    }
+
+    fn report<T>(&self, task: &Task<T>, state: TaskState) {
+        crate::logs::trace!("Task changed state", id = task.id, label = task.label, state = format!("{:?}", state));
+        self.send(AppEvent::TaskProgress(TaskProgress { id: task.id, label: task.label.clone(), state }));
+    }
+
+    fn send(&self, event: AppEvent) {
+        if let Ok(sender) = self.app_sender.lock() {
+            let _ = sender.send(event);
+        }
     }
 }
 
 impl<T> TaskHandle <T> {
+    pub fn id(&self) -> TaskId {
+        self.task.id
+    }
+
     pub fn poll(&self) -> Option<T> {
         self.receiver.try_recv().ok()
     }
 }
+
+impl<T> Drop for TaskHandle<T> {
+    fn drop(&mut self) {
+        if self.task.handles.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.task.cancelled.store(true, Ordering::SeqCst);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::time::Duration;
+
+    fn wait<T>(handle: &TaskHandle<T>) -> T {
+        handle.receiver.recv_timeout(Duration::from_secs(5)).unwrap()
+    }
+
+    #[test]
+    fn identical_tasks_are_deduplicated() {
+        let (sender, events) = mpsc::channel();
+        let manager = TaskManager::new(sender);
+        let other_screen = manager.for_screen(7);
+        let (unblock, blocker) = mpsc::channel::<()>();
+        let runs = Arc::new(AtomicUsize::new(0));
+
+        let counter = Arc::clone(&runs);
+        let first = manager.post("key", "first", move || {
+            blocker.recv().unwrap();
+            Ok::<_, Error>(counter.fetch_add(1, Ordering::SeqCst) + 1)
+        });
+        let second = other_screen.post("key", "second", || Ok::<usize, Error>(100));
+        unblock.send(()).unwrap();
+
+        assert_eq!(wait(&first).unwrap(), 1);
+        assert_eq!(wait(&second).unwrap(), 1);
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+
+        let third = manager.post("key", "third", || Ok(3));
+        assert_eq!(wait(&third).unwrap(), 3);
+        assert_eq!(first.id(), second.id());
+        let completions: Vec<_> = events.try_iter()
+            .filter_map(|e| match e {
+                AppEvent::TaskCompleted(screen, id) if id == first.id() => Some(screen),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(completions, vec![0, 7]);
+    }
+
+    #[test]
+    fn unique_tasks_run_separately() {
+        let (sender, _events) = mpsc::channel();
+        let manager = TaskManager::new(sender);
+        let (unblock, blocker) = mpsc::channel::<()>();
+
+        let first = manager.post_unique("key", "first", move || { blocker.recv().unwrap(); Ok::<_, Error>(1) });
+        let second = manager.post_unique("key", "second", || Ok(2));
+        assert_eq!(wait(&second).unwrap(), 2);
+        unblock.send(()).unwrap();
+        assert_eq!(wait(&first).unwrap(), 1);
+        assert_ne!(first.id(), second.id());
+    }
+
+    #[test]
+    fn dropped_tasks_are_cancelled() {
+        let (sender, events) = mpsc::channel();
+        let manager = TaskManager::new(sender);
+        let (unblock, blocker) = mpsc::channel::<()>();
+
+        // Occupy every worker, so the next task stays queued
+        let blocker = Arc::new(Mutex::new(blocker));
+        let busy: Vec<_> = (0..WORKER_COUNT).map(|i| {
+            let blocker = Arc::clone(&blocker);
+            manager.post(&format!("busy-{}", i), "busy", move || { let _ = blocker.lock().unwrap().recv(); Ok::<_, Error>(()) })
+        }).collect();
+
+        let runs = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&runs);
+        drop(manager.post("dropped", "dropped", move || Ok::<_, Error>(counter.fetch_add(1, Ordering::SeqCst))));
+
+        for _ in 0..WORKER_COUNT {
+            unblock.send(()).unwrap();
+        }
+        busy.iter().map(wait).for_each(Result::unwrap);
+
+        let cancelled = std::iter::from_fn(|| events.recv_timeout(Duration::from_secs(5)).ok())
+            .any(|e| matches!(e, AppEvent::TaskProgress(TaskProgress { state: TaskState::Cancelled, .. })));
+        assert!(cancelled);
+        assert_eq!(runs.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn panicking_tasks_fail() {
+        let (sender, events) = mpsc::channel();
+        let manager = TaskManager::new(sender);
+
+        let failed = manager.post("key", "failed", || -> Result<usize, Error> { panic!("broken") });
+        assert!(matches!(wait(&failed), Err(Error::Other(message)) if message.contains("broken")));
+        let reported = std::iter::from_fn(|| events.recv_timeout(Duration::from_secs(5)).ok())
+            .any(|e| matches!(e, AppEvent::TaskProgress(TaskProgress { state: TaskState::Failed, .. })));
+        assert!(reported);
+
+        let retried = manager.post("key", "retried", || Ok(1));
+        assert_eq!(wait(&retried).unwrap(), 1);
+        assert_ne!(failed.id(), retried.id());
+    }
+
+    #[test]
+    fn shutting_down_waits_for_running_tasks() {
+        let (sender, _events) = mpsc::channel();
+        let manager = TaskManager::new(sender);
+
+        let running = manager.post("running", "running", || { thread::sleep(Duration::from_millis(100)); Ok::<_, Error>(1) });
+        manager.shut_down();
+        assert_eq!(running.poll().unwrap().unwrap(), 1);
+
+        let late = manager.post("late", "late", || Ok::<_, Error>(2));
+        assert!(late.receiver.recv_timeout(Duration::from_millis(100)).is_err());
+    }
+}
@@ -0,0 +1,280 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml::value::{Table, Value};
+use crate::error::Error;
+use crate::frontend::keymap::{self, Action, KeyBinding, KeymapContext};
+use crate::frontend::theme::{self, StyleSpec, Theme};
+
+const CONFIG_FILE: &str = "config.toml";
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub queries_dir: Option<PathBuf>,
+    pub page_size: u32,
+    pub pr_list_size: u32,
+    // Events of the timeline are numerous, so they are fetched with a limit of their own
+    pub timeline_size: u32,
+    // Opens a file at a line, e.g. `nvim +{line} {file}`. $VISUAL or $EDITOR otherwise
+    pub open_command: Option<String>,
+    pub layout: LayoutConfig,
+    pub prefixes: PrefixesConfig,
+    pub keys: Vec<KeyBinding>,
+    pub theme: Theme,
+    theme_name: String,
+    colors: Vec<(String, StyleSpec)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct LayoutConfig {
+    pub tree_percentage: u16,
+    pub hunk_percentage: u16,
+}
+
+#[derive(Debug, Clone)]
+pub struct PrefixesConfig {
+    pub collapsed: String,
+    pub expanded: String,
+    pub comment: String,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            queries_dir: None,
+            page_size: 15,
+            pr_list_size: 5,
+            timeline_size: 100,
+            open_command: None,
+            layout: LayoutConfig { tree_percentage: 33, hunk_percentage: 50 },
+            prefixes: PrefixesConfig {
+                collapsed: "▶".to_string(),
+                expanded: "▼".to_string(),
+                comment: "-".to_string(),
+            },
+            keys: vec![],
+            theme: Theme::new("dark", &[], no_color()),
+            theme_name: "dark".to_string(),
+            colors: vec![],
+        }
+    }
+}
+
+impl Config {
+    pub fn load(repo: &str) -> Result<Self, Error> {
+        let config_file = xdg::BaseDirectories::with_prefix("ghterm")
+            .ok()
+            .and_then(|dirs| dirs.find_config_file(CONFIG_FILE));
+
+        match config_file {
+            Some(path) => Config::load_from(&path, repo),
+            None => Ok(Config::default()),
+        }
+    }
+
+    pub fn load_from(path: &Path, repo: &str) -> Result<Self, Error> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| Error::Config(format!("{}: {}", path.display(), e)))?;
+        Config::parse(&text, repo)
+            .map_err(|e| Error::Config(format!("{}: {}", path.display(), e)))
+    }
+
+    // Reads the top level settings first and then the ones from `[repos."owner/name"]`
+    // matching the current repository, so the latter take precedence.
+    fn parse(text: &str, repo: &str) -> Result<Self, String> {
+        let table = text.parse::<Value>().map_err(|e| e.to_string())?;
+        let table = expect_table(&table, "")?;
+
+        let mut config = Config::default();
+        config.apply(table, "")?;
+
+        if let Some(repos) = table.get("repos") {
+            for (name, overrides) in expect_table(repos, "repos")? {
+                let path = format!("repos.\"{}\"", name);
+                let overrides = expect_table(overrides, &path)?;
+                // GitHub ignores the case of the names
+                if name.eq_ignore_ascii_case(repo) {
+                    config.apply(overrides, &path)?;
+                } else {
+                    // Validate the overrides of other repositories as well, so mistakes
+                    // don't go unnoticed until that repository is opened
+                    Config::default().apply(overrides, &path)?;
+                }
+            }
+        }
+
+        config.theme = Theme::new(&config.theme_name, &config.colors, no_color());
+        Ok(config)
+    }
+
+    fn apply(&mut self, table: &Table, path: &str) -> Result<(), String> {
+        for (key, value) in table {
+            let key_path = join(path, key);
+            match key.as_str() {
+                "queries_dir" => self.queries_dir = Some(PathBuf::from(expect_string(value, &key_path)?)),
+                "page_size" => self.page_size = expect_int(value, &key_path, 1, 100)? as u32,
+                "pr_list_size" => self.pr_list_size = expect_int(value, &key_path, 1, 100)? as u32,
+                "timeline_size" => self.timeline_size = expect_int(value, &key_path, 1, 100)? as u32,
+                "open_command" => {
+                    let command = expect_string(value, &key_path)?;
+                    if !command.contains("{file}") {
+                        return Err(format!("`{}` must contain {{file}}", key_path));
+                    }
+                    self.open_command = Some(command);
+                },
+                "layout" => self.layout.apply(expect_table(value, &key_path)?, &key_path)?,
+                "prefixes" => self.prefixes.apply(expect_table(value, &key_path)?, &key_path)?,
+                "keys" => self.apply_keys(expect_table(value, &key_path)?, &key_path)?,
+                "theme" => {
+                    let name = expect_string(value, &key_path)?;
+                    if !theme::THEME_NAMES.contains(&name.as_str()) {
+                        return Err(format!("`{}` must be one of: {}", key_path, theme::THEME_NAMES.join(", ")));
+                    }
+                    self.theme_name = name;
+                },
+                "colors" => self.apply_colors(expect_table(value, &key_path)?, &key_path)?,
+                "repos" if path.is_empty() => (),
+                _ => return Err(unknown_key(&key_path)),
+            }
+        }
+        Ok(())
+    }
+
+    fn apply_colors(&mut self, table: &Table, path: &str) -> Result<(), String> {
+        for (name, value) in table {
+            let key_path = join(path, name);
+            if !Theme::has_style(name) {
+                return Err(unknown_key(&key_path));
+            }
+            let spec = StyleSpec::parse(value).map_err(|e| format!("`{}`: {}", key_path, e))?;
+            self.colors.push((name.to_string(), spec));
+        }
+        Ok(())
+    }
+
+    fn apply_keys(&mut self, table: &Table, path: &str) -> Result<(), String> {
+        for (context_name, bindings) in table {
+            let context_path = join(path, context_name);
+            let context = KeymapContext::from_name(context_name).ok_or_else(|| unknown_key(&context_path))?;
+            for (action_name, sequences) in expect_table(bindings, &context_path)? {
+                let action_path = format!("{}.{}", context_path, action_name);
+                let action = Action::from_name(action_name).ok_or_else(|| unknown_key(&action_path))?;
+                let sequences = match sequences {
+                    Value::String(s) => vec![s.as_str()],
+                    Value::Array(array) => array.iter()
+                        .map(|v| v.as_str().ok_or_else(|| format!("`{}` must be a list of key sequences", action_path)))
+                        .collect::<Result<Vec<_>, _>>()?,
+                    _ => return Err(format!("`{}` must be a key sequence or a list of them", action_path)),
+                };
+                let sequences = sequences.into_iter()
+                    .map(|s| keymap::parse_sequence(s).map_err(|e| format!("`{}`: {}", action_path, e)))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                self.keys.push(KeyBinding { context, action, sequences });
+            }
+        }
+        Ok(())
+    }
+}
+
+impl LayoutConfig {
+    fn apply(&mut self, table: &Table, path: &str) -> Result<(), String> {
+        for (key, value) in table {
+            let key_path = join(path, key);
+            match key.as_str() {
+                "tree_percentage" => self.tree_percentage = expect_int(value, &key_path, 10, 90)? as u16,
+                "hunk_percentage" => self.hunk_percentage = expect_int(value, &key_path, 10, 90)? as u16,
+                _ => return Err(unknown_key(&key_path)),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl PrefixesConfig {
+    fn apply(&mut self, table: &Table, path: &str) -> Result<(), String> {
+        for (key, value) in table {
+            let key_path = join(path, key);
+            let symbol = expect_string(value, &key_path)?;
+            match key.as_str() {
+                "collapsed" => self.collapsed = symbol,
+                "expanded" => self.expanded = symbol,
+                "comment" => self.comment = symbol,
+                _ => return Err(unknown_key(&key_path)),
+            }
+        }
+        Ok(())
+    }
+}
+
+// See https://no-color.org
+fn no_color() -> bool {
+    std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty())
+}
+
+fn join(path: &str, key: &str) -> String {
+    if path.is_empty() { key.to_string() } else { format!("{}.{}", path, key) }
+}
+
+fn unknown_key(path: &str) -> String {
+    format!("unknown key `{}`", path)
+}
+
+fn expect_table<'a>(value: &'a Value, path: &str) -> Result<&'a Table, String> {
+    value.as_table().ok_or_else(|| format!("`{}` must be a table", path))
+}
+
+fn expect_string(value: &Value, path: &str) -> Result<String, String> {
+    value.as_str()
+        .map(|s| s.to_string())
+        .ok_or_else(|| format!("`{}` must be a string", path))
+}
+
+fn expect_int(value: &Value, path: &str, min: i64, max: i64) -> Result<i64, String> {
+    value.as_integer()
+        .filter(|v| (min..=max).contains(v))
+        .ok_or_else(|| format!("`{}` must be an integer between {} and {}", path, min, max))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn repo_overrides() {
+        let text = r#"
+            page_size = 30
+            [layout]
+            tree_percentage = 40
+            [repos."owner/repo".layout]
+            tree_percentage = 50
+        "#;
+
+        let config = Config::parse(text, "owner/repo").unwrap();
+        assert_eq!(config.page_size, 30);
+        assert_eq!(config.layout.tree_percentage, 50);
+
+        let config = Config::parse(text, "owner/other").unwrap();
+        assert_eq!(config.layout.tree_percentage, 40);
+    }
+
+    #[test]
+    fn example_is_valid() {
+        Config::parse(include_str!("../data/config.example.toml"), "owner/name").unwrap();
+    }
+
+    #[test]
+    fn errors_point_at_key() {
+        let error = Config::parse("[layout]\ntree_width = 3", "a/b").unwrap_err();
+        assert_eq!(error, "unknown key `layout.tree_width`");
+
+        let error = Config::parse("[repos.\"a/c\"]\npage_size = \"big\"", "a/b").unwrap_err();
+        assert_eq!(error, "`repos.\"a/c\".page_size` must be an integer between 1 and 100");
+
+        let error = Config::parse("[keys.conversation]\nnext-item = \"<Hyper-j>\"", "a/b").unwrap_err();
+        assert_eq!(error, "`keys.conversation.next-item`: unknown key <Hyper-j>");
+
+        let error = Config::parse("open_command = \"nvim +{line}\"", "a/b").unwrap_err();
+        assert_eq!(error, "`open_command` must contain {file}");
+    }
+}
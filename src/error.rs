@@ -1,13 +1,19 @@
 use std::fmt::Display;
 use std::convert::From;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Error {
     Other(String),
     GhNotInstalled,
     RefusedToAuthenticate,
     NotARepo(String),
     PrDoesntExist(String, u32),
+    NotCached(String),
+    Offline(String),
+    Config(String),
+    Api(String),
+    MissingField(String),
+    MalformedResponse(String),
 }
 
 impl Display for Error {
@@ -18,6 +24,12 @@ impl Display for Error {
             Error::RefusedToAuthenticate => write!(f, "You need to be authenticated to GitHub to use ghterm"),
             Error::NotARepo(repo) => write!(f, "{} is not a GitHub repository", repo),
             Error::PrDoesntExist(repo, pr) => write!(f, "Pull request #{} in {} doesn't exist", pr, repo),
+            Error::NotCached(what) => write!(f, "No cached {} is available in offline mode", what),
+            Error::Offline(what) => write!(f, "Can't {} in offline mode", what),
+            Error::Config(msg) => write!(f, "Invalid configuration in {}", msg),
+            Error::Api(msg) => write!(f, "GitHub returned an error: {}", msg),
+            Error::MissingField(field) => write!(f, "GitHub response is missing the `{}` field", field),
+            Error::MalformedResponse(msg) => write!(f, "GitHub response is malformed: {}", msg),
         }
     }
 }
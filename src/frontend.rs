@@ -4,4 +4,17 @@ pub mod screen;
 pub mod main_screen;
 pub mod main_screen_handler;
 pub mod conversation_tab;
+pub mod files_tab;
+pub mod keymap;
+pub mod help_overlay;
+pub mod reaction_picker;
+pub mod markdown;
+pub mod text_editor;
+pub mod confirm_prompt;
+pub mod theme;
+pub mod status_line;
+pub mod loading;
+pub mod scroll;
+pub mod tree;
+pub mod time;
 mod util;
@@ -0,0 +1,35 @@
+use super::theme::Theme;
+use super::util::centered_rect;
+
+use tui::{
+    buffer::Buffer,
+    layout::Rect,
+    text::Span,
+    widgets::{Block, Borders, Clear, Paragraph, Widget, Wrap},
+};
+
+// Yes or no question shown over the screen
+pub struct ConfirmPrompt<'a> {
+    question: &'a str,
+    theme: &'a Theme,
+}
+
+impl<'a> ConfirmPrompt<'a> {
+    pub fn new(question: &'a str, theme: &'a Theme) -> Self {
+        ConfirmPrompt { question, theme }
+    }
+}
+
+impl<'a> Widget for ConfirmPrompt<'a> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let popup = centered_rect(area, (self.question.chars().count() as u16 + 4).max(40), 4);
+        Clear.render(popup, buf);
+        Paragraph::new(vec![
+                Span::styled(self.question, self.theme.text).into(),
+                Span::styled("<Enter> to confirm, <Esc> to cancel", self.theme.dimmed).into(),
+            ])
+            .wrap(Wrap { trim: true })
+            .block(Block::default().borders(Borders::ALL).border_style(self.theme.title))
+            .render(popup, buf);
+    }
+}
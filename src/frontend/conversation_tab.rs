@@ -1,30 +1,43 @@
-mod conversation_tree;
 mod conversation_tree_content;
 mod conversation_tree_state;
 mod conversation_draw;
 
 use std::rc::Rc;
 use std::sync::mpsc;
-use std::cell::RefCell;
-use std::ops::DerefMut;
+use std::cell::{Cell, RefCell};
 
-use crate::backend::pr::PrConversation;
-use crate::backend::diff::ChangeList;
+use crate::backend::pr::{CommentKind, CommentTarget, PrConversation, PrCommentEdit, ReactionContent, References};
+use crate::backend::editor::draft_context;
+use crate::backend::diff::{ChangeList, DiffSide};
+use crate::config::Config;
+use super::loading::{LoadState, Placeholder};
+use super::scroll::{ScrollState, ScrollableText};
 
 use super::screen::InteractableScreen;
 use super::main_screen_handler::MainScreenEvent;
 
-use conversation_tree::{ConversationTree, Prefixes};
+pub use conversation_draw::diff_text;
+use conversation_draw::history_text;
 use conversation_tree_content::ConversationTreeContent;
-use conversation_tree_state::ConversationTreeState;
+use conversation_tree_state::{ConversationTreeState, ConversationNode};
+use super::tree::{Focus, Prefixes, TreeView};
 
+use super::keymap::{Action, KeymapContext};
+use super::reaction_picker::ReactionPicker;
+use super::text_editor::{TextEditor, TextEditorView};
+use super::confirm_prompt::ConfirmPrompt;
+use super::markdown::quote;
+
+use chrono::Local;
 use termion::event::Key;
 
 use tui::{
     backend::Backend,
-    layout::{Rect, Layout, Alignment, Direction, Constraint},
-    widgets::{Block, Borders, BorderType},
-    style::{Style, Modifier},
+    buffer::Buffer,
+    text::Span,
+    layout::{Rect, Layout, Direction, Constraint},
+    widgets::{Block, Borders},
+    style::Style,
     Frame,
 };
 
@@ -32,71 +45,606 @@ pub struct ConversationTab {
     screen_event_sender: mpsc::Sender<MainScreenEvent>,
     conversation_tree: RefCell<Option<ConversationTreeState>>,
     changelist: Option<Rc<ChangeList>>,
+    conversation_state: LoadState,
+    diff_state: LoadState,
+    focus: Focus,
+    show_events: bool,
+    absolute_times: bool,
+    history: Option<EditHistory>,
+    // Selected reaction while the picker is open
+    reaction_picker: Option<usize>,
+    draft: Option<Draft>,
+    // Comment waiting for the deletion to be confirmed
+    deleted_comment: Option<(String, CommentKind)>,
+    references: Rc<References>,
+    content_scroll: Cell<ScrollState>,
+    config: Rc<Config>,
+}
+
+// Edit history of a comment, shown in place of the content until the selection changes
+struct EditHistory {
+    comment_id: String,
+    state: LoadState,
+    edits: Vec<PrCommentEdit>,
+}
+
+// Comment being written, with what it replies to for the external editor
+struct Draft {
+    target: CommentTarget,
+    context: String,
+    editor: TextEditor,
 }
 
 impl ConversationTab {
-    pub fn new (screen_event_sender: mpsc::Sender<MainScreenEvent>) -> Self {
-        ConversationTab { screen_event_sender, conversation_tree: RefCell::new(None), changelist: None }
+    pub fn new (screen_event_sender: mpsc::Sender<MainScreenEvent>, config: Rc<Config>) -> Self {
+        ConversationTab {
+            screen_event_sender,
+            conversation_tree: RefCell::new(None),
+            changelist: None,
+            conversation_state: LoadState::loading(),
+            diff_state: LoadState::loading(),
+            focus: Focus::Tree,
+            show_events: false,
+            absolute_times: false,
+            history: None,
+            reaction_picker: None,
+            draft: None,
+            deleted_comment: None,
+            references: Rc::new(References::default()),
+            content_scroll: Cell::new(ScrollState::default()),
+            config,
+        }
     }
 
+    // The tree keeps its selection when the conversation is fetched again
     pub fn set_conversation(&mut self, conversation: PrConversation) {
-        self.conversation_tree = RefCell::new(Some(ConversationTreeState::new(conversation)));
+        let mut state = ConversationTreeState::new(conversation, self.show_events);
+        if let Some(old_state) = self.conversation_tree.get_mut() {
+            state.tree.restore_state(&old_state.tree, |node| Some(*node));
+        }
+        self.conversation_tree = RefCell::new(Some(state));
+        self.conversation_state = LoadState::Loaded;
+        self.content_scroll.set(ScrollState::default());
+    }
+
+    pub fn set_edits(&mut self, comment_id: &str, edits: Result<Vec<PrCommentEdit>, String>) {
+        if let Some(history) = self.history.as_mut().filter(|h| h.comment_id == comment_id) {
+            match edits {
+                Ok(edits) => {
+                    history.edits = edits;
+                    history.state = LoadState::Loaded;
+                },
+                Err(error) => history.state = LoadState::Failed(error),
+            }
+        }
+    }
+
+    pub fn set_reaction(&mut self, comment_id: &str, content: ReactionContent, is_added: bool) {
+        let mut state = self.conversation_tree.borrow_mut();
+        if let Some(comment) = state.as_mut().and_then(|s| s.conversation.comment_mut(comment_id)) {
+            comment.set_reaction(content, is_added);
+        }
+    }
+
+    pub fn comment_sent(&mut self, target: &CommentTarget, body: String) {
+        if let CommentTarget::Edit { comment_id, .. } = target {
+            if let Some(comment) = self.conversation_tree.get_mut().as_mut().and_then(|s| s.conversation.comment_mut(comment_id)) {
+                comment.body = body;
+                comment.last_edited_at = Some(Local::now());
+            }
+        }
+        if self.draft.as_ref().is_some_and(|d| d.target == *target) {
+            self.draft = None;
+        }
+    }
+
+    // The text is kept in the editor, so it can be sent again
+    pub fn comment_not_sent(&mut self, target: &CommentTarget, body: String) {
+        let editor = (!body.is_empty()).then(|| self.new_editor(&body));
+        match (self.draft.as_mut(), editor) {
+            (Some(draft), editor) if draft.target == *target => {
+                if let Some(editor) = editor.filter(|_| draft.editor.text() != body) {
+                    draft.editor = editor;
+                }
+                draft.editor.is_sending = false;
+            },
+            (None, Some(editor)) => {
+                self.draft = Some(Draft { target: target.clone(), context: String::new(), editor });
+            },
+            _ => (),
+        }
+    }
+
+    pub fn comment_deleted(&mut self, comment_id: &str) {
+        if let Some(state) = self.conversation_tree.get_mut() {
+            state.remove_comment(comment_id);
+        }
+        if self.history.as_ref().is_some_and(|h| h.comment_id == comment_id) {
+            self.history = None;
+        }
+        self.content_scroll.set(ScrollState::default());
+    }
+
+    pub fn keymap_context(&self) -> KeymapContext {
+        if self.draft.is_some() {
+            KeymapContext::TextInput
+        } else if self.reaction_picker.is_some() || self.deleted_comment.is_some() {
+            KeymapContext::Popup
+        } else {
+            KeymapContext::Conversation
+        }
+    }
+
+    pub fn process_text_input(&mut self, key: Key) {
+        if let Some(draft) = self.draft.as_mut() {
+            draft.editor.process_text_input(key);
+        }
+    }
+
+    pub fn process_paste(&mut self, text: &str) {
+        if let Some(draft) = self.draft.as_mut() {
+            draft.editor.process_paste(text);
+        }
+    }
+
+    pub fn show_timeline(&mut self) {
+        self.show_events = true;
+        if let Some(state) = self.conversation_tree.get_mut() {
+            state.set_show_events(true);
+        }
+    }
+
+    pub fn set_references(&mut self, references: References) {
+        self.references = Rc::new(references);
     }
 
     pub fn set_changelist(&mut self, changelist: Rc<ChangeList>) {
         self.changelist = Some(Rc::clone(&changelist));
+        self.diff_state = LoadState::Loaded;
+    }
+
+    // Data that is already shown is kept when loading it again fails or starts over
+    pub fn start_loading(&mut self) {
+        for state in [&mut self.conversation_state, &mut self.diff_state] {
+            if !state.is_loaded() {
+                *state = LoadState::loading();
+            }
+        }
+    }
+
+    pub fn conversation_failed(&mut self, error: &str) {
+        if !self.conversation_state.is_loaded() {
+            self.conversation_state = LoadState::Failed(error.to_string());
+        }
+    }
+
+    pub fn diff_failed(&mut self, error: &str) {
+        if !self.diff_state.is_loaded() {
+            self.diff_state = LoadState::Failed(error.to_string());
+        }
     }
 
     pub fn draw<B: Backend>(&self, frame: &mut Frame<B>, rect: Rect) {
+        let layout_config = &self.config.layout;
         let layout = Layout::default()
             .direction(Direction::Horizontal)
             .constraints(vec![
-                Constraint::Ratio(1, 3),
-                Constraint::Ratio(2, 3)
+                Constraint::Percentage(layout_config.tree_percentage),
+                Constraint::Percentage(100 - layout_config.tree_percentage)
             ])
             .split(rect);
 
-        let tree_widget = ConversationTree::default()
-            .block(Block::default().borders(Borders::all()))
-            .prefixes(Prefixes::new("▶", "▼", "-"))
-            .highlighted_style(Style::default().add_modifier(Modifier::BOLD));
-        frame.render_stateful_widget(tree_widget, layout[0], self.conversation_tree.borrow_mut().deref_mut());
+        let prefixes = &self.config.prefixes;
+        let theme = &self.config.theme;
+        let is_empty = self.conversation_tree.borrow().as_ref().map(|t| t.is_empty());
+        let placeholder = match is_empty {
+            Some(true) => Some(Placeholder::empty("No comments yet", theme)),
+            Some(false) => None,
+            None => self.conversation_state.placeholder("the conversation", theme),
+        };
+        if let Some(placeholder) = placeholder {
+            let block = Block::default().borders(Borders::all()).border_style(theme.border);
+            frame.render_widget(placeholder.block(block), layout[0]);
+            frame.render_widget(Block::default().borders(Borders::all()).border_style(theme.border), layout[1]);
+            return;
+        }
+
+        let border_style = |focus| if self.focus == focus { theme.title } else { theme.border };
+        if let Some(state) = self.conversation_tree.borrow_mut().as_mut() {
+            let conversation = &state.conversation;
+            let title = match (self.show_events, conversation.hidden_events) {
+                (false, _) => Span::raw(""),
+                (true, 0) => Span::styled("Timeline", theme.title),
+                (true, hidden) => Span::styled(format!("Timeline, {} earlier events not shown", hidden), theme.title),
+            };
+            let draw_label = |node: &ConversationNode, area: Rect, buffer: &mut Buffer, style: Style| {
+                node.tree_draw(conversation).draw(area, buffer, theme, style);
+            };
+            let tree_widget = TreeView::new(&draw_label)
+                .block(Block::default().borders(Borders::all()).border_style(border_style(Focus::Tree)).title(title))
+                .prefixes(Prefixes::new(&prefixes.collapsed, &prefixes.expanded, &prefixes.comment))
+                .style(theme.text)
+                .highlighted_style(theme.selection);
+            frame.render_stateful_widget(tree_widget, layout[0], &mut state.tree);
+        }
+
+        if let Some(history) = self.history.as_ref() {
+            self.draw_history(frame, layout[1], history);
+        } else {
+            self.draw_content(frame, layout[1]);
+        }
 
+        let state = self.conversation_tree.borrow();
+        let comment = state.as_ref().and_then(|s| s.selected_comment());
+        if let (Some(selected), Some(comment)) = (self.reaction_picker, comment) {
+            frame.render_widget(ReactionPicker::new(comment, selected, theme), rect);
+        }
+        if let Some(draft) = self.draft.as_ref() {
+            let title = match draft.target {
+                CommentTarget::Edit { kind: CommentKind::Review, .. } => "Edit review",
+                CommentTarget::Edit { .. } => "Edit comment",
+                CommentTarget::Reply { .. } => "Reply",
+                CommentTarget::NewComment { .. } => "Comment",
+            };
+            frame.render_widget(TextEditorView::new(&draft.editor, title, theme), rect);
+        }
+        if self.deleted_comment.is_some() {
+            frame.render_widget(ConfirmPrompt::new("Delete this comment?", theme), rect);
+        }
+    }
+
+    fn draw_content<B: Backend>(&self, frame: &mut Frame<B>, area: Rect) {
+        let theme = &self.config.theme;
         let state = self.conversation_tree.borrow();
         let content_widget = ConversationTreeContent::default()
-            .block(Block::default().borders(Borders::all()))
+            .block(Block::default().borders(Borders::all()).border_style(theme.border))
+            .style(theme.text)
+            .theme(theme)
             .state(state.as_ref())
+            .layout(&self.config.layout)
+            .diff_state(&self.diff_state)
+            .scroll(&self.content_scroll)
+            .focused(self.focus == Focus::Content)
+            .absolute_times(self.absolute_times)
             .changelist(self.changelist.as_ref().map(|rc| Rc::clone(rc)));
-        frame.render_widget(content_widget, layout[1]);
+        frame.render_widget(content_widget, area);
     }
-}
 
-impl InteractableScreen for ConversationTab {
-    fn validate_input(&self, input: Key) -> bool {
-        self.conversation_tree.borrow().is_some() &&
-            (input == Key::Char('j') || input == Key::Char('k') || input == Key::Char('h') || input == Key::Char('l') ||
-             input == Key::Char(' '))
+    fn draw_history<B: Backend>(&self, frame: &mut Frame<B>, area: Rect, history: &EditHistory) {
+        let theme = &self.config.theme;
+        let border_style = if self.focus == Focus::Content { theme.title } else { theme.border };
+        let block = Block::default()
+            .borders(Borders::all())
+            .border_style(border_style)
+            .title(Span::styled("Edit history", theme.title));
+        if let Some(placeholder) = history.state.placeholder("the edit history", theme) {
+            frame.render_widget(placeholder.block(block), area);
+            return;
+        }
+
+        let text = history_text(&history.edits, theme, self.absolute_times);
+        let history_widget = ScrollableText::new(text, &self.content_scroll)
+            .block(block)
+            .style(theme.text)
+            .scrollbar_style(border_style);
+        frame.render_widget(history_widget, area);
+    }
+
+    fn toggle_reaction(&mut self, content: ReactionContent) {
+        let state = self.conversation_tree.borrow();
+        if let Some(comment) = state.as_ref().and_then(|s| s.selected_comment()) {
+            let event = MainScreenEvent::SetReaction { comment_id: comment.id.clone(), content, is_added: !comment.has_reacted(content) };
+            let _ = self.screen_event_sender.send(event);
+        }
+    }
+
+    // Input goes to the reaction picker while it's open
+    fn process_picker_input(&mut self, input: Action, count: usize, selected: usize) {
+        let reactions = ReactionContent::ALL.len();
+        match input {
+            Action::NextItem => self.reaction_picker = Some((selected + count) % reactions),
+            Action::PreviousItem => self.reaction_picker = Some((selected + reactions - count % reactions) % reactions),
+            Action::Select => {
+                self.reaction_picker = None;
+                self.toggle_reaction(ReactionContent::ALL[selected]);
+            },
+            Action::Back => self.reaction_picker = None,
+            _ => (),
+        }
     }
 
-    fn process_input(&mut self, input: Key) {
-        let vertical_offset = match input {
-            Key::Char('j') => 1,
-            Key::Char('k') => -1,
-            _ => 0
+    // The editor is prefilled with the current body of the comment
+    fn start_editing(&mut self) {
+        let context = self.selected_context(false);
+        let state = self.conversation_tree.borrow();
+        if let Some(comment) = state.as_ref().and_then(|s| s.selected_comment()) {
+            let target = CommentTarget::Edit { comment_id: comment.id.clone(), kind: comment.kind };
+            self.draft = Some(Draft { target, context, editor: self.new_editor(&comment.body) });
+        }
+    }
+
+    // Threads get the reply, other comments are answered in the conversation
+    fn reply_target(&self) -> Option<CommentTarget> {
+        let state = self.conversation_tree.borrow();
+        let state = state.as_ref()?;
+        state.selected_comment()?;
+        match state.selected_thread() {
+            Some(thread) => Some(CommentTarget::Reply { thread_id: thread.id.clone() }),
+            None => state.conversation.id.clone().map(|subject_id| CommentTarget::NewComment { subject_id }),
+        }
+    }
+
+    // Hunk of the selected thread and, if asked, the selected comment
+    fn selected_context(&self, with_comment: bool) -> String {
+        let state = self.conversation_tree.borrow();
+        let state = match state.as_ref() {
+            Some(state) => state,
+            None => return String::new(),
+        };
+        let hunk = state.selected_thread()
+            .and_then(|thread| thread.code_range.as_ref())
+            .zip(self.changelist.as_ref())
+            .map_or("", |(code_range, changelist)| changelist.get_hunk(code_range));
+        draft_context(state.selected_comment().filter(|_| with_comment), hunk)
+    }
+
+    fn start_reply(&mut self, in_editor: bool) {
+        if let Some(target) = self.reply_target() {
+            let context = self.selected_context(true);
+            if in_editor {
+                let _ = self.screen_event_sender.send(MainScreenEvent::OpenEditor { target, text: String::new(), context });
+            } else {
+                self.draft = Some(Draft { target, context, editor: self.new_editor("") });
+            }
+        }
+    }
+
+    // Threads quote their first comment
+    fn start_quote_reply(&mut self) {
+        let body = self.conversation_tree.borrow().as_ref()
+            .and_then(|state| state.selected_comment())
+            .map(|comment| comment.body.clone());
+        if let Some((target, body)) = self.reply_target().zip(body) {
+            let context = self.selected_context(true);
+            self.draft = Some(Draft { target, context, editor: self.new_editor(&quote(&body)) });
+        }
+    }
+
+    // Right side lines are in the commit the thread was started on. Left side ones are in the base
+    // and are moved onto the head with the diff of the pull request
+    fn open_selected_file(&self) {
+        let state = self.conversation_tree.borrow();
+        let state = match state.as_ref() {
+            Some(state) => state,
+            None => return,
+        };
+        let (thread, code_range) = match state.selected_thread().and_then(|t| t.code_range.as_ref().map(|r| (t, r))) {
+            Some(selected) => selected,
+            None => return,
         };
-        let horizontal_offset = match input {
-            Key::Char('h') => -1, 
-            Key::Char('l') => 1,
-            _ => 0,
+        let head_sha = state.conversation.head_sha.clone();
+        let file_name = code_range.file_name.clone();
+        let (positions, fallback_line) = match code_range.side {
+            DiffSide::Right => {
+                let original = thread.original_commit.clone().map(|commit| (commit, code_range.start_line));
+                let head = head_sha.zip(thread.line);
+                (original.into_iter().chain(head).collect(), thread.line.unwrap_or(code_range.start_line))
+            },
+            DiffSide::Left => {
+                let line = self.changelist.as_ref()
+                    .map_or(code_range.start_line, |changelist| changelist.map_line(&file_name, code_range.start_line));
+                (head_sha.map(|commit| (commit, line)).into_iter().collect(), line)
+            },
         };
-        
+        let _ = self.screen_event_sender.send(MainScreenEvent::OpenFile { file_name, positions, fallback_line });
+    }
+
+    fn send_selected_url(&self, event: fn(String) -> MainScreenEvent) {
+        let url = self.conversation_tree.borrow().as_ref()
+            .and_then(|state| state.selected_comment())
+            .map(|comment| comment.url.clone());
+        if let Some(url) = url {
+            let _ = self.screen_event_sender.send(event(url));
+        }
+    }
+
+    // People in the conversation can be mentioned even if they aren't collaborators
+    fn new_editor(&self, text: &str) -> TextEditor {
+        let mut references = References::clone(&self.references);
+        if let Some(state) = self.conversation_tree.borrow().as_ref() {
+            for participant in state.conversation.participants() {
+                if !references.users.iter().any(|user| user == participant) {
+                    references.users.push(participant.to_string());
+                }
+            }
+        }
+        TextEditor::new(text).references(references)
+    }
+
+    fn process_draft_input(&mut self, input: Action, count: usize) {
+        if let Some(draft) = self.draft.as_mut().filter(|d| d.editor.validate_input(input)) {
+            draft.editor.process_input(input, count);
+            return;
+        }
+        match (input, self.draft.as_mut()) {
+            (Action::Submit, Some(draft)) => {
+                draft.editor.is_sending = true;
+                let event = MainScreenEvent::SendComment { target: draft.target.clone(), body: draft.editor.text() };
+                let _ = self.screen_event_sender.send(event);
+            },
+            (Action::OpenEditor, Some(draft)) => {
+                draft.editor.is_sending = true;
+                let event = MainScreenEvent::OpenEditor { target: draft.target.clone(), text: draft.editor.text(), context: draft.context.clone() };
+                let _ = self.screen_event_sender.send(event);
+            },
+            (Action::Back, _) => self.draft = None,
+            _ => (),
+        }
+    }
+
+    fn process_deletion_input(&mut self, input: Action) {
+        match input {
+            Action::Select => if let Some((comment_id, kind)) = self.deleted_comment.take() {
+                let _ = self.screen_event_sender.send(MainScreenEvent::DeleteComment { comment_id, kind });
+            },
+            Action::Back => self.deleted_comment = None,
+            _ => (),
+        }
+    }
+
+    // Closes the history if it's open, otherwise asks for the history of the selected comment
+    fn toggle_history(&mut self) {
+        if self.history.take().is_some() {
+            return;
+        }
+
+        let comment_id = self.conversation_tree.borrow().as_ref()
+            .and_then(|state| state.selected_comment())
+            .map(|comment| comment.id.clone());
+        if let Some(comment_id) = comment_id {
+            self.history = Some(EditHistory { comment_id: comment_id.clone(), state: LoadState::loading(), edits: vec![] });
+            let _ = self.screen_event_sender.send(MainScreenEvent::LoadEdits(comment_id));
+        }
+    }
+}
+
+impl InteractableScreen for ConversationTab {
+    fn validate_input(&self, input: Action) -> bool {
+        if let Some(draft) = self.draft.as_ref() {
+            return draft.editor.validate_input(input) || input == Action::Back ||
+                (matches!(input, Action::Submit | Action::OpenEditor) && !draft.editor.is_sending);
+        }
+        if self.deleted_comment.is_some() {
+            return matches!(input, Action::Select | Action::Back);
+        }
+        if self.reaction_picker.is_some() {
+            return matches!(input, Action::NextItem | Action::PreviousItem | Action::Select | Action::Back);
+        }
+
+        if matches!(input, Action::Reply | Action::ReplyInEditor | Action::QuoteReply) {
+            return self.reply_target().is_some();
+        }
+
+        let state = self.conversation_tree.borrow();
+        match input {
+            Action::React | Action::ThumbsUp => return state.as_ref().and_then(|s| s.selected_comment()).is_some(),
+            Action::OpenFile => return state.as_ref()
+                .and_then(|s| s.selected_thread())
+                .is_some_and(|t| t.code_range.is_some()),
+            Action::CopyLink | Action::OpenInBrowser => return state.as_ref()
+                .and_then(|s| s.selected_comment())
+                .is_some_and(|c| !c.url.is_empty()),
+            // Only the viewer's own comments can be changed
+            Action::EditComment => return state.as_ref()
+                .and_then(|s| s.selected_comment())
+                .is_some_and(|c| c.viewer_did_author),
+            // Submitted reviews can't be deleted, only the pending ones which aren't in the conversation
+            Action::DeleteComment => return state.as_ref()
+                .and_then(|s| s.selected_comment())
+                .is_some_and(|c| c.viewer_did_author && c.kind != CommentKind::Review),
+            // Events can be shown even if there are no comments
+            Action::ToggleTimeline | Action::ToggleTimeFormat => return state.is_some(),
+            Action::ShowHistory => return self.history.is_some() ||
+                state.as_ref().and_then(|s| s.selected_comment()).is_some_and(|c| c.last_edited_at.is_some()),
+            _ => (),
+        }
+        state.as_ref().is_some_and(|t| !t.is_empty()) &&
+            matches!(input, Action::NextItem | Action::PreviousItem | Action::FirstItem | Action::LastItem |
+                     Action::NextThread | Action::PreviousThread | Action::Expand | Action::Collapse | Action::ToggleExpand |
+                     Action::ExpandAll | Action::CollapseAll |
+                     Action::SwitchPane | Action::ScrollDown | Action::ScrollUp | Action::PageDown | Action::PageUp)
+    }
+
+    fn hides_action(&self, input: Action) -> bool {
+        input == Action::DeleteComment && !self.validate_input(input)
+    }
+
+    fn process_input(&mut self, input: Action, count: usize) {
+        if self.draft.is_some() {
+            self.process_draft_input(input, count);
+            return;
+        }
+        if self.deleted_comment.is_some() {
+            self.process_deletion_input(input);
+            return;
+        }
+        if let Some(selected) = self.reaction_picker {
+            self.process_picker_input(input, count, selected);
+            return;
+        }
+
+        let count_delta = count.min(i32::MAX as usize) as i32;
+        let mut scroll = self.content_scroll.get();
+        match (input, self.focus) {
+            (Action::SwitchPane, Focus::Tree) => self.focus = Focus::Content,
+            (Action::SwitchPane, Focus::Content) => self.focus = Focus::Tree,
+            (Action::ToggleTimeFormat, _) => self.absolute_times = !self.absolute_times,
+            (Action::React, _) => self.reaction_picker = Some(0),
+            (Action::ThumbsUp, _) => self.toggle_reaction(ReactionContent::ThumbsUp),
+            (Action::EditComment, _) => self.start_editing(),
+            (Action::Reply, _) => self.start_reply(false),
+            (Action::ReplyInEditor, _) => self.start_reply(true),
+            (Action::QuoteReply, _) => self.start_quote_reply(),
+            (Action::OpenFile, _) => self.open_selected_file(),
+            (Action::CopyLink, _) => self.send_selected_url(MainScreenEvent::CopyLink),
+            (Action::OpenInBrowser, _) => self.send_selected_url(MainScreenEvent::OpenLink),
+            (Action::DeleteComment, _) => {
+                let state = self.conversation_tree.borrow();
+                self.deleted_comment = state.as_ref()
+                    .and_then(|s| s.selected_comment())
+                    .map(|c| (c.id.clone(), c.kind));
+            },
+            (Action::ShowHistory, _) => {
+                self.toggle_history();
+                scroll = ScrollState::default();
+            },
+            (Action::ToggleTimeline, _) => {
+                self.history = None;
+                self.show_events = !self.show_events;
+                if let Some(state) = self.conversation_tree.get_mut() {
+                    state.set_show_events(self.show_events);
+                }
+                scroll = ScrollState::default();
+            },
+            (Action::ScrollDown, _) | (Action::NextItem, Focus::Content) => scroll.scroll_by(count_delta),
+            (Action::ScrollUp, _) | (Action::PreviousItem, Focus::Content) => scroll.scroll_by(-count_delta),
+            (Action::PageDown, _) => scroll.scroll_pages(count_delta),
+            (Action::PageUp, _) => scroll.scroll_pages(-count_delta),
+            (Action::FirstItem, Focus::Content) => scroll.scroll_to_top(),
+            (Action::LastItem, Focus::Content) => scroll.scroll_to_bottom(),
+            _ => {
+                self.process_tree_input(input, count);
+                return;
+            },
+        }
+        self.content_scroll.set(scroll);
+    }
+}
+
+impl ConversationTab {
+    fn process_tree_input(&mut self, input: Action, count: usize) {
         let mut borrow = self.conversation_tree.borrow_mut();
-        (*borrow).as_mut().map(|t| {
-            if input == Key::Char(' ') {
-                t.toggle_expansion();
-            } else if vertical_offset != 0 {
-                t.move_selection(vertical_offset > 0);
-            } 
-        });
+        if let Some(t) = borrow.as_mut().map(|state| &mut state.tree) {
+            let selected_node = t.selected();
+            match input {
+                Action::NextItem => t.select_next(count),
+                Action::PreviousItem => t.select_previous(count),
+                Action::FirstItem => t.select_first(),
+                Action::LastItem => t.select_last(),
+                Action::NextThread => t.select_matching(true, count, ConversationNode::is_thread),
+                Action::PreviousThread => t.select_matching(false, count, ConversationNode::is_thread),
+                Action::Expand => t.set_expansion(true),
+                Action::Collapse => t.set_expansion(false),
+                Action::ToggleExpand => t.toggle_expansion(),
+                Action::ExpandAll => t.set_expansion_all(true),
+                Action::CollapseAll => t.set_expansion_all(false),
+                _ => (),
+            }
+
+            if t.selected() != selected_node {
+                self.content_scroll.set(ScrollState::default());
+                self.history = None;
+            }
+        }
     }
 }
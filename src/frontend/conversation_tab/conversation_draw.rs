@@ -1,126 +1,243 @@
 use crate::frontend::conversation_tab::ChangeList;
-use crate::frontend::conversation_tab::conversation_tree::Prefixes;
+use crate::frontend::theme::Theme;
+use crate::frontend::time::format_time;
+use crate::frontend::reaction_picker::reaction_counts;
+use crate::frontend::markdown::markdown_text;
+use crate::frontend::loading::LoadState;
+use crate::frontend::scroll::{ScrollState, ScrollableText};
 use crate::backend::pr::*;
+use crate::config::LayoutConfig;
+use chrono::{DateTime, Local};
+use std::cell::Cell;
 use std::rc::Rc;
 
 use tui::{
     layout::{Rect, Layout, Direction, Constraint},
     buffer::Buffer,
-    style::{Style, Modifier},
-    text::{Span, Spans},
+    style::Style,
+    text::{Span, Spans, Text},
     widgets::{Widget, Paragraph, Wrap, Block, Borders},
 };
 
 pub trait TreeDraw {
-    fn draw(&self, area: Rect, buffer: &mut Buffer, prefixes: Prefixes, style: Style, is_expanded: bool);
+    fn draw(&self, area: Rect, buffer: &mut Buffer, theme: &Theme, style: Style);
 }
 
 pub trait ContentDraw {
-    fn draw(&self, area: Rect, buffer: &mut Buffer, style: Style, changelist: &Option<Rc<ChangeList>>);
+    fn draw(&self, area: Rect, buffer: &mut Buffer, context: &ContentContext);
 }
 
-impl TreeDraw for PrReview {
-    fn draw(&self, area: Rect, buffer: &mut Buffer, prefixes: Prefixes, style: Style, is_expanded: bool) {
-        let has_threads = !self.threads.is_empty();
-        let symbol = if has_threads && is_expanded { prefixes.expanded_symbol } else if has_threads { prefixes.collapsed_symbol } else { " " };
+// Everything the content of a tree item needs to be drawn
+pub struct ContentContext<'a> {
+    pub theme: &'a Theme,
+    pub changelist: &'a Option<Rc<ChangeList>>,
+    pub diff_state: &'a LoadState,
+    pub layout: &'a LayoutConfig,
+    pub scroll: &'a Cell<ScrollState>,
+    pub is_focused: bool,
+    pub absolute_times: bool,
+}
+
+impl<'a> ContentContext<'a> {
+    fn time<'b>(&self, timestamp: &DateTime<Local>) -> Span<'b> {
+        Span::styled(format_time(timestamp, self.absolute_times), self.theme.timestamp)
+    }
+
+    // Author, time and whether the comment was edited
+    fn comment_header<'b>(&self, comment: &'b PrComment) -> Vec<Span<'b>> {
+        let mut header = vec![
+            Span::styled(comment.author_name.as_str(), self.theme.author),
+            Span::raw(" "),
+            self.time(&comment.timestamp),
+        ];
+        if comment.last_edited_at.is_some() {
+            header.push(Span::styled(" · edited", self.theme.dimmed));
+        }
+        if !comment.reactions.is_empty() {
+            header.push(Span::raw("  "));
+            header.extend(reaction_counts(comment, self.theme));
+        }
+        header
+    }
 
-        buffer.set_stringn(area.x, area.y, format!("{} {} {}", symbol, self.review_comment.author_name, self.verdict), area.width as usize, style);
+    fn border_style(&self) -> Style {
+        if self.is_focused { self.theme.title } else { self.theme.border }
+    }
+
+    fn render_scrollable(&self, text: Vec<Spans>, area: Rect, buffer: &mut Buffer) {
+        let block = Block::default().borders(Borders::all()).border_style(self.border_style());
+        ScrollableText::new(text, self.scroll)
+            .block(block)
+            .style(self.theme.text)
+            .scrollbar_style(self.border_style())
+            .render(area, buffer);
     }
 }
 
-impl TreeDraw for PrComment {
-    fn draw(&self, area: Rect, buffer: &mut Buffer, _: Prefixes, style: Style, _: bool) {
-        buffer.set_stringn(area.x, area.y, &self.body, area.width as usize, style);
+fn bordered(theme: &Theme) -> Block<'static> {
+    Block::default().borders(Borders::all()).border_style(theme.border)
+}
+
+pub fn diff_text<'a>(hunk: &'a str, theme: &Theme) -> Text<'a> {
+    let lines: Vec<Spans> = hunk.lines()
+        .map(|line| Spans::from(Span::styled(line, theme.diff_line(line))))
+        .collect();
+    Text::from(lines)
+}
+
+impl TreeDraw for PrReview {
+    fn draw(&self, area: Rect, buffer: &mut Buffer, theme: &Theme, style: Style) {
+        let line = Spans::from(vec![
+            Span::styled(&self.review_comment.author_name, theme.author.patch(style)),
+            Span::styled(" ", style),
+            Span::styled(self.verdict.to_string(), theme.verdict(&self.verdict).patch(style)),
+        ]);
+        buffer.set_spans(area.x, area.y, &line, area.width);
     }
 }
 
+impl TreeDraw for PrComment {
+    fn draw(&self, area: Rect, buffer: &mut Buffer, theme: &Theme, style: Style) {
+        let line = Spans::from(vec![
+            Span::styled(&self.author_name, theme.author.patch(style)),
+            Span::styled(" ", style),
+            Span::styled(self.body.lines().next().unwrap_or_default(), style),
+        ]);
+        buffer.set_spans(area.x, area.y, &line, area.width);
+    }
+}
 
+// Threads are labeled by the code they are about, their comments are listed under them
 impl TreeDraw for PrConversationThread {
-    fn draw(&self, area: Rect, buffer: &mut Buffer, prefixes: Prefixes, style: Style, _: bool) {
-        if let Some(comment) = self.comments.get(0) {
-            buffer.set_stringn(area.x + 4, area.y, format!("{} {}", prefixes.comment_prefix, comment.body), (area.width - 4) as usize, style);
+    fn draw(&self, area: Rect, buffer: &mut Buffer, theme: &Theme, style: Style) {
+        let label = match (&self.code_range, self.comments.first()) {
+            (Some(code_range), _) => format!("{}:{}", code_range.file_name, code_range.start_line),
+            (None, Some(comment)) => comment.body.lines().next().unwrap_or_default().to_string(),
+            (None, None) => String::new(),
+        };
+        buffer.set_stringn(area.x, area.y, label, area.width as usize, theme.title.patch(style));
+    }
+}
+
+impl TreeDraw for PrEvent {
+    fn draw(&self, area: Rect, buffer: &mut Buffer, theme: &Theme, style: Style) {
+        let line = Spans::from(vec![
+            Span::styled(&self.actor_name, theme.author.patch(style)),
+            Span::styled(" ", style),
+            Span::styled(event_description(&self.kind), theme.dimmed.patch(style)),
+        ]);
+        buffer.set_spans(area.x, area.y, &line, area.width);
+    }
+}
+
+fn event_description(kind: &PrEventKind) -> String {
+    match kind {
+        PrEventKind::Commit { oid, message } => format!("committed {} {}", oid, message.lines().next().unwrap_or_default()),
+        PrEventKind::ForcePushed { before, after } => format!("force-pushed {} → {}", before, after),
+        PrEventKind::Labeled(label) => format!("added the {} label", label),
+        PrEventKind::Unlabeled(label) => format!("removed the {} label", label),
+        PrEventKind::ReviewRequested(reviewer) => format!("requested a review from {}", reviewer),
+        PrEventKind::Merged { commit, base_branch } => format!("merged {} into {}", commit, base_branch),
+        PrEventKind::Closed => "closed the pull request".to_string(),
+        PrEventKind::Reopened => "reopened the pull request".to_string(),
+    }
+}
+
+impl ContentDraw for PrEvent {
+    fn draw(&self, area: Rect, buffer: &mut Buffer, context: &ContentContext) {
+        let theme = context.theme;
+        let mut text = vec![
+            Spans::from(vec![
+                Span::styled(&self.actor_name, theme.author),
+                Span::raw(" "),
+                context.time(&self.timestamp),
+            ]),
+            Spans::from(Span::raw(event_description(&self.kind))),
+        ];
+        // The headline is already in the description
+        if let PrEventKind::Commit { message, .. } = &self.kind {
+            text.extend(body_lines(message).skip(1));
         }
+        context.render_scrollable(text, area, buffer);
     }
 }
 
 impl ContentDraw for PrReview {
-    fn draw(&self, area: Rect, buffer: &mut Buffer, style: Style, _: &Option<Rc<ChangeList>>) {
-        let text = vec![
-            Spans::from(Span::raw(format!("{} {}", self.review_comment.author_name, self.verdict))),
-            Spans::from(Span::raw(&self.review_comment.body)),
-        ];
-        let paragraph = Paragraph::new(text)
-            .block(Block::default().borders(Borders::all()))
-            .style(style);
-        paragraph.render(area, buffer);
+    fn draw(&self, area: Rect, buffer: &mut Buffer, context: &ContentContext) {
+        let theme = context.theme;
+        let mut header = context.comment_header(&self.review_comment);
+        header.push(Span::raw(" "));
+        header.push(Span::styled(self.verdict.to_string(), theme.verdict(&self.verdict)));
+        let text = vec![Spans::from(header)];
+        let text = text.into_iter().chain(markdown_text(&self.review_comment.body, theme)).collect();
+        context.render_scrollable(text, area, buffer);
     }
 }
 
 impl ContentDraw for PrComment {
-    fn draw(&self, area: Rect, buffer: &mut Buffer, style: Style, _: &Option<Rc<ChangeList>>) {
-        let paragraph = Paragraph::new(self.body.as_str())
-            .style(style)
-            .block(Block::default().borders(Borders::all()));
+    fn draw(&self, area: Rect, buffer: &mut Buffer, context: &ContentContext) {
+        let mut text = vec![Spans::from(context.comment_header(self))];
+        text.extend(markdown_text(&self.body, context.theme));
+        context.render_scrollable(text, area, buffer);
+    }
+}
 
-        paragraph.render(area, buffer);
+// Versions of a comment from the newest one, each under a header with its author and time
+pub fn history_text<'a>(edits: &'a [PrCommentEdit], theme: &Theme, absolute_times: bool) -> Vec<Spans<'a>> {
+    let mut text = vec![];
+    for edit in edits {
+        let action = if edit.is_creation { " created" } else { " edited" };
+        text.push(Spans::from(vec![
+            Span::styled(edit.editor_name.as_str(), theme.author),
+            Span::raw(action),
+            Span::raw(" "),
+            Span::styled(format_time(&edit.timestamp, absolute_times), theme.timestamp),
+        ]));
+        match &edit.body {
+            Some(body) => text.extend(markdown_text(body, theme)),
+            None => text.push(Spans::from(Span::styled("This version was deleted", theme.dimmed))),
+        }
+        text.push(Spans::from(Span::raw("")));
     }
+    text
+}
+
+fn body_lines(body: &str) -> impl Iterator<Item = Spans<'_>> {
+    body.lines().map(|line| Spans::from(Span::raw(line)))
 }
 
 impl ContentDraw for PrConversationThread {
-    fn draw(&self, area: Rect, buffer: &mut Buffer, style: Style, changelist: &Option<Rc<ChangeList>>) {
+    fn draw(&self, area: Rect, buffer: &mut Buffer, context: &ContentContext) {
+        let theme = context.theme;
         let mut threads_text = vec![];
         for comment in self.comments.iter() {
-            threads_text.push(Spans::from(Span::styled(&comment.author_name, Style::default().add_modifier(Modifier::BOLD))));
-            threads_text.push(Spans::from(Span::raw(&comment.body)));
+            threads_text.push(Spans::from(context.comment_header(comment)));
+            threads_text.extend(markdown_text(&comment.body, theme));
             threads_text.push(Spans::from(Span::raw("")));
         }
 
-        let paragraph = Paragraph::new(threads_text)
-            .block(Block::default().borders(Borders::all()));
-
         if let Some(code_range) = &self.code_range {
-            let hunk = changelist.as_ref().map(|c| c.get_hunk(code_range));
-            let hunk_height = hunk.map_or(3, |h| std::cmp::min(h.lines().count() as u16, area.height / 2) + 2);
+            let hunk = context.changelist.as_ref().map(|c| c.get_hunk(code_range));
+            let hunk_height = hunk.map_or(3, |h| std::cmp::min(h.lines().count() as u16, area.height * context.layout.hunk_percentage / 100) + 2);
+            let hunk_height = hunk_height.min(area.height);
             let comments_height = area.height - hunk_height;
             let layout = Layout::default()
                 .direction(Direction::Vertical)
                 .constraints([Constraint::Length(hunk_height), Constraint::Length(comments_height)]);
 
             let parts = layout.split(area);
+            let hunk_block = bordered(theme).title(Span::styled(code_range.file_name.as_str(), theme.title));
             if let Some(hunk) = hunk {
-                let diff_paragraph = Paragraph::new(hunk)
+                let diff_paragraph = Paragraph::new(diff_text(hunk, theme))
                     .wrap(Wrap{ trim: false })
-                    .block(Block::default().borders(Borders::all()));
+                    .block(hunk_block);
                 diff_paragraph.render(parts[0], buffer);
+            } else if let Some(placeholder) = context.diff_state.placeholder("the diff", theme) {
+                placeholder.block(hunk_block).render(parts[0], buffer);
             }
-            paragraph.render(parts[1], buffer);
+            context.render_scrollable(threads_text, parts[1], buffer);
         } else {
-            paragraph.render(area, buffer);
+            context.render_scrollable(threads_text, area, buffer);
         }
-/*
-        let mut thread_writer : ScreenWriter;
-
-        if let Some(code_range) = &self.code_range {
-
-            let thread_screen = screen.split_horizontally();
-            thread_writer = thread_screen.get_content_rect().screen().get_writer();
-
-            if let Some(changelist) = changelist.as_ref() {
-                let mut changelist_writer = screen.get_content_rect().screen().get_writer();
-                changelist_writer.write_line(buffer, changelist.get_hunk(code_range)); //TODO draw diff properly
-            }
-
-            thread_screen.draw_border(buffer);
-
-        } else {
-            thread_writer = screen.get_content_rect().screen().get_writer();
-            screen.draw_border(buffer);
-        }
- 
-        for comment in self.comments.iter() {
-            thread_writer.write_line(buffer, &comment.author_name);
-            thread_writer.write_line(buffer, &comment.body);
-            thread_writer.write_line(buffer, "");
-        }*/
     }
 }
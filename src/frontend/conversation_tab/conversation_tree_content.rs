@@ -1,6 +1,12 @@
+use std::cell::Cell;
 use std::rc::Rc;
 use crate::backend::diff::ChangeList;
+use crate::config::LayoutConfig;
+use crate::frontend::theme::Theme;
+use crate::frontend::loading::LoadState;
+use crate::frontend::scroll::ScrollState;
 use super::ConversationTreeState;
+use super::conversation_draw::ContentContext;
 
 use tui::{
     widgets::{Widget, Block},
@@ -14,6 +20,12 @@ pub struct ConversationTreeContent<'a> {
     block: Block<'a>,
     state: Option<&'a ConversationTreeState>,
     changelist: Option<Rc<ChangeList>>,
+    diff_state: Option<&'a LoadState>,
+    layout: Option<&'a LayoutConfig>,
+    theme: Option<&'a Theme>,
+    scroll: Option<&'a Cell<ScrollState>>,
+    is_focused: bool,
+    absolute_times: bool,
 }
 
 impl<'a> Default for ConversationTreeContent<'a> {
@@ -22,7 +34,13 @@ impl<'a> Default for ConversationTreeContent<'a> {
             style: Style::default(),
             block: Block::default(),
             state: None,
-            changelist: None 
+            changelist: None,
+            diff_state: None,
+            layout: None,
+            theme: None,
+            scroll: None,
+            is_focused: false,
+            absolute_times: false,
         }
     }
 }
@@ -43,10 +61,40 @@ impl<'a> ConversationTreeContent<'a> {
         self
     }
 
+    pub fn theme(mut self, theme: &'a Theme) -> Self {
+        self.theme = Some(theme);
+        self
+    }
+
+    pub fn layout(mut self, layout: &'a LayoutConfig) -> Self {
+        self.layout = Some(layout);
+        self
+    }
+
     pub fn changelist(mut self, changelist: Option<Rc<ChangeList>>) -> Self {
         self.changelist = changelist;
         self
     }
+
+    pub fn diff_state(mut self, diff_state: &'a LoadState) -> Self {
+        self.diff_state = Some(diff_state);
+        self
+    }
+
+    pub fn scroll(mut self, scroll: &'a Cell<ScrollState>) -> Self {
+        self.scroll = Some(scroll);
+        self
+    }
+
+    pub fn focused(mut self, is_focused: bool) -> Self {
+        self.is_focused = is_focused;
+        self
+    }
+
+    pub fn absolute_times(mut self, absolute_times: bool) -> Self {
+        self.absolute_times = absolute_times;
+        self
+    }
 }
 
 impl<'a> Widget for ConversationTreeContent<'a> {
@@ -55,8 +103,19 @@ impl<'a> Widget for ConversationTreeContent<'a> {
 
         if area.width < 1 || area.height < 1 { return; }
 
-        if let Some(state) = self.state {
-            state.draw_selected_item(area, buf, self.style, &self.changelist);
+        if let (Some(state), Some(layout), Some(theme)) = (self.state, self.layout, self.theme) {
+            let diff_state = self.diff_state.cloned().unwrap_or(LoadState::Loaded);
+            let default_scroll = Cell::new(ScrollState::default());
+            let context = ContentContext {
+                theme,
+                changelist: &self.changelist,
+                diff_state: &diff_state,
+                layout,
+                scroll: self.scroll.unwrap_or(&default_scroll),
+                is_focused: self.is_focused,
+                absolute_times: self.absolute_times,
+            };
+            state.draw_selected_item(area, buf, &context);
         }
     }
 }
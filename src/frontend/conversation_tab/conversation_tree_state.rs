@@ -1,167 +1,254 @@
-use crate::frontend::conversation_tab::ChangeList;
 use crate::backend::pr::*;
-use std::io::Write;
-use std::rc::Rc;
-use crate::frontend::screen::{ScreenWriter, Screen};
-use super::conversation_draw::{TreeDraw, ContentDraw};
-use if_chain::if_chain;
+use crate::frontend::tree::Tree;
+use super::conversation_draw::{TreeDraw, ContentDraw, ContentContext};
 
 use tui::{
     layout::Rect,
     buffer::Buffer,
-    style::Style,
 };
 
 pub struct ConversationTreeState {
     pub conversation: PrConversation,
-    pub nodes: Vec<ConversationTreeNode>,
-    pub selected_node: usize,
+    pub tree: Tree<ConversationNode>,
+    show_events: bool,
 }
 
-pub struct ConversationTreeNode {
-    pub data: ConversationTreeItem,
-    pub is_expanded: bool,
-
-    pub next: Option<usize>,
-    pub previous: Option<usize>,
-    pub parent: Option<usize>,
-    pub child: Option<usize>,
+// Position of a node's data in the conversation
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum ConversationNode {
+    Review(usize),
+    Thread(usize, usize),
+    ThreadComment(usize, usize, usize),
+    Comment(usize),
+    Event(usize),
 }
 
-pub struct ConversationTreeItem(usize, Option<usize>);
+// What was taken out of the conversation along with a comment
+#[derive(Copy, Clone)]
+enum Removal {
+    Item(usize),
+    Thread(usize, usize),
+    ThreadComment(usize, usize, usize),
+}
 
 impl ConversationTreeState {
 
-    pub fn new (conversation: PrConversation) -> Self {
-        let mut nodes = vec![];
-        let mut previous = None;
-
-        for (index, item) in conversation.items.iter().enumerate() {
-            match item {
-
-                ConversationItem::Review(r) => {
-                    let review_node = ConversationTreeNode {
-                        data: ConversationTreeItem(index, None),
-                        next: if index != conversation.items.len() - 1 { Some(nodes.len() + r.threads.len() + 1) } else { None },
-                        previous,
-                        parent: None,
-                        child: if r.threads.len() > 0 { Some(nodes.len() + 1) } else { None },
-
-                        is_expanded: r.threads.len() > 0,
-                    };
-
-                    let review_node_index = nodes.len();
-                    nodes.push(review_node);
-                    previous = Some(review_node_index);
-
-                    for (thread_index, _) in r.threads.iter().enumerate() {
-                        let thread_node = ConversationTreeNode {
-                            data: ConversationTreeItem(index, Some(thread_index)),
-                            next: if thread_index != r.threads.len() - 1 { Some(nodes.len() + 1) } else { None },
-                            previous: Some(nodes.len() - 1),
-                            parent: Some(review_node_index),
-                            child: None,
-
-                            is_expanded: false,
-                        };
-                        nodes.push(thread_node);
+    // Events of the pull request are only shown in the timeline mode
+    pub fn new (conversation: PrConversation, show_events: bool) -> Self {
+        let tree = build_tree(&conversation, show_events);
+        ConversationTreeState { conversation, tree, show_events }
+    }
+
+    // The selected item stays selected if it's still in the tree
+    pub fn set_show_events(&mut self, show_events: bool) {
+        self.show_events = show_events;
+        let old_tree = std::mem::replace(&mut self.tree, build_tree(&self.conversation, show_events));
+        self.tree.restore_state(&old_tree, |node| Some(*node));
+    }
+
+    // A thread goes away with its last comment, and a review with its last thread unless it has a body
+    pub fn remove_comment(&mut self, id: &str) {
+        let removal = match comment_position(&self.conversation, id) {
+            Some(ConversationNode::ThreadComment(i, t, c)) => {
+                let review = match &mut self.conversation.items[i] {
+                    ConversationItem::Review(r) => r,
+                    _ => return,
+                };
+                review.threads[t].comments.remove(c);
+                if !review.threads[t].comments.is_empty() {
+                    Removal::ThreadComment(i, t, c)
+                } else {
+                    review.threads.remove(t);
+                    if !review.threads.is_empty() || !review.review_comment.body.is_empty() {
+                        Removal::Thread(i, t)
+                    } else {
+                        self.conversation.items.remove(i);
+                        Removal::Item(i)
                     }
-                },
-
-                ConversationItem::Comment(_) => {
-                    let comment_node = ConversationTreeNode {
-                        data: ConversationTreeItem(index, None),
-                        next: if index != conversation.items.len() - 1 { Some(nodes.len() + 1) } else { None },
-                        previous,
-                        parent: None,
-                        child: None,
-
-                        is_expanded: false,
-                    };
-
-                    previous = Some(nodes.len());
-                    nodes.push(comment_node);
-                },
-            }
-        }
+                }
+            },
+            Some(ConversationNode::Review(i)) | Some(ConversationNode::Comment(i)) => {
+                self.conversation.items.remove(i);
+                Removal::Item(i)
+            },
+            _ => return,
+        };
+
+        let old_tree = std::mem::replace(&mut self.tree, build_tree(&self.conversation, self.show_events));
+        self.tree.restore_state(&old_tree, |node| node.after_removal(removal));
+    }
 
-        ConversationTreeState { conversation, nodes, selected_node: 0 }
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
     }
 
-    pub fn move_selection (&mut self, forward: bool) {
-        let selected_node = self.nodes.get(self.selected_node);
-        if selected_node.is_none() {
-            return;
+    // Comment the selected item is about, the first one for a thread
+    pub fn selected_comment(&self) -> Option<&PrComment> {
+        match *self.tree.selected_data()? {
+            ConversationNode::Review(i) => Some(&review(&self.conversation, i).review_comment),
+            ConversationNode::Thread(i, t) => thread(&self.conversation, i, t).comments.first(),
+            ConversationNode::ThreadComment(i, t, c) => thread(&self.conversation, i, t).comments.get(c),
+            ConversationNode::Comment(i) => Some(comment(&self.conversation, i)),
+            ConversationNode::Event(_) => None,
         }
+    }
 
-        let selected_node = selected_node.unwrap();
-        self.selected_node = if forward {
-            if selected_node.is_expanded {
-                self.selected_node + 1
-            } else if let Some(next_index) = selected_node.next {
-                next_index
-            } else if let Some(_) = selected_node.child { //The last review in the list and not expanded. Don't move
-                self.selected_node
-            } else {
-                if self.nodes.len() > self.selected_node + 1 { self.selected_node + 1 } else { self.selected_node }
-            }
-        } else {
-            if_chain! {
-                if let Some(previous_index) = selected_node.previous;
-                if let Some(previous_node) = self.nodes.get(previous_index);
-                if let Some(_) = previous_node.child;
-                if !previous_node.is_expanded;
-                then {
-                    previous_index
-                } else {
-                    if self.selected_node > 0 { self.selected_node - 1 } else { self.selected_node }
-                }
-            }
+    pub fn selected_thread(&self) -> Option<&PrConversationThread> {
+        match *self.tree.selected_data()? {
+            ConversationNode::Thread(i, t) | ConversationNode::ThreadComment(i, t, _) => Some(thread(&self.conversation, i, t)),
+            _ => None,
         }
     }
 
-    pub fn toggle_expansion(&mut self) {
-        if let Some(node) = self.nodes.get_mut(self.selected_node) {
-            node.is_expanded = node.child.is_some() && !node.is_expanded;
+    pub fn draw_selected_item(&self, area: Rect, buffer: &mut Buffer, context: &ContentContext) {
+        if let Some(node) = self.tree.selected_data() {
+            node.content_draw(&self.conversation).draw(area, buffer, context);
         }
     }
+}
 
-    pub fn draw_selected_item(&self, area: Rect, buffer: &mut Buffer, style: Style, changelist: &Option<Rc<ChangeList>>) {
-        if let Some(selected_node) = self.nodes.get(self.selected_node) {
-            self.get_content_draw(&selected_node.data).draw(area, buffer, style, changelist);
+impl ConversationNode {
+    pub fn tree_draw<'a>(&self, conversation: &'a PrConversation) -> &'a dyn TreeDraw {
+        match *self {
+            ConversationNode::Review(i) => review(conversation, i),
+            ConversationNode::Thread(i, t) => thread(conversation, i, t),
+            ConversationNode::ThreadComment(i, t, c) => &thread(conversation, i, t).comments[c],
+            ConversationNode::Comment(i) => comment(conversation, i),
+            ConversationNode::Event(i) => event(conversation, i),
         }
     }
 
-    //TODO figure out how to reuse code in these two methods
-    pub fn get_tree_draw<'a>(&'a self, index: &ConversationTreeItem) -> &'a dyn TreeDraw {
-        let (conversation_index, thread_index) = (index.0, index.1);
-        let conversation_item = self.conversation.items.get(conversation_index).unwrap();
+    // A comment of a thread is shown along with the rest of the thread
+    pub fn content_draw<'a>(&self, conversation: &'a PrConversation) -> &'a dyn ContentDraw {
+        match *self {
+            ConversationNode::Review(i) => review(conversation, i),
+            ConversationNode::Thread(i, t) | ConversationNode::ThreadComment(i, t, _) => thread(conversation, i, t),
+            ConversationNode::Comment(i) => comment(conversation, i),
+            ConversationNode::Event(i) => event(conversation, i),
+        }
+    }
 
-        match conversation_item {
-            ConversationItem::Review(r) => {
-                if let Some(thread_index) = thread_index {
-                    r.threads.get(thread_index).unwrap()
-                } else {
-                    r
-                }
-            },
-            ConversationItem::Comment(c) => c,
+    pub fn is_thread(&self) -> bool {
+        matches!(self, ConversationNode::Thread(..))
+    }
+
+    // Position of the same data once the removed part of the conversation is gone
+    fn after_removal(self, removal: Removal) -> Option<ConversationNode> {
+        use ConversationNode::*;
+        match (self, removal) {
+            (Review(i), Removal::Item(r)) => shift(i, r).map(Review),
+            (Thread(i, t), Removal::Item(r)) => shift(i, r).map(|i| Thread(i, t)),
+            (ThreadComment(i, t, c), Removal::Item(r)) => shift(i, r).map(|i| ThreadComment(i, t, c)),
+            (Comment(i), Removal::Item(r)) => shift(i, r).map(Comment),
+            (Event(i), Removal::Item(r)) => shift(i, r).map(Event),
+            (Thread(i, t), Removal::Thread(ri, rt)) if i == ri => shift(t, rt).map(|t| Thread(i, t)),
+            (ThreadComment(i, t, c), Removal::Thread(ri, rt)) if i == ri => shift(t, rt).map(|t| ThreadComment(i, t, c)),
+            (ThreadComment(i, t, c), Removal::ThreadComment(ri, rt, rc)) if i == ri && t == rt => shift(c, rc).map(|c| ThreadComment(i, t, c)),
+            (node, _) => Some(node),
         }
     }
+}
+
+fn shift(index: usize, removed: usize) -> Option<usize> {
+    match index.cmp(&removed) {
+        std::cmp::Ordering::Less => Some(index),
+        std::cmp::Ordering::Equal => None,
+        std::cmp::Ordering::Greater => Some(index - 1),
+    }
+}
 
-    pub fn get_content_draw<'a>(&'a self, index: &ConversationTreeItem) -> &'a dyn ContentDraw {
-        let (conversation_index, thread_index) = (index.0, index.1);
-        let conversation_item = self.conversation.items.get(conversation_index).unwrap();
+fn comment_position(conversation: &PrConversation, id: &str) -> Option<ConversationNode> {
+    conversation.items.iter().enumerate().find_map(|(i, item)| match item {
+        ConversationItem::Comment(c) if c.id == id => Some(ConversationNode::Comment(i)),
+        ConversationItem::Review(r) if r.review_comment.id == id => Some(ConversationNode::Review(i)),
+        ConversationItem::Review(r) => r.threads.iter().enumerate().find_map(|(t, thread)| {
+            thread.comments.iter().position(|c| c.id == id).map(|c| ConversationNode::ThreadComment(i, t, c))
+        }),
+        _ => None,
+    })
+}
 
-        match conversation_item {
+fn build_tree(conversation: &PrConversation, show_events: bool) -> Tree<ConversationNode> {
+    let mut tree = Tree::default();
+    for (index, item) in conversation.items.iter().enumerate() {
+        match item {
             ConversationItem::Review(r) => {
-                if let Some(thread_index) = thread_index {
-                    r.threads.get(thread_index).unwrap()
-                } else {
-                    r
+                let review = tree.add_root(ConversationNode::Review(index));
+                for (thread_index, thread) in r.threads.iter().enumerate() {
+                    let thread_node = tree.add_child(review, ConversationNode::Thread(index, thread_index));
+                    for comment_index in 0..thread.comments.len() {
+                        tree.add_child(thread_node, ConversationNode::ThreadComment(index, thread_index, comment_index));
+                    }
                 }
+                tree.set_expanded(review, true);
+            },
+            ConversationItem::Comment(_) => {
+                tree.add_root(ConversationNode::Comment(index));
             },
-            ConversationItem::Comment(c) => c,
+            ConversationItem::Event(_) if show_events => {
+                tree.add_root(ConversationNode::Event(index));
+            },
+            ConversationItem::Event(_) => (),
         }
     }
+    tree
+}
+
+fn review(conversation: &PrConversation, index: usize) -> &PrReview {
+    match &conversation.items[index] {
+        ConversationItem::Review(r) => r,
+        _ => panic!("Conversation item {} is not a review", index),
+    }
+}
+
+fn thread(conversation: &PrConversation, index: usize, thread_index: usize) -> &PrConversationThread {
+    &review(conversation, index).threads[thread_index]
+}
+
+fn comment(conversation: &PrConversation, index: usize) -> &PrComment {
+    match &conversation.items[index] {
+        ConversationItem::Comment(c) => c,
+        _ => panic!("Conversation item {} is not a comment", index),
+    }
+}
+
+fn event(conversation: &PrConversation, index: usize) -> &PrEvent {
+    match &conversation.items[index] {
+        ConversationItem::Event(e) => e,
+        _ => panic!("Conversation item {} is not an event", index),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn removed_comments_keep_the_tree() {
+        let json = json::parse(r#"{"data": {"repository": {"pullRequest": {
+            "reviewThreads": {"edges": [
+                {"node": {"id": "th1", "path": "src/main.rs", "diffSide": "RIGHT", "originalLine": 3, "comments": {"edges": [
+                    {"node": {"id": "t1", "author": {"login": "bob"}, "body": "why?", "publishedAt": "2021-11-20T10:00:00Z"}},
+                    {"node": {"id": "t2", "author": {"login": "bob"}, "body": "ping", "publishedAt": "2021-11-20T11:00:00Z"}}
+                ]}}}
+            ]},
+            "reviews": {"edges": [
+                {"node": {"id": "r1", "state": "COMMENTED", "author": {"login": "bob"}, "body": "", "publishedAt": "2021-11-20T10:00:00Z", "comments": {"edges": [{"node": {"id": "t1"}}]}}}
+            ]},
+            "comments": {"edges": [
+                {"node": {"id": "c1", "author": {"login": "alice"}, "body": "hi", "publishedAt": "2021-11-20T12:00:00Z"}}
+            ]}
+        }}}}"#).unwrap();
+        let mut state = ConversationTreeState::new(parse_conversation(&json).unwrap(), false);
+        let reply = state.tree.all_nodes().find(|id| state.tree.get(*id) == Some(&ConversationNode::ThreadComment(0, 0, 1))).unwrap();
+        state.tree.select(reply);
+        assert_eq!(state.selected_comment().map(|c| c.kind), Some(CommentKind::ReviewComment));
+
+        state.remove_comment("t2");
+        assert_eq!(state.selected_comment().map(|c| c.id.as_str()), Some("c1"));
+        state.remove_comment("t1");
+        assert_eq!(state.conversation.items.len(), 1);
+        assert_eq!(state.selected_comment().map(|c| c.id.as_str()), Some("c1"));
+    }
 }
@@ -0,0 +1,343 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::sync::mpsc;
+
+use crate::backend::diff::ChangeList;
+use crate::backend::pr::{PrConversationThread, ViewedState};
+use crate::config::Config;
+use super::conversation_tab::diff_text;
+use super::keymap::Action;
+use super::loading::{LoadState, Placeholder};
+use super::main_screen_handler::MainScreenEvent;
+use super::screen::InteractableScreen;
+use super::scroll::{ScrollState, ScrollableText};
+use super::tree::{Focus, Prefixes, Tree, TreeView};
+
+use tui::{
+    backend::Backend,
+    buffer::Buffer,
+    layout::{Constraint, Direction, Layout, Rect},
+    style::Style,
+    text::Span,
+    widgets::{Block, Borders},
+    Frame,
+};
+
+// Directories hold their own name, files and their threads the full path of the file
+pub enum FileNode {
+    Directory(String),
+    File(String),
+    Thread { path: String, label: String },
+}
+
+// Thread listed under the file it's on
+#[derive(Debug, Clone)]
+pub struct ThreadSummary {
+    pub file_name: String,
+    pub line: Option<usize>,
+    pub label: String,
+}
+
+impl ThreadSummary {
+    pub fn new(thread: &PrConversationThread) -> Option<Self> {
+        let file_name = thread.code_range.as_ref()?.file_name.clone();
+        let comment = thread.comments.first()?;
+        let position = thread.line.map_or("outdated".to_string(), |line| format!("line {}", line));
+        let label = format!("{} · {}: {}", position, comment.author_name, comment.body.lines().next().unwrap_or(""));
+        Some(ThreadSummary { file_name, line: thread.line, label })
+    }
+}
+
+// Changed files grouped by their directories, with the diff of the selected file
+pub struct FilesTab {
+    tree: RefCell<Tree<FileNode>>,
+    changelist: Option<Rc<ChangeList>>,
+    threads: Vec<ThreadSummary>,
+    diff_state: LoadState,
+    // Shown when there are no files
+    empty_message: String,
+    // Known only for pull requests
+    viewed_files: Option<HashMap<String, ViewedState>>,
+    event_sender: mpsc::Sender<MainScreenEvent>,
+    focus: Focus,
+    content_scroll: Cell<ScrollState>,
+    config: Rc<Config>,
+}
+
+impl FilesTab {
+    pub fn new(event_sender: mpsc::Sender<MainScreenEvent>, config: Rc<Config>) -> Self {
+        FilesTab {
+            tree: RefCell::new(Tree::default()),
+            changelist: None,
+            threads: vec![],
+            diff_state: LoadState::loading(),
+            empty_message: "No changed files".to_string(),
+            viewed_files: None,
+            event_sender,
+            focus: Focus::Tree,
+            content_scroll: Cell::new(ScrollState::default()),
+            config,
+        }
+    }
+
+    pub fn set_changelist(&mut self, changelist: Rc<ChangeList>) {
+        self.tree = RefCell::new(file_tree(&changelist.file_names(), &self.threads));
+        self.changelist = Some(changelist);
+        self.diff_state = LoadState::Loaded;
+        self.content_scroll.set(ScrollState::default());
+    }
+
+    // Threads are shown under their files, the ones on files without changes are left out
+    pub fn set_threads(&mut self, threads: Vec<ThreadSummary>) {
+        self.threads = threads;
+        if let Some(changelist) = self.changelist.as_ref() {
+            self.tree = RefCell::new(file_tree(&changelist.file_names(), &self.threads));
+        }
+    }
+
+    // Nothing to show, for a reason other than a failure
+    pub fn set_notice(&mut self, message: &str) {
+        self.tree = RefCell::new(Tree::default());
+        self.changelist = None;
+        self.diff_state = LoadState::Loaded;
+        self.empty_message = message.to_string();
+    }
+
+    pub fn set_viewed_files(&mut self, viewed_files: Option<HashMap<String, ViewedState>>) {
+        self.viewed_files = viewed_files;
+    }
+
+    pub fn set_viewed(&mut self, path: &str, is_viewed: bool) {
+        if let Some(viewed_files) = self.viewed_files.as_mut() {
+            let state = if is_viewed { ViewedState::Viewed } else { ViewedState::Unviewed };
+            viewed_files.insert(path.to_string(), state);
+        }
+    }
+
+    fn viewed_state(&self, path: &str) -> ViewedState {
+        self.viewed_files.as_ref()
+            .and_then(|viewed_files| viewed_files.get(path).copied())
+            .unwrap_or(ViewedState::Unviewed)
+    }
+
+    fn selected_path(&self) -> Option<String> {
+        match self.tree.borrow().selected_data() {
+            Some(FileNode::File(path)) | Some(FileNode::Thread { path, .. }) => Some(path.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn start_loading(&mut self) {
+        if !self.diff_state.is_loaded() {
+            self.diff_state = LoadState::loading();
+        }
+    }
+
+    pub fn diff_failed(&mut self, error: &str) {
+        if !self.diff_state.is_loaded() {
+            self.diff_state = LoadState::Failed(error.to_string());
+        }
+    }
+
+    pub fn draw<B: Backend>(&self, frame: &mut Frame<B>, rect: Rect) {
+        let layout_config = &self.config.layout;
+        let layout = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints(vec![
+                Constraint::Percentage(layout_config.tree_percentage),
+                Constraint::Percentage(100 - layout_config.tree_percentage)
+            ])
+            .split(rect);
+
+        let theme = &self.config.theme;
+        let is_empty = self.tree.borrow().is_empty();
+        let placeholder = match (&self.diff_state, is_empty) {
+            (LoadState::Loaded, true) => Some(Placeholder::empty(&self.empty_message, theme)),
+            (LoadState::Loaded, false) => None,
+            (state, _) => state.placeholder("the diff", theme),
+        };
+        if let Some(placeholder) = placeholder {
+            let block = Block::default().borders(Borders::all()).border_style(theme.border);
+            frame.render_widget(placeholder.block(block), layout[0]);
+            frame.render_widget(Block::default().borders(Borders::all()).border_style(theme.border), layout[1]);
+            return;
+        }
+
+        let border_style = |focus| if self.focus == focus { theme.title } else { theme.border };
+        let prefixes = &self.config.prefixes;
+        let draw_label = |node: &FileNode, area: Rect, buffer: &mut Buffer, style: Style| {
+            let (label, label_style) = match node {
+                FileNode::Directory(name) => (format!("{}/", name), theme.title.patch(style)),
+                FileNode::File(path) if self.viewed_files.is_none() => (file_name(path).to_string(), style),
+                FileNode::File(path) => match self.viewed_state(path) {
+                    ViewedState::Viewed => (format!("✓ {}", file_name(path)), theme.dimmed.patch(style)),
+                    ViewedState::Unviewed => (format!("  {}", file_name(path)), style),
+                    ViewedState::Dismissed => (format!("  {} · changed since viewed", file_name(path)), style),
+                },
+                FileNode::Thread { label, .. } => (label.clone(), theme.dimmed.patch(style)),
+            };
+            buffer.set_stringn(area.x, area.y, label, area.width as usize, label_style);
+        };
+        let mut tree_block = Block::default().borders(Borders::all()).border_style(border_style(Focus::Tree));
+        if let (Some(changelist), Some(_)) = (self.changelist.as_ref(), self.viewed_files.as_ref()) {
+            let paths = changelist.file_names();
+            let viewed = paths.iter().filter(|path| self.viewed_state(path) == ViewedState::Viewed).count();
+            tree_block = tree_block.title(Span::styled(format!("{}/{} files viewed", viewed, paths.len()), theme.dimmed));
+        }
+        let tree_widget = TreeView::new(&draw_label)
+            .block(tree_block)
+            .prefixes(Prefixes::new(&prefixes.collapsed, &prefixes.expanded, &prefixes.comment))
+            .style(theme.text)
+            .highlighted_style(theme.selection);
+        frame.render_stateful_widget(tree_widget, layout[0], &mut self.tree.borrow_mut());
+
+        let tree = self.tree.borrow();
+        let path = match tree.selected_data() {
+            Some(FileNode::File(path)) | Some(FileNode::Thread { path, .. }) => path,
+            _ => {
+                let placeholder = Placeholder::empty("Select a file to see its changes", theme)
+                    .block(Block::default().borders(Borders::all()).border_style(theme.border));
+                frame.render_widget(placeholder, layout[1]);
+                return;
+            },
+        };
+        let diff = self.changelist.as_ref().map_or("", |c| c.file_diff(path));
+        let block = Block::default()
+            .borders(Borders::all())
+            .border_style(border_style(Focus::Content))
+            .title(Span::styled(path.as_str(), theme.title));
+        let text = diff_text(diff, theme).lines;
+        let content_widget = ScrollableText::new(text, &self.content_scroll)
+            .block(block)
+            .style(theme.text)
+            .scrollbar_style(border_style(Focus::Content));
+        frame.render_widget(content_widget, layout[1]);
+    }
+}
+
+impl InteractableScreen for FilesTab {
+    fn validate_input(&self, input: Action) -> bool {
+        if input == Action::ToggleViewed {
+            return self.viewed_files.is_some() && self.selected_path().is_some();
+        }
+        !self.tree.borrow().is_empty() &&
+            matches!(input, Action::NextItem | Action::PreviousItem | Action::FirstItem | Action::LastItem |
+                     Action::Expand | Action::Collapse | Action::ToggleExpand | Action::ExpandAll | Action::CollapseAll |
+                     Action::SwitchPane | Action::ScrollDown | Action::ScrollUp | Action::PageDown | Action::PageUp)
+    }
+
+    fn process_input(&mut self, input: Action, count: usize) {
+        if input == Action::ToggleViewed {
+            if let Some(path) = self.selected_path() {
+                let is_viewed = self.viewed_state(&path) != ViewedState::Viewed;
+                let _ = self.event_sender.send(MainScreenEvent::SetViewed { path, is_viewed });
+            }
+            return;
+        }
+        let count_delta = count.min(i32::MAX as usize) as i32;
+        let mut scroll = self.content_scroll.get();
+        match (input, self.focus) {
+            (Action::SwitchPane, Focus::Tree) => self.focus = Focus::Content,
+            (Action::SwitchPane, Focus::Content) => self.focus = Focus::Tree,
+            (Action::ScrollDown, _) | (Action::NextItem, Focus::Content) => scroll.scroll_by(count_delta),
+            (Action::ScrollUp, _) | (Action::PreviousItem, Focus::Content) => scroll.scroll_by(-count_delta),
+            (Action::PageDown, _) => scroll.scroll_pages(count_delta),
+            (Action::PageUp, _) => scroll.scroll_pages(-count_delta),
+            (Action::FirstItem, Focus::Content) => scroll.scroll_to_top(),
+            (Action::LastItem, Focus::Content) => scroll.scroll_to_bottom(),
+            _ => {
+                self.process_tree_input(input, count);
+                return;
+            },
+        }
+        self.content_scroll.set(scroll);
+    }
+}
+
+impl FilesTab {
+    fn process_tree_input(&mut self, input: Action, count: usize) {
+        let tree = self.tree.get_mut();
+        let selected_node = tree.selected();
+        match input {
+            Action::NextItem => tree.select_next(count),
+            Action::PreviousItem => tree.select_previous(count),
+            Action::FirstItem => tree.select_first(),
+            Action::LastItem => tree.select_last(),
+            Action::Expand => tree.set_expansion(true),
+            Action::Collapse => tree.set_expansion(false),
+            Action::ToggleExpand => tree.toggle_expansion(),
+            Action::ExpandAll => tree.set_expansion_all(true),
+            Action::CollapseAll => tree.set_expansion_all(false),
+            _ => (),
+        }
+
+        if tree.selected() != selected_node {
+            self.content_scroll.set(ScrollState::default());
+        }
+    }
+}
+
+fn file_name(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+// Every directory of the paths becomes a node, all of them expanded along with the files with threads
+fn file_tree(paths: &[&str], threads: &[ThreadSummary]) -> Tree<FileNode> {
+    let mut tree = Tree::default();
+    let mut directories: HashMap<&str, usize> = HashMap::new();
+    for path in paths {
+        let mut parent = None;
+        for (end, _) in path.match_indices('/') {
+            let directory = &path[..end];
+            let id = match directories.get(directory) {
+                Some(id) => *id,
+                None => {
+                    let node = FileNode::Directory(file_name(directory).to_string());
+                    let id = match parent {
+                        Some(parent) => tree.add_child(parent, node),
+                        None => tree.add_root(node),
+                    };
+                    directories.insert(directory, id);
+                    id
+                },
+            };
+            parent = Some(id);
+        }
+
+        let node = FileNode::File(path.to_string());
+        let id = match parent {
+            Some(parent) => tree.add_child(parent, node),
+            None => tree.add_root(node),
+        };
+        for thread in threads.iter().filter(|t| t.file_name == *path) {
+            tree.add_child(id, FileNode::Thread { path: path.to_string(), label: thread.label.clone() });
+        }
+        tree.set_expanded(id, true);
+    }
+
+    for id in directories.values() {
+        tree.set_expanded(*id, true);
+    }
+    tree
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn files_are_grouped_by_directory() {
+        let threads = vec![ThreadSummary { file_name: "src/main.rs".to_string(), line: Some(3), label: "thread".to_string() }];
+        let tree = file_tree(&["README.md", "src/app.rs", "src/frontend/tree.rs", "src/main.rs"], &threads);
+        let labels: Vec<(usize, &str)> = tree.visible_nodes()
+            .map(|id| match tree.get(id).unwrap() {
+                FileNode::Directory(name) => (tree.depth(id), name.as_str()),
+                FileNode::File(path) => (tree.depth(id), file_name(path)),
+                FileNode::Thread { label, .. } => (tree.depth(id), label.as_str()),
+            })
+            .collect();
+        assert_eq!(labels, vec![(0, "README.md"), (0, "src"), (1, "app.rs"), (1, "frontend"), (2, "tree.rs"), (1, "main.rs"), (2, "thread")]);
+    }
+}
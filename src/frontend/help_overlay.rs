@@ -0,0 +1,52 @@
+use super::keymap::{Action, Keymap, KeymapContext};
+use super::theme::Theme;
+
+use tui::{
+    buffer::Buffer,
+    layout::{Constraint, Rect},
+    text::Span,
+    widgets::{Block, Borders, Clear, Row, Table, Widget},
+};
+
+pub struct HelpOverlay<'a> {
+    keymap: &'a Keymap,
+    context: KeymapContext,
+    theme: &'a Theme,
+    hidden: Vec<Action>,
+}
+
+impl<'a> HelpOverlay<'a> {
+    pub fn new(keymap: &'a Keymap, context: KeymapContext, theme: &'a Theme) -> Self {
+        HelpOverlay { keymap, context, theme, hidden: vec![] }
+    }
+
+    pub fn hide(mut self, actions: Vec<Action>) -> Self {
+        self.hidden = actions;
+        self
+    }
+}
+
+impl<'a> Widget for HelpOverlay<'a> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let mut rows = vec![];
+        for context in [self.context, KeymapContext::Global] {
+            for (action, keys) in self.keymap.bindings_for(context).into_iter().filter(|(a, _)| !self.hidden.contains(a)) {
+                rows.push(Row::new(vec![keys.join(" "), action.name().to_string(), action.description().to_string()]));
+            }
+        }
+
+        let height = (rows.len() as u16 + 3).min(area.height);
+        let width = (area.width * 4 / 5).max(area.width.min(60));
+        let popup = Rect::new(area.x + (area.width - width) / 2, area.y + (area.height - height) / 2, width, height);
+
+        let table = Table::new(rows)
+            .block(Block::default().borders(Borders::ALL).border_style(self.theme.border)
+                   .title(Span::styled(format!("Key bindings: {}", self.context.name()), self.theme.title)))
+            .style(self.theme.text)
+            .widths(&[Constraint::Percentage(20), Constraint::Percentage(25), Constraint::Percentage(55)])
+            .header(Row::new(vec!["Keys", "Action", ""]).style(self.theme.title));
+
+        Clear.render(popup, buf);
+        table.render(popup, buf);
+    }
+}
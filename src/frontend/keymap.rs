@@ -0,0 +1,454 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use termion::event::Key;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum KeymapContext {
+    Global,
+    PrList,
+    Conversation,
+    Files,
+    Popup,
+    // Keys that aren't bound in this context are typed as text
+    TextInput,
+}
+
+macro_rules! actions {
+    ($($variant:ident => $name:literal, $description:literal;)*) => {
+        #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+        pub enum Action {
+            $($variant,)*
+        }
+
+        impl Action {
+            pub const ALL: &'static [Action] = &[$(Action::$variant,)*];
+
+            pub fn name(&self) -> &'static str {
+                match self {
+                    $(Action::$variant => $name,)*
+                }
+            }
+
+            pub fn description(&self) -> &'static str {
+                match self {
+                    $(Action::$variant => $description,)*
+                }
+            }
+        }
+    };
+}
+
+actions! {
+    Quit => "quit", "Quit ghterm";
+    Help => "help", "Show active key bindings";
+    Back => "back", "Close the popup or go to the previous screen";
+    Refresh => "refresh", "Fetch the data again";
+    NextItem => "next-item", "Select next item";
+    PreviousItem => "previous-item", "Select previous item";
+    FirstItem => "first-item", "Select first item";
+    LastItem => "last-item", "Select last item";
+    NextThread => "next-thread", "Select next thread";
+    PreviousThread => "previous-thread", "Select previous thread";
+    Expand => "expand", "Expand selected item";
+    Collapse => "collapse", "Collapse selected item";
+    ToggleExpand => "toggle-expand", "Expand or collapse selected item";
+    ExpandAll => "expand-all", "Expand all items";
+    CollapseAll => "collapse-all", "Collapse all items";
+    Select => "select", "Open selected item";
+    SwitchPane => "switch-pane", "Move focus between the tree and the content";
+    ScrollDown => "scroll-down", "Scroll the content down by a line";
+    ScrollUp => "scroll-up", "Scroll the content up by a line";
+    PageDown => "page-down", "Scroll the content down by a page";
+    PageUp => "page-up", "Scroll the content up by a page";
+    ToggleTimeline => "toggle-timeline", "Show or hide the pull request events";
+    ToggleTimeFormat => "toggle-time-format", "Switch between relative and absolute times";
+    ShowHistory => "show-history", "Show or hide the edit history of the selected comment";
+    React => "react", "Pick a reaction for the selected comment";
+    ThumbsUp => "thumbs-up", "Add or remove a 👍 on the selected comment";
+    EditComment => "edit-comment", "Edit the selected comment";
+    DeleteComment => "delete-comment", "Delete the selected comment";
+    Reply => "reply", "Reply to the selected thread or comment";
+    ReplyInEditor => "reply-in-editor", "Reply to the selected thread or comment in $VISUAL or $EDITOR";
+    QuoteReply => "quote-reply", "Reply with the selected comment quoted";
+    CopyLink => "copy-link", "Copy the link to the selected comment";
+    OpenInBrowser => "open-in-browser", "Open the selected comment in the browser";
+    OpenFile => "open-file", "Open the file of the selected thread at its line";
+    ToggleViewed => "toggle-viewed", "Mark the selected file as viewed or not viewed";
+    CheckoutBranch => "checkout-branch", "Fetch the branch of the pull request and check it out";
+    CheckoutWorktree => "checkout-worktree", "Fetch the branch of the pull request and check it out in a new worktree";
+    Submit => "submit", "Send the text";
+    OpenEditor => "open-editor", "Continue writing in $VISUAL or $EDITOR";
+    Undo => "undo", "Undo the last change to the text";
+    Redo => "redo", "Redo the last undone change";
+    Paste => "paste", "Insert the last text cut with <C-w>, <C-k>, <C-u> or <A-d>";
+    TogglePreview => "toggle-preview", "Show or hide the rendered text";
+    NextTab => "next-tab", "Go to the next tab";
+    PreviousTab => "previous-tab", "Go to the previous tab";
+}
+
+impl Action {
+    pub fn from_name(name: &str) -> Option<Action> {
+        Action::ALL.iter().copied().find(|a| a.name() == name)
+    }
+}
+
+impl KeymapContext {
+    pub const ALL: &'static [KeymapContext] = &[KeymapContext::Global, KeymapContext::PrList, KeymapContext::Conversation, KeymapContext::Files, KeymapContext::Popup, KeymapContext::TextInput];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            KeymapContext::Global => "global",
+            KeymapContext::PrList => "pr_list",
+            KeymapContext::Conversation => "conversation",
+            KeymapContext::Files => "files",
+            KeymapContext::Popup => "popup",
+            KeymapContext::TextInput => "text_input",
+        }
+    }
+
+    pub fn is_text_input(&self) -> bool {
+        *self == KeymapContext::TextInput
+    }
+
+    pub fn from_name(name: &str) -> Option<KeymapContext> {
+        KeymapContext::ALL.iter().copied().find(|c| c.name() == name)
+    }
+}
+
+pub type KeySequence = Vec<Key>;
+
+#[derive(Debug, Clone)]
+pub struct KeyBinding {
+    pub context: KeymapContext,
+    pub action: Action,
+    pub sequences: Vec<KeySequence>,
+}
+
+pub enum KeymapResult {
+    Pending,
+    Action(Action, usize),
+    Unbound,
+}
+
+pub struct Keymap {
+    bindings: HashMap<KeymapContext, Vec<(KeySequence, Action)>>,
+    pending: KeySequence,
+    count: Option<usize>,
+}
+
+impl Keymap {
+    pub fn new(overrides: &[KeyBinding]) -> Self {
+        let mut keymap = Keymap { bindings: HashMap::new(), pending: vec![], count: None };
+        for (context, action, keys) in default_bindings() {
+            let sequences = keys.iter().map(|k| parse_sequence(k).unwrap()).collect();
+            keymap.bind(*context, *action, sequences);
+        }
+        for binding in overrides {
+            keymap.bind(binding.context, binding.action, binding.sequences.clone());
+        }
+        keymap
+    }
+
+    // Replaces all the sequences an action had in the context
+    fn bind(&mut self, context: KeymapContext, action: Action, sequences: Vec<KeySequence>) {
+        let bindings = self.bindings.entry(context).or_default();
+        bindings.retain(|(_, a)| *a != action);
+        bindings.extend(sequences.into_iter().map(|s| (s, action)));
+    }
+
+    // Digits are typed as text rather than counted in a text input
+    pub fn feed(&mut self, context: KeymapContext, key: Key) -> KeymapResult {
+        if self.pending.is_empty() && !context.is_text_input() {
+            if let Key::Char(c @ '0'..='9') = key {
+                if c != '0' || self.count.is_some() {
+                    let digit = c.to_digit(10).unwrap() as usize;
+                    self.count = Some(self.count.unwrap_or(0).saturating_mul(10).saturating_add(digit));
+                    return KeymapResult::Pending;
+                }
+            }
+        }
+
+        self.pending.push(key);
+        let mut is_prefix = false;
+        let mut found = None;
+        for (sequence, action) in self.active_bindings(context) {
+            if *sequence == self.pending {
+                found = Some(*action);
+            } else if sequence.starts_with(&self.pending) {
+                is_prefix = true;
+            }
+        }
+
+        if let Some(action) = found {
+            let count = self.count.unwrap_or(1);
+            self.reset();
+            return KeymapResult::Action(action, count);
+        }
+
+        if is_prefix {
+            return KeymapResult::Pending;
+        }
+
+        // The sequence was broken by this key, try it as a start of a new one
+        let was_pending = self.pending.len() > 1;
+        self.reset();
+        if was_pending {
+            self.feed(context, key)
+        } else {
+            KeymapResult::Unbound
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.pending.clear();
+        self.count = None;
+    }
+
+    pub fn bindings_for(&self, context: KeymapContext) -> Vec<(Action, Vec<String>)> {
+        let mut result: Vec<(Action, Vec<String>)> = vec![];
+        for (sequence, action) in self.bindings.get(&context).into_iter().flatten() {
+            let notation = sequence_to_string(sequence);
+            match result.iter_mut().find(|(a, _)| a == action) {
+                Some((_, keys)) => keys.push(notation),
+                None => result.push((*action, vec![notation])),
+            }
+        }
+        result.sort_by_key(|(a, _)| Action::ALL.iter().position(|x| x == a));
+        result
+    }
+
+    // Notation of the first sequence bound to the action, for hints in the UI
+    pub fn key_hint(&self, context: KeymapContext, action: Action) -> String {
+        self.active_bindings(context)
+            .find(|(_, a)| *a == action)
+            .map(|(sequence, _)| sequence_to_string(sequence))
+            .unwrap_or_else(|| format!(":{}", action.name()))
+    }
+
+    fn active_bindings(&self, context: KeymapContext) -> impl Iterator<Item = &(KeySequence, Action)> {
+        let global = self.bindings.get(&KeymapContext::Global).into_iter().flatten()
+            .filter(move |_| !context.is_text_input());
+        let screen = self.bindings.get(&context).into_iter().flatten();
+        screen.chain(global)
+    }
+}
+
+fn default_bindings() -> &'static [(KeymapContext, Action, &'static [&'static str])] {
+    use KeymapContext::*;
+    use Action::*;
+    &[
+        (Global, Quit, &["q", "<C-c>"]),
+        (Global, Help, &["?"]),
+        (Global, Back, &["<Esc>"]),
+        (Global, Refresh, &["<C-r>"]),
+        (Global, NextTab, &["gt"]),
+        (Global, PreviousTab, &["gT"]),
+
+        (PrList, NextItem, &["j", "<Down>"]),
+        (PrList, PreviousItem, &["k", "<Up>"]),
+        (PrList, FirstItem, &["gg"]),
+        (PrList, LastItem, &["G"]),
+        (PrList, Select, &["<Enter>"]),
+
+        (Conversation, NextItem, &["j", "<Down>"]),
+        (Conversation, PreviousItem, &["k", "<Up>"]),
+        (Conversation, FirstItem, &["gg"]),
+        (Conversation, LastItem, &["G"]),
+        (Conversation, NextThread, &["]c"]),
+        (Conversation, PreviousThread, &["[c"]),
+        (Conversation, Expand, &["l", "<Right>"]),
+        (Conversation, Collapse, &["h", "<Left>"]),
+        (Conversation, ToggleExpand, &["<Space>"]),
+        (Conversation, ExpandAll, &["zR"]),
+        (Conversation, CollapseAll, &["zM"]),
+        (Conversation, ToggleTimeline, &["t"]),
+        (Conversation, ToggleTimeFormat, &["T"]),
+        (Conversation, ShowHistory, &["H"]),
+        (Conversation, React, &["R"]),
+        (Conversation, ThumbsUp, &["+"]),
+        (Conversation, EditComment, &["e"]),
+        (Conversation, DeleteComment, &["dd"]),
+        (Conversation, Reply, &["r"]),
+        (Conversation, ReplyInEditor, &["E"]),
+        (Conversation, QuoteReply, &[">"]),
+        (Conversation, CopyLink, &["yy"]),
+        (Conversation, OpenInBrowser, &["gx"]),
+        (Conversation, OpenFile, &["o"]),
+        (Conversation, CheckoutBranch, &["C"]),
+        (Conversation, CheckoutWorktree, &["W"]),
+        (Conversation, SwitchPane, &["<Tab>"]),
+        (Conversation, ScrollDown, &["<C-e>"]),
+        (Conversation, ScrollUp, &["<C-y>"]),
+        (Conversation, PageDown, &["<C-d>", "<PageDown>"]),
+        (Conversation, PageUp, &["<C-u>", "<PageUp>"]),
+
+        (Files, NextItem, &["j", "<Down>"]),
+        (Files, PreviousItem, &["k", "<Up>"]),
+        (Files, FirstItem, &["gg"]),
+        (Files, LastItem, &["G"]),
+        (Files, Expand, &["l", "<Right>"]),
+        (Files, Collapse, &["h", "<Left>"]),
+        (Files, ToggleExpand, &["<Space>"]),
+        (Files, ExpandAll, &["zR"]),
+        (Files, CollapseAll, &["zM"]),
+        (Files, SwitchPane, &["<Tab>"]),
+        (Files, ToggleViewed, &["v"]),
+        (Files, CheckoutBranch, &["C"]),
+        (Files, CheckoutWorktree, &["W"]),
+        (Files, ScrollDown, &["<C-e>"]),
+        (Files, ScrollUp, &["<C-y>"]),
+        (Files, PageDown, &["<C-d>", "<PageDown>"]),
+        (Files, PageUp, &["<C-u>", "<PageUp>"]),
+
+        (Popup, NextItem, &["l", "j", "<Right>", "<Down>"]),
+        (Popup, PreviousItem, &["h", "k", "<Left>", "<Up>"]),
+        (Popup, Select, &["<Enter>"]),
+
+        (TextInput, Submit, &["<C-s>"]),
+        (TextInput, OpenEditor, &["<C-e>"]),
+        (TextInput, Back, &["<Esc>"]),
+        (TextInput, Undo, &["<C-z>"]),
+        (TextInput, Redo, &["<C-r>"]),
+        (TextInput, Paste, &["<C-y>"]),
+        (TextInput, TogglePreview, &["<C-p>"]),
+    ]
+}
+
+// Parses vim-like key notation, e.g. `gg`, `]c`, `<C-r>`, `<A-j>`, `<Enter>`
+pub fn parse_sequence(notation: &str) -> Result<KeySequence, String> {
+    let mut keys = vec![];
+    let mut chars = notation.chars();
+    while let Some(c) = chars.next() {
+        if c != '<' {
+            keys.push(Key::Char(c));
+            continue;
+        }
+
+        let name: String = chars.by_ref().take_while(|c| *c != '>').collect();
+        keys.push(parse_special_key(&name).ok_or_else(|| format!("unknown key <{}>", name))?);
+    }
+
+    if keys.is_empty() {
+        Err("key sequence is empty".to_string())
+    } else {
+        Ok(keys)
+    }
+}
+
+fn parse_special_key(name: &str) -> Option<Key> {
+    let single_char = |s: &str| {
+        let mut chars = s.chars();
+        chars.next().filter(|_| chars.next().is_none())
+    };
+
+    if let Some(c) = name.strip_prefix("C-").and_then(single_char) {
+        return Some(Key::Ctrl(c));
+    }
+    if let Some(c) = name.strip_prefix("A-").and_then(single_char) {
+        return Some(Key::Alt(c));
+    }
+
+    let key = match name {
+        "Enter" | "CR" => Key::Char('\n'),
+        "Space" => Key::Char(' '),
+        "Tab" => Key::Char('\t'),
+        "BS" => Key::Backspace,
+        "Del" => Key::Delete,
+        "Esc" => Key::Esc,
+        "Up" => Key::Up,
+        "Down" => Key::Down,
+        "Left" => Key::Left,
+        "Right" => Key::Right,
+        "Home" => Key::Home,
+        "End" => Key::End,
+        "PageUp" => Key::PageUp,
+        "PageDown" => Key::PageDown,
+        "lt" => Key::Char('<'),
+        _ => return None,
+    };
+    Some(key)
+}
+
+pub fn sequence_to_string(sequence: &[Key]) -> String {
+    sequence.iter().map(|k| KeyNotation(*k).to_string()).collect()
+}
+
+struct KeyNotation(Key);
+
+impl Display for KeyNotation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            Key::Char('\n') => write!(f, "<Enter>"),
+            Key::Char(' ') => write!(f, "<Space>"),
+            Key::Char('\t') => write!(f, "<Tab>"),
+            Key::Char('<') => write!(f, "<lt>"),
+            Key::Char(c) => write!(f, "{}", c),
+            Key::Ctrl(c) => write!(f, "<C-{}>", c),
+            Key::Alt(c) => write!(f, "<A-{}>", c),
+            Key::Backspace => write!(f, "<BS>"),
+            Key::Delete => write!(f, "<Del>"),
+            Key::Esc => write!(f, "<Esc>"),
+            Key::Up => write!(f, "<Up>"),
+            Key::Down => write!(f, "<Down>"),
+            Key::Left => write!(f, "<Left>"),
+            Key::Right => write!(f, "<Right>"),
+            Key::Home => write!(f, "<Home>"),
+            Key::End => write!(f, "<End>"),
+            Key::PageUp => write!(f, "<PageUp>"),
+            Key::PageDown => write!(f, "<PageDown>"),
+            other => write!(f, "{:?}", other),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn feed_all(keymap: &mut Keymap, keys: &str) -> Vec<(Action, usize)> {
+        parse_sequence(keys).unwrap().into_iter()
+            .filter_map(|k| match keymap.feed(KeymapContext::Conversation, k) {
+                KeymapResult::Action(a, count) => Some((a, count)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn sequences_and_counts() {
+        let mut keymap = Keymap::new(&[]);
+        assert_eq!(feed_all(&mut keymap, "gg"), vec![(Action::FirstItem, 1)]);
+        assert_eq!(feed_all(&mut keymap, "12j"), vec![(Action::NextItem, 12)]);
+        assert_eq!(feed_all(&mut keymap, "]c<C-r>"), vec![(Action::NextThread, 1), (Action::Refresh, 1)]);
+        assert_eq!(feed_all(&mut keymap, "gj"), vec![(Action::NextItem, 1)]);
+    }
+
+    #[test]
+    fn overrides_replace_defaults() {
+        let overrides = [KeyBinding {
+            context: KeymapContext::Conversation,
+            action: Action::NextItem,
+            sequences: vec![parse_sequence("<A-n>").unwrap()],
+        }];
+        let mut keymap = Keymap::new(&overrides);
+        assert_eq!(feed_all(&mut keymap, "j<A-n>"), vec![(Action::NextItem, 1)]);
+    }
+
+    #[test]
+    fn text_input_only_has_its_own_bindings() {
+        let mut keymap = Keymap::new(&[]);
+        for key in parse_sequence("2q?").unwrap() {
+            assert!(matches!(keymap.feed(KeymapContext::TextInput, key), KeymapResult::Unbound));
+        }
+        assert!(matches!(keymap.feed(KeymapContext::TextInput, Key::Ctrl('s')), KeymapResult::Action(Action::Submit, 1)));
+    }
+
+    #[test]
+    fn notation_roundtrip() {
+        for notation in &["gg", "<C-r><Enter>", "]c", "<lt><Space>"] {
+            assert_eq!(sequence_to_string(&parse_sequence(notation).unwrap()), *notation);
+        }
+        assert!(parse_sequence("<Hyper-x>").is_err());
+    }
+}
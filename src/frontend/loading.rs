@@ -0,0 +1,97 @@
+use std::time::{Duration, Instant};
+
+use super::theme::Theme;
+
+use tui::{
+    buffer::Buffer,
+    layout::{Alignment, Rect},
+    style::Style,
+    text::Span,
+    widgets::{Block, Paragraph, Widget, Wrap},
+};
+
+pub const SPINNER_FRAMES: &[char] = &['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+const SPINNER_INTERVAL: Duration = Duration::from_millis(100);
+
+// State of a piece of data that is fetched in the background
+#[derive(Debug, Clone)]
+pub enum LoadState {
+    Loading(Instant),
+    Loaded,
+    Failed(String),
+}
+
+impl LoadState {
+    pub fn loading() -> Self {
+        LoadState::Loading(Instant::now())
+    }
+
+    pub fn is_loaded(&self) -> bool {
+        matches!(self, LoadState::Loaded)
+    }
+
+    // Placeholder to show instead of the data, `what` names it in the messages
+    pub fn placeholder<'a>(&self, what: &str, theme: &Theme) -> Option<Placeholder<'a>> {
+        match self {
+            LoadState::Loading(since) => Some(Placeholder::loading(what, *since, theme)),
+            LoadState::Failed(error) => Some(Placeholder::failed(what, error, theme)),
+            LoadState::Loaded => None,
+        }
+    }
+}
+
+pub fn spinner(elapsed: Duration) -> char {
+    let frame = elapsed.as_millis() / SPINNER_INTERVAL.as_millis();
+    SPINNER_FRAMES[frame as usize % SPINNER_FRAMES.len()]
+}
+
+// A message centered in its area, shown while there is nothing else to draw
+pub struct Placeholder<'a> {
+    text: String,
+    style: Style,
+    block: Option<Block<'a>>,
+}
+
+impl<'a> Placeholder<'a> {
+    pub fn loading(what: &str, since: Instant, theme: &Theme) -> Self {
+        let elapsed = since.elapsed();
+        let text = format!("{} Loading {}… {}s", spinner(elapsed), what, elapsed.as_secs());
+        Placeholder { text, style: theme.dimmed, block: None }
+    }
+
+    pub fn empty(message: &str, theme: &Theme) -> Self {
+        Placeholder { text: message.to_string(), style: theme.dimmed, block: None }
+    }
+
+    pub fn failed(what: &str, error: &str, theme: &Theme) -> Self {
+        let text = format!("Failed to load {}: {}", what, error.replace('\n', " "));
+        Placeholder { text, style: theme.error, block: None }
+    }
+
+    pub fn block(mut self, block: Block<'a>) -> Self {
+        self.block = Some(block);
+        self
+    }
+}
+
+impl<'a> Widget for Placeholder<'a> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let area = match self.block {
+            Some(block) => {
+                let inner = block.inner(area);
+                block.render(area, buf);
+                inner
+            },
+            None => area,
+        };
+        if area.width < 1 || area.height < 1 { return; }
+
+        let lines = (self.text.chars().count() as u16 / area.width) + 1;
+        let top = area.height.saturating_sub(lines) / 2;
+        let text_area = Rect::new(area.x, area.y + top, area.width, area.height - top);
+        Paragraph::new(Span::styled(self.text, self.style))
+            .alignment(Alignment::Center)
+            .wrap(Wrap { trim: true })
+            .render(text_area, buf);
+    }
+}
@@ -1,49 +1,78 @@
+use std::collections::HashMap;
 use std::rc::Rc;
 use crate::backend::diff::ChangeList;
 use crate::app::events::AppEvent;
-use crate::backend::pr::PrConversation;
+use crate::backend::pr::{CommentTarget, PrConversation, PrCommentEdit, ReactionContent, References, ViewedState};
+use termion::event::Key;
+use crate::config::Config;
 use super::main_screen_handler::MainScreenEvent;
 use tui::{
     backend::Backend,
-    widgets::{Block, Borders, Tabs},
-    style::{Style, Modifier, Color},
-    text::{Spans, Span},
-    layout::{Layout, Direction, Constraint},
+    widgets::{Paragraph, Tabs},
+    text::Spans,
+    layout::{Alignment, Layout, Direction, Constraint, Rect},
     Frame,
 };
 
 use std::sync::mpsc;
+use chrono::{DateTime, Local};
 use std::fmt::{Display, Formatter, Error};
 
 use super::screen::{DrawableScreen, InteractableScreen};
 use super::conversation_tab::ConversationTab;
+use super::files_tab::{FilesTab, ThreadSummary};
+use super::keymap::{Action, KeymapContext};
+use super::confirm_prompt::ConfirmPrompt;
 
 pub enum MainScreenTab { 
-    Conversation(ConversationTab),
+    Conversation(Box<ConversationTab>),
+    Files(FilesTab),
+    // Changes pushed since the viewer's last review
+    Interdiff(FilesTab),
 }
 
 impl Display for MainScreenTab {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
         let label = match self {
             MainScreenTab::Conversation(_) => "Conversation",
-            _ => "",
+            MainScreenTab::Files(_) => "Files",
+            MainScreenTab::Interdiff(_) => "Since review",
         };
         write!(f, "{}", label)
     }
 }
 
 impl InteractableScreen for MainScreenTab { 
-    fn validate_input(&self, input: termion::event::Key) -> bool {
+    fn validate_input(&self, input: Action) -> bool {
         match self {
             MainScreenTab::Conversation(ct) => ct.validate_input(input),
-            _ => false,
+            MainScreenTab::Files(ft) | MainScreenTab::Interdiff(ft) => ft.validate_input(input),
         }
     }
 
-    fn process_input(&mut self, input: termion::event::Key) {
+    fn process_input(&mut self, input: Action, count: usize) {
         match self {
-            MainScreenTab::Conversation(ct) => ct.process_input(input),
-            _ => (),
+            MainScreenTab::Conversation(ct) => ct.process_input(input, count),
+            MainScreenTab::Files(ft) | MainScreenTab::Interdiff(ft) => ft.process_input(input, count),
+        }
+    }
+
+    fn process_text_input(&mut self, key: Key) {
+        if let MainScreenTab::Conversation(ct) = self {
+            ct.process_text_input(key);
+        }
+    }
+
+    fn process_paste(&mut self, text: &str) {
+        if let MainScreenTab::Conversation(ct) = self {
+            ct.process_paste(text);
+        }
+    }
+
+    fn hides_action(&self, input: Action) -> bool {
+        match self {
+            MainScreenTab::Conversation(ct) => ct.hides_action(input),
+            MainScreenTab::Files(ft) | MainScreenTab::Interdiff(ft) => ft.hides_action(input),
         }
     }
 }
@@ -52,41 +81,207 @@ pub struct MainScreen  {
     tabs: Vec<MainScreenTab>,
     current_tab_index: usize,
     app_event_sender: mpsc::Sender<AppEvent>,
+    stale_since: Option<DateTime<Local>>,
+    // Question and the event sent if it's confirmed
+    confirmation: Option<(String, MainScreenEvent)>,
+    screen_event_sender: mpsc::Sender<MainScreenEvent>,
+    config: Rc<Config>,
 }
 
 impl MainScreen {
-    pub fn new (app_event_sender: mpsc::Sender<AppEvent>, screen_event_sender: mpsc::Sender<MainScreenEvent>) -> Self {
+    pub fn new (app_event_sender: mpsc::Sender<AppEvent>, screen_event_sender: mpsc::Sender<MainScreenEvent>, config: Rc<Config>) -> Self {
         let tabs = vec![
-            MainScreenTab::Conversation(ConversationTab::new(screen_event_sender.clone())),
+            MainScreenTab::Conversation(Box::new(ConversationTab::new(screen_event_sender.clone(), Rc::clone(&config)))),
+            MainScreenTab::Files(FilesTab::new(screen_event_sender.clone(), Rc::clone(&config))),
         ];
-        MainScreen{tabs, current_tab_index: 0, app_event_sender}
+        MainScreen{tabs, current_tab_index: 0, app_event_sender, stale_since: None, confirmation: None, screen_event_sender, config}
     }
 
     pub fn set_conversation(&mut self, conversation: PrConversation) {
+        if let Some(ct) = self.conversation_tab() {
+            ct.set_conversation(conversation);
+        }
+        self.app_event_sender.send(AppEvent::ScreenRepaint).unwrap();
+    }
+
+    pub fn set_viewed_files(&mut self, viewed_files: HashMap<String, ViewedState>) {
         for tab in self.tabs.iter_mut() {
-            if let MainScreenTab::Conversation(ct) = tab {
-                ct.set_conversation(conversation);
-                self.app_event_sender.send(AppEvent::ScreenRepaint).unwrap();
-                break;
+            if let MainScreenTab::Files(ft) | MainScreenTab::Interdiff(ft) = tab {
+                ft.set_viewed_files(Some(viewed_files.clone()));
             }
         }
+        self.app_event_sender.send(AppEvent::ScreenRepaint).unwrap();
+    }
+
+    pub fn set_viewed(&mut self, path: &str, is_viewed: bool) {
+        for tab in self.tabs.iter_mut() {
+            if let MainScreenTab::Files(ft) | MainScreenTab::Interdiff(ft) = tab {
+                ft.set_viewed(path, is_viewed);
+            }
+        }
+        self.app_event_sender.send(AppEvent::ScreenRepaint).unwrap();
+    }
+
+    pub fn add_interdiff_tab(&mut self) {
+        self.tabs.push(MainScreenTab::Interdiff(FilesTab::new(self.screen_event_sender.clone(), Rc::clone(&self.config))));
+    }
+
+    pub fn set_interdiff(&mut self, changelist: ChangeList, threads: Vec<ThreadSummary>) {
+        if let Some(it) = self.interdiff_tab() {
+            it.set_threads(threads);
+            it.set_changelist(Rc::new(changelist));
+        }
+        self.app_event_sender.send(AppEvent::ScreenRepaint).unwrap();
+    }
+
+    pub fn interdiff_notice(&mut self, message: &str) {
+        if let Some(it) = self.interdiff_tab() {
+            it.set_notice(message);
+        }
+        self.app_event_sender.send(AppEvent::ScreenRepaint).unwrap();
+    }
+
+    pub fn interdiff_failed(&mut self, error: &str) {
+        if let Some(it) = self.interdiff_tab() {
+            it.diff_failed(error);
+        }
+        self.app_event_sender.send(AppEvent::ScreenRepaint).unwrap();
+    }
+
+    pub fn set_edits(&mut self, comment_id: &str, edits: Result<Vec<PrCommentEdit>, String>) {
+        if let Some(ct) = self.conversation_tab() {
+            ct.set_edits(comment_id, edits);
+        }
+        self.app_event_sender.send(AppEvent::ScreenRepaint).unwrap();
+    }
+
+    pub fn set_reaction(&mut self, comment_id: &str, content: ReactionContent, is_added: bool) {
+        if let Some(ct) = self.conversation_tab() {
+            ct.set_reaction(comment_id, content, is_added);
+        }
+        self.app_event_sender.send(AppEvent::ScreenRepaint).unwrap();
+    }
+
+    pub fn comment_sent(&mut self, target: &CommentTarget, body: String) {
+        if let Some(ct) = self.conversation_tab() {
+            ct.comment_sent(target, body);
+        }
+        self.app_event_sender.send(AppEvent::ScreenRepaint).unwrap();
+    }
+
+    pub fn comment_not_sent(&mut self, target: &CommentTarget, body: String) {
+        if let Some(ct) = self.conversation_tab() {
+            ct.comment_not_sent(target, body);
+        }
+        self.app_event_sender.send(AppEvent::ScreenRepaint).unwrap();
+    }
+
+    pub fn confirm(&mut self, question: String, event: MainScreenEvent) {
+        self.confirmation = Some((question, event));
+        self.app_event_sender.send(AppEvent::ScreenRepaint).unwrap();
+    }
+
+    pub fn show_timeline(&mut self) {
+        if let Some(ct) = self.conversation_tab() {
+            ct.show_timeline();
+        }
+    }
+
+    pub fn set_references(&mut self, references: References) {
+        if let Some(ct) = self.conversation_tab() {
+            ct.set_references(references);
+        }
+    }
+
+    pub fn comment_deleted(&mut self, comment_id: &str) {
+        if let Some(ct) = self.conversation_tab() {
+            ct.comment_deleted(comment_id);
+        }
+        self.app_event_sender.send(AppEvent::ScreenRepaint).unwrap();
+    }
+
+    pub fn start_loading(&mut self) {
+        if let Some(ct) = self.conversation_tab() {
+            ct.start_loading();
+        }
+        if let Some(ft) = self.files_tab() {
+            ft.start_loading();
+        }
+        self.app_event_sender.send(AppEvent::ScreenRepaint).unwrap();
+    }
+
+    pub fn conversation_failed(&mut self, error: &str) {
+        if let Some(ct) = self.conversation_tab() {
+            ct.conversation_failed(error);
+        }
+        self.app_event_sender.send(AppEvent::ScreenRepaint).unwrap();
+    }
+
+    pub fn diff_failed(&mut self, error: &str) {
+        if let Some(ct) = self.conversation_tab() {
+            ct.diff_failed(error);
+        }
+        if let Some(ft) = self.files_tab() {
+            ft.diff_failed(error);
+        }
+        self.app_event_sender.send(AppEvent::ScreenRepaint).unwrap();
+    }
+
+    fn conversation_tab(&mut self) -> Option<&mut ConversationTab> {
+        self.tabs.iter_mut()
+            .find_map(|tab| match tab {
+                MainScreenTab::Conversation(ct) => Some(ct.as_mut()),
+                _ => None,
+            })
+    }
+
+    fn files_tab(&mut self) -> Option<&mut FilesTab> {
+        self.tabs.iter_mut()
+            .find_map(|tab| match tab {
+                MainScreenTab::Files(ft) => Some(ft),
+                _ => None,
+            })
+    }
+
+    fn interdiff_tab(&mut self) -> Option<&mut FilesTab> {
+        self.tabs.iter_mut()
+            .find_map(|tab| match tab {
+                MainScreenTab::Interdiff(it) => Some(it),
+                _ => None,
+            })
+    }
+
+    pub fn keymap_context(&self) -> KeymapContext {
+        if self.confirmation.is_some() {
+            return KeymapContext::Popup;
+        }
+        match &self.tabs[self.current_tab_index] {
+            MainScreenTab::Conversation(ct) => ct.keymap_context(),
+            MainScreenTab::Files(_) | MainScreenTab::Interdiff(_) => KeymapContext::Files,
+        }
+    }
+
+    pub fn set_stale_since(&mut self, stale_since: Option<DateTime<Local>>) {
+        self.stale_since = stale_since;
+        self.app_event_sender.send(AppEvent::ScreenRepaint).unwrap();
     }
 
     pub fn set_changelist(&mut self, changelist: ChangeList) {
         let changelist = Rc::new(changelist);
         for tab in self.tabs.iter_mut() {
-            if let MainScreenTab::Conversation(ct) = tab {
-                ct.set_changelist(Rc::clone(&changelist));
-                self.app_event_sender.send(AppEvent::ScreenRepaint).unwrap();
-                break;
+            match tab {
+                MainScreenTab::Conversation(ct) => ct.set_changelist(Rc::clone(&changelist)),
+                MainScreenTab::Files(ft) => ft.set_changelist(Rc::clone(&changelist)),
+                MainScreenTab::Interdiff(_) => (),
             }
         }
+        self.app_event_sender.send(AppEvent::ScreenRepaint).unwrap();
     }
 }
 
 impl<B: Backend> DrawableScreen<B> for MainScreen {
-    fn draw(&self, frame: &mut Frame<B>) {
-        let size = frame.size();
+    fn draw(&self, frame: &mut Frame<B>, area: Rect) {
+        let size = area;
         let layout = Layout::default()
             .direction(Direction::Vertical)
             .constraints(vec![
@@ -102,26 +297,73 @@ impl<B: Backend> DrawableScreen<B> for MainScreen {
             })
             .collect();
 
+        let theme = &self.config.theme;
         let tabs = Tabs::new(titles)
+            .style(theme.dimmed)
+            .highlight_style(theme.title)
             .select(self.current_tab_index);
 
         frame.render_widget(tabs, layout[0]);
 
+        if let Some(timestamp) = self.stale_since {
+            let indicator = Paragraph::new(format!("stale since {}", timestamp.format("%Y-%m-%d %H:%M")))
+                .style(theme.dimmed)
+                .alignment(Alignment::Right);
+            frame.render_widget(indicator, layout[0]);
+        }
+
         if let Some(selected_tab) = self.tabs.get(self.current_tab_index) {
             match selected_tab {
                 MainScreenTab::Conversation(ct) => ct.draw(frame, layout[1]),
+                MainScreenTab::Files(ft) | MainScreenTab::Interdiff(ft) => ft.draw(frame, layout[1]),
             }
         }
+
+        if let Some((question, _)) = self.confirmation.as_ref() {
+            frame.render_widget(ConfirmPrompt::new(question, theme), layout[1]);
+        }
     }
 }
 
 impl InteractableScreen for MainScreen {
-    fn validate_input(&self, input: termion::event::Key) -> bool {
-        self.tabs[self.current_tab_index].validate_input(input)
+    fn validate_input(&self, input: Action) -> bool {
+        if self.confirmation.is_some() {
+            return matches!(input, Action::Select | Action::Back);
+        }
+        match input {
+            Action::NextTab | Action::PreviousTab => self.tabs.len() > 1,
+            _ => self.tabs[self.current_tab_index].validate_input(input),
+        }
+    }
+
+    fn process_input(&mut self, input: Action, count: usize) {
+        let tab_count = self.tabs.len();
+        if let Some((_, event)) = self.confirmation.take() {
+            if input == Action::Select {
+                let _ = self.screen_event_sender.send(event);
+            }
+            self.app_event_sender.send(AppEvent::ScreenRepaint).unwrap();
+            return;
+        }
+        match input {
+            Action::NextTab => self.current_tab_index = (self.current_tab_index + count) % tab_count,
+            Action::PreviousTab => self.current_tab_index = (self.current_tab_index + tab_count - count % tab_count) % tab_count,
+            _ => self.tabs[self.current_tab_index].process_input(input, count),
+        }
+        self.app_event_sender.send(AppEvent::ScreenRepaint).unwrap();
     }
 
-    fn process_input(&mut self, input: termion::event::Key) {
-        self.tabs[self.current_tab_index].process_input(input);
+    fn process_text_input(&mut self, key: Key) {
+        self.tabs[self.current_tab_index].process_text_input(key);
         self.app_event_sender.send(AppEvent::ScreenRepaint).unwrap();
     }
+
+    fn process_paste(&mut self, text: &str) {
+        self.tabs[self.current_tab_index].process_paste(text);
+        self.app_event_sender.send(AppEvent::ScreenRepaint).unwrap();
+    }
+
+    fn hides_action(&self, input: Action) -> bool {
+        self.tabs[self.current_tab_index].hides_action(input)
+    }
 }
@@ -1,98 +1,794 @@
-use crate::backend::diff::ChangeList;
+use crate::backend::diff::{ChangeList, DiffSource};
 use json::JsonValue;
 use crate::app::events::AppEvent;
 use crate::backend::task::*;
-use crate::backend::pr;
+use crate::backend::pr::{self, CommentKind, CommentTarget, PrConversation, PrEvent, PrHead, ReactionContent, ReviewedHead};
+use crate::backend::{browser, editor, git};
+use crate::backend::git::{Checkout, Repository};
 use crate::backend::gh::*;
+use crate::backend::cache::Cache;
+use crate::config::Config;
 use crate::error::Error;
 use tui::backend::Backend;
 use tui::Frame;
 
 use super::screen::{Rect, ApplicationScreen, DrawableScreen, InteractableScreen, ScreenHandler};
 use super::main_screen::MainScreen;
+use super::files_tab::ThreadSummary;
+use super::keymap::{Action, KeymapContext};
 
 use std::sync::mpsc;
+use std::rc::Rc;
+use std::path::PathBuf;
+use termion::event::Key;
 
+// Requests of the tabs that need the backend
 pub enum MainScreenEvent {
+    LoadEdits(String),
+    SetReaction { comment_id: String, content: ReactionContent, is_added: bool },
+    SendComment { target: CommentTarget, body: String },
+    DeleteComment { comment_id: String, kind: CommentKind },
+    // The text continues in an external editor and is sent when it exits
+    OpenEditor { target: CommentTarget, text: String, context: String },
+    // Lines of the file at commits of the pull request, tried in order, and the line
+    // to open if none of the commits is available locally
+    OpenFile { file_name: String, positions: Vec<(String, usize)>, fallback_line: usize },
+    CopyLink(String),
+    OpenLink(String),
+    // Checking out over uncommitted changes was confirmed
+    Checkout { in_worktree: bool },
+    SetViewed { path: String, is_viewed: bool },
+}
+
+// A file being marked as viewed or not viewed
+struct ViewedTask {
+    path: String,
+    is_viewed: bool,
+    task: TaskHandle<Result<JsonValue, Error>>,
+}
+
+// A reaction being added or removed
+struct ReactionTask {
+    comment_id: String,
+    content: ReactionContent,
+    is_added: bool,
+    task: TaskHandle<Result<JsonValue, Error>>,
+}
+
+// A comment or its deletion being sent
+struct CommentTask {
+    change: CommentChange,
+    task: TaskHandle<Result<JsonValue, Error>>,
+}
+
+// The checkout is planned first, then the branch is fetched and checked out
+struct CheckoutTask {
+    repository: Repository,
+    checkout: Checkout,
+    step: CheckoutStep,
+    task: TaskHandle<Result<String, Error>>,
+}
+
+enum CheckoutStep { Fetch, CheckOut }
 
+// The planned checkout and whether the working tree has uncommitted changes
+type CheckoutPlan = (Repository, Checkout, bool);
+
+enum CommentChange {
+    Send { target: CommentTarget, body: String },
+    Delete { comment_id: String },
+}
+
+// What the screen shows: a pull request, the commits of a range or a pull request with
+// the diff of a range, and where the diff comes from
+pub struct Review {
+    pub number: Option<u32>,
+    pub diff_source: DiffSource,
 }
 
 pub struct MainScreenHandler<'a> {
     screen: MainScreen,
     app_events_sender: mpsc::Sender<AppEvent>,
-    conversation_task: TaskHandle<Result<JsonValue, Error>>,
-    diff_task: TaskHandle<Result<String, Error>>,
+    conversation_task: Option<TaskHandle<Result<JsonValue, Error>>>,
+    diff_task: Option<TaskHandle<Result<String, Error>>>,
+    // Commits shown in place of the conversation when there is no pull request
+    commits_task: Option<TaskHandle<Result<Vec<PrEvent>, Error>>>,
+    interdiff_task: Option<TaskHandle<Result<String, Error>>>,
+    viewed_files_task: Option<TaskHandle<Result<Vec<JsonValue>, Error>>>,
+    // Reviewed head and current head the interdiff is made for
+    interdiff_range: Option<(String, String)>,
+    threads: Vec<ThreadSummary>,
+    edits_task: Option<(String, TaskHandle<Result<JsonValue, Error>>)>,
+    // Users and issues for completion in the editor
+    references_task: Option<TaskHandle<Result<JsonValue, Error>>>,
+    // Planning a checkout, with whether the uncommitted changes were confirmed
+    checkout_plan_task: Option<(bool, TaskHandle<Result<CheckoutPlan, Error>>)>,
+    checkout_task: Option<CheckoutTask>,
+    reaction_tasks: Vec<ReactionTask>,
+    viewed_tasks: Vec<ViewedTask>,
+    comment_tasks: Vec<CommentTask>,
+    // Draft file open in the external editor
+    draft: Option<(PathBuf, CommentTarget)>,
     task_manager: TaskManager,
     client: &'a GhClient,
     screen_events_receiver: mpsc::Receiver<MainScreenEvent>,
+    number: Option<u32>,
+    diff_source: DiffSource,
+    // Head the local diff was made for, it's made again when the head moves
+    local_diff_head: Option<String>,
+    cache: Cache,
+    head_sha: Option<String>,
+    head: Option<PrHead>,
+    // Node id of the pull request, files are marked as viewed with it
+    pr_id: Option<String>,
+    uncached_diff: Option<String>,
+    // The cached data stays stale until both are refreshed
+    conversation_refreshed: bool,
+    diff_refreshed: bool,
+    offline: bool,
+    config: Rc<Config>,
 }
 
 impl<'a> MainScreenHandler<'a> {
-    pub fn new (number: u32, app_events_sender: mpsc::Sender<AppEvent>, client: &'a GhClient) -> Self {
-        let mut conversation_request = client.pr_conversation(number).expect("Problem fetching pr conversation");
-        let mut diff_request = client.pr_diff(number);
-        let mut task_manager = TaskManager::new(app_events_sender.clone());
-        let conversation_task = task_manager.post(move || conversation_request.execute());
-        let diff_task = task_manager.post(move || diff_request.execute());
-        
+    pub fn new (review: Review, app_events_sender: mpsc::Sender<AppEvent>, task_manager: TaskManager, client: &'a GhClient, cache: Cache, offline: bool, config: Rc<Config>) -> Self {
         let (events_tx, screen_events_receiver) = mpsc::channel();
-        let screen = MainScreen::new(app_events_sender.clone(), events_tx.clone());
+        let screen = MainScreen::new(app_events_sender.clone(), events_tx.clone(), Rc::clone(&config));
 
-        MainScreenHandler{
+        let mut handler = MainScreenHandler{
             screen,
             app_events_sender,
-            conversation_task,
-            diff_task,
+            conversation_task: None,
+            diff_task: None,
+            commits_task: None,
+            interdiff_task: None,
+            viewed_files_task: None,
+            interdiff_range: None,
+            threads: vec![],
+            edits_task: None,
+            references_task: None,
+            checkout_plan_task: None,
+            checkout_task: None,
+            reaction_tasks: vec![],
+            viewed_tasks: vec![],
+            comment_tasks: vec![],
+            draft: None,
             task_manager,
             client,
             screen_events_receiver,
+            number: review.number,
+            diff_source: review.diff_source,
+            local_diff_head: None,
+            cache,
+            head_sha: None,
+            head: None,
+            pr_id: None,
+            uncached_diff: None,
+            conversation_refreshed: false,
+            diff_refreshed: false,
+            offline,
+            config,
+        };
+        if handler.number.is_some() {
+            handler.screen.add_interdiff_tab();
         }
+        handler.load_from_cache();
+        handler.fetch();
+        handler.load_references();
+        handler
     }
-}
 
-impl<'a, B: Backend> ScreenHandler<B> for MainScreenHandler<'a> {
-    fn update(&mut self) {
-        if let Some(evt) = self.screen_events_receiver.try_recv().ok() {
+    fn load_references(&mut self) {
+        if self.offline || self.number.is_none() {
+            return;
+        }
+
+        match self.client.references() {
+            Ok(mut request) => {
+                let task = self.task_manager.post("references", "Loading users and issues", move || request.execute());
+                self.references_task = Some(task);
+            },
+            Err(error) => self.report_error(error),
+        }
+    }
+
+    // Ranges are read from the local repository, so they can be loaded again in offline mode as well
+    fn fetch(&mut self) {
+        if !self.offline {
+            self.screen.start_loading();
+        }
+        // Diffs made locally aren't cached, so they are never stale
+        self.conversation_refreshed = false;
+        self.diff_refreshed = self.diff_source != DiffSource::Api;
+        if let Some(number) = self.number.filter(|_| !self.offline) {
+            match self.client.pr_conversation(number) {
+                Ok(mut request) => {
+                    let task = self.task_manager.post(&format!("conversation/{}", number), &format!("Loading conversation of #{}", number), move || request.execute());
+                    self.conversation_task = Some(task);
+                },
+                Err(error) => self.conversation_failed(error),
+            }
+            match self.client.viewed_files(number) {
+                Ok(mut request) => {
+                    let task = self.task_manager.post(&format!("viewed_files/{}", number), "Loading viewed files", move || request.execute());
+                    self.viewed_files_task = Some(task);
+                },
+                Err(error) => self.report_error(error),
+            }
+            if self.diff_source == DiffSource::Api {
+                let mut diff_request = self.client.pr_diff(number);
+                let task = self.task_manager.post(&format!("diff/{}", number), &format!("Loading diff of #{}", number), move || diff_request.execute());
+                self.diff_task = Some(task);
+            }
+        }
+
+        if let DiffSource::Range(range) = &self.diff_source {
+            let diff_range = range.clone();
+            let task = self.task_manager.post(&format!("diff/{}", range), &format!("Running git diff {}", range), move || git::diff(&diff_range));
+            self.diff_task = Some(task);
+            if self.number.is_none() {
+                let commits_range = range.clone();
+                let task = self.task_manager.post(&format!("commits/{}", range), &format!("Reading the commits of {}", range), move || git::commits(&commits_range));
+                self.commits_task = Some(task);
+            }
+        }
+        // The local diff of a pull request is made once the conversation tells its base and head
+        self.local_diff_head = None;
+    }
+
+    fn load_from_cache(&mut self) {
+        let number = match self.number {
+            Some(number) => number,
+            None => return,
+        };
+        let conversation = self.cache.load_conversation(number);
+        // Diffs made locally aren't cached
+        let diff = match self.diff_source {
+            DiffSource::Api => self.cache.load_diff(number),
+            _ => None,
+        };
+        let stale_since = conversation.iter().map(|e| e.timestamp)
+            .chain(diff.iter().map(|e| e.timestamp))
+            .min();
+
+        match conversation.map(|entry| pr::parse_conversation(&entry.data)) {
+            Some(Ok(conversation)) => self.set_conversation(conversation),
+            Some(Err(error)) => self.conversation_failed(error),
+            None if self.offline => self.conversation_failed(Error::NotCached(format!("conversation of #{}", number))),
+            None => (),
+        }
+
+        match diff {
+            Some(entry) => self.screen.set_changelist(ChangeList::new(entry.data)),
+            None if self.offline && self.diff_source == DiffSource::Api => self.diff_failed(Error::NotCached(format!("diff of #{}", number))),
+            None => (),
+        }
+
+        self.screen.set_stale_since(stale_since);
+    }
 
+    fn load_local_diff(&mut self, conversation: &PrConversation) {
+        let (base, head) = match (conversation.base_sha.as_ref(), conversation.head_sha.as_ref()) {
+            (Some(base), Some(head)) => (base, head),
+            _ => return self.diff_failed(Error::NotCached("base and head commits of the pull request".to_string())),
+        };
+        if self.local_diff_head.as_ref() == Some(head) {
+            return;
         }
 
-        if let Some(res) = self.conversation_task.poll() {
+        self.local_diff_head = Some(head.clone());
+        let range = format!("{}...{}", base, head);
+        let label = format!("Running git diff for #{}", self.number.unwrap_or_default());
+        let task = self.task_manager.post(&format!("diff/{}", range), &label, move || {
+            git::diff(&range).map_err(|error| Error::Other(format!("The commits of the pull request have to be in the local repository, check out its branch first.\n{}", error)))
+        });
+        self.diff_task = Some(task);
+    }
+
+    fn store_diff(&mut self, diff: String) {
+        match (self.head_sha.as_ref(), self.number) {
+            (Some(head_sha), Some(number)) => self.cache.store_diff(number, head_sha, &diff),
+            _ => self.uncached_diff = Some(diff),
+        }
+    }
+
+    fn update_staleness(&mut self) {
+        if self.conversation_refreshed && self.diff_refreshed && self.head_sha.is_some() {
+            self.screen.set_stale_since(None);
+        }
+    }
+
+    fn conversation_failed(&mut self, error: Error) {
+        self.screen.conversation_failed(&error.to_string());
+        // The interdiff needs the conversation to know the reviewed head
+        self.screen.interdiff_failed(&error.to_string());
+        self.report_error(error);
+    }
+
+    fn diff_failed(&mut self, error: Error) {
+        self.screen.diff_failed(&error.to_string());
+        self.report_error(error);
+    }
+
+    fn process_screen_events(&mut self) {
+        while let Ok(event) = self.screen_events_receiver.try_recv() {
+            match event {
+                MainScreenEvent::LoadEdits(comment_id) => self.load_edits(comment_id),
+                MainScreenEvent::SetReaction { comment_id, content, is_added } => self.set_reaction(comment_id, content, is_added),
+                MainScreenEvent::SendComment { target, body } => {
+                    let request = self.client.send_comment(&target, &body);
+                    self.change_comment(CommentChange::Send { target, body }, request);
+                },
+                MainScreenEvent::DeleteComment { comment_id, kind } => {
+                    let request = self.client.delete_comment(&comment_id, kind);
+                    self.change_comment(CommentChange::Delete { comment_id }, request);
+                },
+                MainScreenEvent::OpenEditor { target, text, context } => self.open_editor(target, &text, &context),
+                MainScreenEvent::OpenFile { file_name, positions, fallback_line } => {
+                    if let Err(error) = self.open_file(&file_name, &positions, fallback_line) {
+                        self.report_error(error);
+                    }
+                },
+                MainScreenEvent::Checkout { in_worktree } => self.checkout(in_worktree, true),
+                MainScreenEvent::SetViewed { path, is_viewed } => self.set_viewed(path, is_viewed),
+                MainScreenEvent::CopyLink(url) => {
+                    if let Err(error) = crate::terminal::copy_to_clipboard(&url) {
+                        self.report_error(error);
+                    }
+                },
+                MainScreenEvent::OpenLink(url) => {
+                    if let Err(error) = browser::open(&url) {
+                        self.report_error(error);
+                    }
+                },
+            }
+        }
+    }
+
+    fn load_edits(&mut self, comment_id: String) {
+        if self.offline {
+            let error = Error::NotCached("edit history".to_string());
+            self.screen.set_edits(&comment_id, Err(error.to_string()));
+            return;
+        }
+
+        match self.client.comment_edits(&comment_id) {
+            Ok(mut request) => {
+                let task = self.task_manager.post(&format!("edits/{}", comment_id), "Loading edit history", move || request.execute());
+                self.edits_task = Some((comment_id, task));
+            },
+            Err(error) => {
+                self.screen.set_edits(&comment_id, Err(error.to_string()));
+                self.report_error(error);
+            },
+        }
+    }
+
+    fn set_reaction(&mut self, comment_id: String, content: ReactionContent, is_added: bool) {
+        if self.offline {
+            self.report_error(Error::Offline("react to comments".to_string()));
+            return;
+        }
+
+        match self.client.set_reaction(&comment_id, content, is_added) {
+            Ok(mut request) => {
+                let key = format!("reaction/{}/{}", comment_id, content.name());
+                let task = self.task_manager.post_unique(&key, "Sending the reaction", move || request.execute());
+                self.reaction_tasks.push(ReactionTask { comment_id, content, is_added, task });
+            },
+            Err(error) => self.report_error(error),
+        }
+    }
+
+    fn set_viewed(&mut self, path: String, is_viewed: bool) {
+        if self.offline {
+            self.report_error(Error::Offline("mark files as viewed".to_string()));
+            return;
+        }
+        let pr_id = match self.pr_id.as_ref() {
+            Some(pr_id) => pr_id,
+            None => return,
+        };
+
+        match self.client.set_file_viewed(pr_id, &path, is_viewed) {
+            Ok(mut request) => {
+                let label = if is_viewed { "Marking the file as viewed" } else { "Marking the file as not viewed" };
+                let task = self.task_manager.post_unique(&format!("viewed/{}", path), label, move || request.execute());
+                self.viewed_tasks.push(ViewedTask { path, is_viewed, task });
+            },
+            Err(error) => self.report_error(error),
+        }
+    }
+
+    fn change_comment(&mut self, change: CommentChange, request: Result<GqlRequest, Error>) {
+        let request = if self.offline { Err(Error::Offline("change comments".to_string())) } else { request };
+        match request {
+            Ok(mut request) => {
+                let (key, label) = match &change {
+                    CommentChange::Send { target: CommentTarget::Edit { comment_id, .. }, .. } => (format!("update/{}", comment_id), "Updating the comment"),
+                    CommentChange::Send { target: CommentTarget::Reply { thread_id }, .. } => (format!("reply/{}", thread_id), "Sending the reply"),
+                    CommentChange::Send { target: CommentTarget::NewComment { subject_id }, .. } => (format!("comment/{}", subject_id), "Sending the comment"),
+                    CommentChange::Delete { comment_id } => (format!("delete/{}", comment_id), "Deleting the comment"),
+                };
+                // Deletions of the same comment are one request, sent texts never are
+                let task = match &change {
+                    CommentChange::Delete { .. } => self.task_manager.post(&key, label, move || request.execute()),
+                    CommentChange::Send { .. } => self.task_manager.post_unique(&key, label, move || request.execute()),
+                };
+                self.comment_tasks.push(CommentTask { change, task });
+            },
+            Err(error) => self.comment_change_failed(change, error),
+        }
+    }
+
+    fn comment_change_failed(&mut self, change: CommentChange, error: Error) {
+        if let CommentChange::Send { target, body } = change {
+            self.screen.comment_not_sent(&target, body);
+        }
+        self.report_error(error);
+    }
+
+    fn open_editor(&mut self, target: CommentTarget, text: &str, context: &str) {
+        match editor::write_draft(text, context) {
+            Ok(path) => {
+                self.app_events_sender.send(AppEvent::RunInTerminal(editor::editor_command(&path))).unwrap();
+                self.draft = Some((path, target));
+            },
+            Err(error) => {
+                self.screen.comment_not_sent(&target, text.to_string());
+                self.report_error(error);
+            },
+        }
+    }
+
+    fn set_conversation(&mut self, conversation: PrConversation) {
+        self.head = conversation.head.clone();
+        self.pr_id = conversation.id.clone();
+        if self.diff_source == DiffSource::Local {
+            self.load_local_diff(&conversation);
+        }
+        self.load_interdiff(&conversation);
+        self.screen.set_conversation(conversation);
+    }
+
+    // Replies sent from ghterm count as a review of the head they were sent on, the later
+    // of them and the viewer's last review on GitHub is what the interdiff starts from
+    fn load_interdiff(&mut self, conversation: &PrConversation) {
+        let number = match self.number {
+            Some(number) => number,
+            None => return,
+        };
+        self.threads = conversation.threads().filter_map(ThreadSummary::new).collect();
+        let replied = self.cache.load_reviewed_head(number)
+            .map(|entry| ReviewedHead { sha: entry.data.trim().to_string(), timestamp: entry.timestamp });
+        let reviewed = match (conversation.last_review.clone(), replied) {
+            (Some(review), Some(reply)) if reply.timestamp > review.timestamp => Some(reply),
+            (review, reply) => review.or(reply),
+        };
+        let (reviewed, head_sha, head) = match (reviewed, conversation.head_sha.clone(), conversation.head.clone()) {
+            (None, _, _) => return self.screen.interdiff_notice("You haven't reviewed this pull request yet"),
+            (Some(reviewed), Some(head_sha), Some(head)) => (reviewed.sha, head_sha, head),
+            _ => return self.screen.interdiff_failed(&Error::NotCached("head of the pull request".to_string()).to_string()),
+        };
+        if reviewed == head_sha {
+            return self.screen.interdiff_notice("Nothing was pushed since your last review");
+        }
+
+        let range = (reviewed, head_sha);
+        if self.interdiff_range.as_ref() == Some(&range) {
+            return;
+        }
+        let repository = match Repository::discover() {
+            Ok(repository) => repository,
+            Err(error) => return self.screen.interdiff_failed(&error.to_string()),
+        };
+        self.interdiff_range = Some(range.clone());
+        let (from, to) = range;
+        let label = format!("Diffing #{} since your review", number);
+        let task = self.task_manager.post(&format!("interdiff/{}", number), &label, move || repository.interdiff(&from, &to, &head));
+        self.interdiff_task = Some(task);
+    }
+
+    fn checkout(&mut self, in_worktree: bool, confirmed: bool) {
+        let (head, number) = match (self.head.clone(), self.number) {
+            (Some(head), Some(number)) => (head, number),
+            _ => return,
+        };
+        let label = format!("Preparing to check out #{}", number);
+        let task = self.task_manager.post(&checkout_key(number, in_worktree), &label, move || {
+            let repository = Repository::discover()?;
+            let checkout = repository.plan_checkout(number, &head, in_worktree)?;
+            let is_dirty = checkout.worktree.is_none() && repository.is_dirty()?;
+            Ok((repository, checkout, is_dirty))
+        });
+        self.checkout_plan_task = Some((confirmed, task));
+    }
+
+    // Switching the branch of the clone with uncommitted changes has to be confirmed, a worktree leaves them alone
+    fn checkout_planned(&mut self, confirmed: bool, plan: CheckoutPlan) {
+        let (repository, checkout, is_dirty) = plan;
+        let in_worktree = checkout.worktree.is_some();
+        if is_dirty && !confirmed {
+            let question = format!("The working tree has uncommitted changes. Check out {} anyway?", checkout.branch);
+            self.screen.confirm(question, MainScreenEvent::Checkout { in_worktree });
+            return;
+        }
+
+        let (task_repository, task_checkout) = (repository.clone(), checkout.clone());
+        let label = format!("Fetching {}", checkout.branch);
+        let key = checkout_key(self.number.unwrap_or_default(), in_worktree);
+        let task = self.task_manager.post(&key, &label, move || task_repository.fetch(&task_checkout));
+        self.checkout_task = Some(CheckoutTask { repository, checkout, step: CheckoutStep::Fetch, task });
+    }
+
+    fn checkout_step_finished(&mut self, finished: CheckoutTask, result: Result<String, Error>) {
+        let CheckoutTask { repository, checkout, step, .. } = finished;
+        match (step, result) {
+            (CheckoutStep::Fetch, Ok(commit)) => {
+                let (task_repository, task_checkout) = (repository.clone(), checkout.clone());
+                let label = format!("Checking out {}", checkout.branch);
+                let key = checkout_key(self.number.unwrap_or_default(), checkout.worktree.is_some());
+                let task = self.task_manager.post(&key, &label,
+                    move || task_repository.check_out(&task_checkout, &commit).map(|_| commit));
+                self.checkout_task = Some(CheckoutTask { repository, checkout, step: CheckoutStep::CheckOut, task });
+            },
+            (CheckoutStep::CheckOut, Ok(_)) => {
+                let notice = match checkout.worktree.as_ref() {
+                    Some(path) => format!("Checked out {} in {}", checkout.branch, path.display()),
+                    None => format!("Checked out {}", checkout.branch),
+                };
+                self.app_events_sender.send(AppEvent::Notice(notice)).unwrap();
+            },
+            (_, Err(error)) => self.report_error(error),
+        }
+    }
+
+    fn open_file(&self, file_name: &str, positions: &[(String, usize)], fallback_line: usize) -> Result<(), Error> {
+        let path = git::toplevel()?.join(file_name);
+        if !path.exists() {
+            return Err(Error::Other(format!("{} isn't in the working tree", file_name)));
+        }
+        let line = positions.iter()
+            .find_map(|(commit, line)| git::working_tree_line(commit, file_name, *line))
+            .unwrap_or(fallback_line);
+        let cmd = editor::open_file_command(self.config.open_command.as_deref(), &path, line);
+        self.app_events_sender.send(AppEvent::RunInTerminal(cmd)).unwrap();
+        Ok(())
+    }
+
+    fn report_error(&self, error: Error) {
+        self.app_events_sender.send(AppEvent::Error(error)).unwrap();
+    }
+}
+
+impl<'a, B: Backend> ScreenHandler<B> for MainScreenHandler<'a> {
+    fn update(&mut self, task: TaskId) {
+        if let Some(res) = self.conversation_task.as_ref().filter(|t| t.id() == task).and_then(|t| t.poll()) {
+            self.conversation_task = None;
+            let res = res.and_then(|json| pr::parse_conversation(&json).map(|c| (c, json)));
             match res { 
-                Ok(json) => {
-                    let conversation = pr::parse_conversation(json);
-                    self.screen.set_conversation(conversation);
+                Ok((conversation, json)) => {
+                    if let (Some(head_sha), Some(number)) = (pr::head_sha(&json), self.number) {
+                        self.cache.store_conversation(number, &head_sha, &json);
+                        self.head_sha = Some(head_sha);
+                        if let Some(diff) = self.uncached_diff.take() {
+                            self.store_diff(diff);
+                        }
+                    }
+                    self.set_conversation(conversation);
+                    self.conversation_refreshed = true;
                 },
-                Err(error) => self.app_events_sender.send(AppEvent::Error(error.to_string())).unwrap()
+                Err(error) => self.conversation_failed(error)
             }
+            self.update_staleness();
         }
 
-        if let Some(diff) = self.diff_task.poll() {
+        if let Some(diff) = self.diff_task.as_ref().filter(|t| t.id() == task).and_then(|t| t.poll()) {
+            self.diff_task = None;
             match diff {
                 Ok(diff) => {
+                    if self.diff_source == DiffSource::Api {
+                        self.store_diff(diff.clone());
+                    }
                     let changelist = ChangeList::new(diff);
                     self.screen.set_changelist(changelist);
+                    self.diff_refreshed = true;
+                },
+                Err(error) => self.diff_failed(error)
+            }
+            self.update_staleness();
+        }
+
+        if let Some(res) = self.commits_task.as_ref().filter(|t| t.id() == task).and_then(|t| t.poll()) {
+            self.commits_task = None;
+            match res {
+                Ok(commits) => {
+                    self.screen.show_timeline();
+                    self.screen.set_conversation(PrConversation::from_commits(commits));
+                },
+                Err(error) => self.conversation_failed(error),
+            }
+        }
+
+        if let Some(res) = self.viewed_files_task.as_ref().filter(|t| t.id() == task).and_then(|t| t.poll()) {
+            self.viewed_files_task = None;
+            match res.and_then(|pages| pr::parse_viewed_files(&pages)) {
+                Ok(viewed_files) => self.screen.set_viewed_files(viewed_files),
+                Err(error) => self.report_error(error),
+            }
+        }
+
+        if let Some(res) = self.interdiff_task.as_ref().filter(|t| t.id() == task).and_then(|t| t.poll()) {
+            self.interdiff_task = None;
+            match res {
+                Ok(diff) => {
+                    let changelist = ChangeList::new(diff);
+                    let threads = self.threads.iter()
+                        .filter(|thread| changelist.touches(&thread.file_name, thread.line))
+                        .cloned()
+                        .collect();
+                    self.screen.set_interdiff(changelist, threads);
+                },
+                Err(error) => {
+                    // Tried again with the next conversation
+                    self.interdiff_range = None;
+                    self.screen.interdiff_failed(&error.to_string());
                 },
-                Err(error) => self.app_events_sender.send(AppEvent::Error(error.to_string())).unwrap()
             }
         }
+
+        if let Some(res) = self.references_task.as_ref().filter(|t| t.id() == task).and_then(|t| t.poll()) {
+            self.references_task = None;
+            match res.and_then(|json| pr::parse_references(&json)) {
+                Ok(references) => self.screen.set_references(references),
+                Err(error) => self.report_error(error),
+            }
+        }
+
+        if let Some(res) = self.checkout_plan_task.as_ref().filter(|(_, t)| t.id() == task).and_then(|(_, t)| t.poll()) {
+            if let Some((confirmed, _)) = self.checkout_plan_task.take() {
+                match res {
+                    Ok(plan) => self.checkout_planned(confirmed, plan),
+                    Err(error) => self.report_error(error),
+                }
+            }
+        }
+
+        if let Some(res) = self.checkout_task.as_ref().filter(|t| t.task.id() == task).and_then(|t| t.task.poll()) {
+            if let Some(finished) = self.checkout_task.take() {
+                self.checkout_step_finished(finished, res);
+            }
+        }
+
+        if let Some(index) = self.reaction_tasks.iter().position(|r| r.task.id() == task) {
+            if let Some(res) = self.reaction_tasks[index].task.poll() {
+                let reaction = self.reaction_tasks.remove(index);
+                match res.and_then(|json| pr::parse_mutation(&json)) {
+                    Ok(()) => self.screen.set_reaction(&reaction.comment_id, reaction.content, reaction.is_added),
+                    Err(error) => self.report_error(error),
+                }
+            }
+        }
+
+        if let Some(index) = self.viewed_tasks.iter().position(|v| v.task.id() == task) {
+            if let Some(res) = self.viewed_tasks[index].task.poll() {
+                let viewed = self.viewed_tasks.remove(index);
+                match res.and_then(|json| pr::parse_mutation(&json)) {
+                    Ok(()) => self.screen.set_viewed(&viewed.path, viewed.is_viewed),
+                    Err(error) => self.report_error(error),
+                }
+            }
+        }
+
+        if let Some(index) = self.comment_tasks.iter().position(|c| c.task.id() == task) {
+            if let Some(res) = self.comment_tasks[index].task.poll() {
+                let change = self.comment_tasks.remove(index).change;
+                match (res.and_then(|json| pr::parse_mutation(&json)), change) {
+                    // New comments get their ids and positions from the conversation fetched again
+                    (Ok(()), CommentChange::Send { target, body }) => {
+                        let is_new = !matches!(target, CommentTarget::Edit { .. });
+                        if let (CommentTarget::Reply { .. }, Some(number), Some(head_sha)) = (&target, self.number, self.head_sha.as_ref()) {
+                            self.cache.store_reviewed_head(number, head_sha);
+                        }
+                        self.screen.comment_sent(&target, body);
+                        if is_new {
+                            self.fetch();
+                        }
+                    },
+                    (Ok(()), CommentChange::Delete { comment_id }) => self.screen.comment_deleted(&comment_id),
+                    (Err(error), change) => self.comment_change_failed(change, error),
+                }
+            }
+        }
+
+        let edits = self.edits_task.as_ref()
+            .filter(|(_, t)| t.id() == task)
+            .and_then(|(comment_id, t)| t.poll().map(|res| (comment_id.clone(), res)));
+        if let Some((comment_id, res)) = edits {
+            self.edits_task = None;
+            match res.and_then(|json| pr::parse_comment_edits(&json)) {
+                Ok(edits) => self.screen.set_edits(&comment_id, Ok(edits)),
+                Err(error) => {
+                    self.screen.set_edits(&comment_id, Err(error.to_string()));
+                    self.report_error(error);
+                },
+            }
+        }
+    }
+
+    fn keymap_context(&self) -> KeymapContext {
+        self.screen.keymap_context()
+    }
+
+    // An empty draft means the comment was abandoned
+    fn terminal_command_finished(&mut self, result: Result<(), Error>) {
+        // Programs other than the editor of a draft only report failures
+        let (path, target) = match self.draft.take() {
+            Some(draft) => draft,
+            None => {
+                if let Err(error) = result {
+                    self.report_error(error);
+                }
+                return;
+            },
+        };
+        match result.and_then(|_| editor::read_draft(&path)) {
+            Ok(body) if body.is_empty() => self.screen.comment_not_sent(&target, body),
+            Ok(body) => {
+                let request = self.client.send_comment(&target, &body);
+                self.change_comment(CommentChange::Send { target, body }, request);
+            },
+            Err(error) => {
+                let _ = std::fs::remove_file(&path);
+                self.comment_change_failed(CommentChange::Send { target, body: String::new() }, error);
+            },
+        }
     }
 }
 
 impl<'a, B: Backend> DrawableScreen<B> for MainScreenHandler<'a> {
-    fn draw(&self, frame: &mut Frame<B>) {
-        self.screen.draw(frame);
+    fn draw(&self, frame: &mut Frame<B>, area: tui::layout::Rect) {
+        self.screen.draw(frame, area);
     }
 }
 
 impl<'a> InteractableScreen for MainScreenHandler<'a> {
-    fn validate_input(&self, input: termion::event::Key) -> bool {
-        self.screen.validate_input(input)
+    fn validate_input(&self, input: Action) -> bool {
+        let can_checkout = !self.offline && self.head.is_some() &&
+            self.checkout_plan_task.is_none() && self.checkout_task.is_none();
+        let can_refresh = !self.offline || matches!(self.diff_source, DiffSource::Range(_));
+        (input == Action::Refresh && can_refresh) ||
+            (matches!(input, Action::CheckoutBranch | Action::CheckoutWorktree) && can_checkout) ||
+            self.screen.validate_input(input)
     }
 
-    fn process_input(&mut self, input: termion::event::Key) {
-        self.screen.process_input(input);
+    fn process_input(&mut self, input: Action, count: usize) {
+        match input {
+            Action::Refresh => self.fetch(),
+            Action::CheckoutBranch => self.checkout(false, false),
+            Action::CheckoutWorktree => self.checkout(true, false),
+            _ => self.screen.process_input(input, count),
+        }
+        self.process_screen_events();
+    }
+
+    fn process_text_input(&mut self, key: Key) {
+        self.screen.process_text_input(key);
+    }
+
+    fn process_paste(&mut self, text: &str) {
+        self.screen.process_paste(text);
+    }
+
+    fn hides_action(&self, input: Action) -> bool {
+        self.screen.hides_action(input)
     }
 }
 
 impl<'a, B: Backend> ApplicationScreen<B> for MainScreenHandler<'a> {
 }
+
+// Checking out the branch and making a worktree are different tasks
+fn checkout_key(number: u32, in_worktree: bool) -> String {
+    let mode = if in_worktree { "worktree" } else { "branch" };
+    format!("checkout/{}/{}", number, mode)
+}
@@ -0,0 +1,191 @@
+use super::theme::Theme;
+
+use tui::{
+    style::{Modifier, Style},
+    text::{Span, Spans},
+};
+
+// Comment bodies with the markdown GitHub comments use the most: headings, emphasis,
+// code, quotes, lists, links, @mentions and #references. Everything else stays as it is
+pub fn markdown_text<'a>(body: &'a str, theme: &Theme) -> Vec<Spans<'a>> {
+    let mut text = vec![];
+    let mut is_code_block = false;
+    for line in body.lines() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") {
+            is_code_block = !is_code_block;
+            text.push(Spans::from(Span::styled(line, theme.dimmed)));
+        } else if is_code_block {
+            text.push(Spans::from(Span::styled(line, theme.code)));
+        } else if let Some(heading) = heading(trimmed) {
+            text.push(Spans::from(inline_spans(heading, theme.title, theme)));
+        } else if let Some(quote) = trimmed.strip_prefix('>') {
+            let mut spans = vec![Span::styled("│ ", theme.dimmed)];
+            spans.extend(inline_spans(quote.trim_start(), theme.dimmed, theme));
+            text.push(Spans::from(spans));
+        } else if let Some(item) = ["- ", "* ", "+ "].iter().find_map(|bullet| trimmed.strip_prefix(bullet)) {
+            let indent = &line[..line.len() - trimmed.len()];
+            let mut spans = vec![Span::raw(indent), Span::styled("• ", theme.dimmed)];
+            spans.extend(inline_spans(item, theme.text, theme));
+            text.push(Spans::from(spans));
+        } else {
+            text.push(Spans::from(inline_spans(line, theme.text, theme)));
+        }
+    }
+    text
+}
+
+// The text as a markdown quote, followed by an empty line to reply under
+pub fn quote(body: &str) -> String {
+    let mut quoted = String::new();
+    for line in body.trim_end().lines() {
+        quoted.push('>');
+        if !line.is_empty() {
+            quoted.push(' ');
+            quoted.push_str(line);
+        }
+        quoted.push('\n');
+    }
+    quoted.push('\n');
+    quoted
+}
+
+fn heading(line: &str) -> Option<&str> {
+    let level = line.chars().take_while(|c| *c == '#').count();
+    if (1..=6).contains(&level) {
+        line[level..].strip_prefix(' ')
+    } else {
+        None
+    }
+}
+
+// Spans of a line, split where the emphasis changes
+struct Inline<'a> {
+    line: &'a str,
+    spans: Vec<Span<'a>>,
+    // Start of the text that isn't in a span yet
+    start: usize,
+    base: Style,
+    is_bold: bool,
+    is_italic: bool,
+}
+
+impl<'a> Inline<'a> {
+    fn style(&self) -> Style {
+        let mut style = self.base;
+        if self.is_bold {
+            style = style.add_modifier(Modifier::BOLD);
+        }
+        if self.is_italic {
+            style = style.add_modifier(Modifier::ITALIC);
+        }
+        style
+    }
+
+    fn flush(&mut self, end: usize) {
+        if self.start < end {
+            self.spans.push(Span::styled(&self.line[self.start..end], self.style()));
+        }
+    }
+
+    // The text from `from` to `to` gets the style, the text before it is flushed
+    fn styled(&mut self, from: usize, text: &'a str, to: usize, style: Style) {
+        self.flush(from);
+        self.spans.push(Span::styled(text, style));
+        self.start = to;
+    }
+}
+
+fn inline_spans<'a>(line: &'a str, base: Style, theme: &Theme) -> Vec<Span<'a>> {
+    let mut inline = Inline { line, spans: vec![], start: 0, base, is_bold: false, is_italic: false };
+    let is_word = |c: char| c.is_alphanumeric() || c == '_' || c == '-';
+    let mut index = 0;
+    while let Some(c) = line[index..].chars().next() {
+        let rest = &line[index..];
+        let previous = line[..index].chars().next_back();
+        let next = rest[c.len_utf8()..].chars().next();
+        let is_word_start = !previous.is_some_and(char::is_alphanumeric);
+
+        if c == '`' {
+            if let Some(end) = rest[1..].find('`') {
+                inline.styled(index, &rest[1..end + 1], index + end + 2, theme.code);
+                index += end + 2;
+                continue;
+            }
+        } else if rest.starts_with("**") {
+            inline.flush(index);
+            inline.is_bold = !inline.is_bold;
+            index += 2;
+            inline.start = index;
+            continue;
+        } else if c == '*' || (c == '_' && (is_word_start || !next.is_some_and(char::is_alphanumeric))) {
+            inline.flush(index);
+            inline.is_italic = !inline.is_italic;
+            index += 1;
+            inline.start = index;
+            continue;
+        } else if c == '[' {
+            let link = rest.find("](").and_then(|middle| rest[middle..].find(')').map(|end| (middle, middle + end)));
+            if let Some((middle, end)) = link {
+                inline.styled(index, &rest[1..middle], index + end + 1, theme.link);
+                index += end + 1;
+                continue;
+            }
+        } else if (c == '@' || c == '#') && is_word_start {
+            let length = rest[1..].find(|c: char| !is_word(c)).unwrap_or(rest.len() - 1);
+            let name = &rest[1..length + 1];
+            let is_reference = if c == '@' { !name.is_empty() } else { !name.is_empty() && name.chars().all(|c| c.is_ascii_digit()) };
+            if is_reference {
+                inline.styled(index, &rest[..length + 1], index + length + 1, theme.link);
+                index += length + 1;
+                continue;
+            }
+        }
+        index += c.len_utf8();
+    }
+    inline.flush(line.len());
+    inline.spans
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn contents<'a>(spans: &[Span<'a>]) -> Vec<(String, Style)> {
+        spans.iter().map(|s| (s.content.to_string(), s.style)).collect()
+    }
+
+    #[test]
+    fn inline_markup() {
+        let theme = Theme::default();
+        let text = Style::default();
+        let spans = inline_spans("a **b** `c_d` see [docs](http://x) @bob #12 snake_case", text, &theme);
+        assert_eq!(contents(&spans), vec![
+            ("a ".to_string(), text),
+            ("b".to_string(), text.add_modifier(Modifier::BOLD)),
+            (" ".to_string(), text),
+            ("c_d".to_string(), theme.code),
+            (" see ".to_string(), text),
+            ("docs".to_string(), theme.link),
+            (" ".to_string(), text),
+            ("@bob".to_string(), theme.link),
+            (" ".to_string(), text),
+            ("#12".to_string(), theme.link),
+            (" snake_case".to_string(), text),
+        ]);
+    }
+
+    #[test]
+    fn blocks() {
+        let theme = Theme::default();
+        let text = markdown_text("## Title\n> quoted\n- item\n```\n# not a heading\n```", &theme);
+        let lines: Vec<String> = text.iter().map(|spans| spans.0.iter().map(|s| s.content.as_ref()).collect()).collect();
+        assert_eq!(lines, vec!["Title", "│ quoted", "• item", "```", "# not a heading", "```"]);
+    }
+
+    #[test]
+    fn quoting() {
+        assert_eq!(quote("Why?\n\nSee above\n"), "> Why?\n>\n> See above\n\n");
+    }
+}
@@ -0,0 +1,69 @@
+use crate::backend::pr::{PrComment, ReactionContent};
+use super::theme::Theme;
+
+use tui::{
+    buffer::Buffer,
+    layout::Rect,
+    text::{Span, Spans},
+    widgets::{Block, Borders, Clear, Paragraph, Widget},
+};
+
+pub fn emoji(content: ReactionContent) -> &'static str {
+    match content {
+        ReactionContent::ThumbsUp => "👍",
+        ReactionContent::ThumbsDown => "👎",
+        ReactionContent::Laugh => "😄",
+        ReactionContent::Hooray => "🎉",
+        ReactionContent::Confused => "😕",
+        ReactionContent::Heart => "❤️",
+        ReactionContent::Rocket => "🚀",
+        ReactionContent::Eyes => "👀",
+    }
+}
+
+// Reactions left on a comment with their counts, e.g. 👍 3 🎉 1
+pub fn reaction_counts<'a>(comment: &PrComment, theme: &Theme) -> Vec<Span<'a>> {
+    comment.reactions.iter()
+        .flat_map(|r| {
+            let style = if r.viewer_has_reacted { theme.title } else { theme.dimmed };
+            [Span::styled(format!("{} {}", emoji(r.content), r.count), style), Span::raw(" ")]
+        })
+        .collect()
+}
+
+// Popup with all the reactions, the ones the viewer has left are highlighted
+pub struct ReactionPicker<'a> {
+    comment: &'a PrComment,
+    selected: usize,
+    theme: &'a Theme,
+}
+
+impl<'a> ReactionPicker<'a> {
+    pub fn new(comment: &'a PrComment, selected: usize, theme: &'a Theme) -> Self {
+        ReactionPicker { comment, selected, theme }
+    }
+}
+
+const CELL_WIDTH: u16 = 4;
+
+impl<'a> Widget for ReactionPicker<'a> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let width = (ReactionContent::ALL.len() as u16 * CELL_WIDTH + 2).min(area.width);
+        let height = 3.min(area.height);
+        let popup = Rect::new(area.x + (area.width - width) / 2, area.y + (area.height - height) / 2, width, height);
+
+        let cells: Vec<Span> = ReactionContent::ALL.iter().enumerate()
+            .map(|(index, content)| {
+                let style = if self.comment.has_reacted(*content) { self.theme.title } else { self.theme.text };
+                let style = if index == self.selected { style.patch(self.theme.selection) } else { style };
+                Span::styled(format!(" {} ", emoji(*content)), style)
+            })
+            .collect();
+
+        Clear.render(popup, buf);
+        Paragraph::new(Spans::from(cells))
+            .block(Block::default().borders(Borders::ALL).border_style(self.theme.border)
+                   .title(Span::styled("React", self.theme.title)))
+            .render(popup, buf);
+    }
+}
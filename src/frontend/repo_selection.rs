@@ -4,44 +4,78 @@ use std::ops::DerefMut;
 use super::screen::*;
 
 use super::util::*;
+use super::loading::{LoadState, Placeholder};
 use tui::{
     backend::Backend,
-    style::{Style, Modifier},
+    text::{Span, Spans},
     widgets::{Block, Borders, ListItem, List},
-    layout::{Layout, Direction, Constraint},
+    layout::{Layout, Direction, Constraint, Rect},
     Frame,
 };
 
 use crate::backend::pr::PrHeader;
+use crate::config::Config;
+use chrono::{DateTime, Local};
+use std::rc::Rc;
 use crate::app::events::AppEvent;
 
-use termion::event::Key;
+use super::keymap::Action;
 
 pub struct RepoSelectionScreen  {
     event_sender: mpsc::Sender<AppEvent>,
     prs: StatefulList<PrHeader>,
+    stale_since: Option<DateTime<Local>>,
+    load_state: LoadState,
+    config: Rc<Config>,
 }
 
 impl RepoSelectionScreen {
-    pub fn new(event_sender: mpsc::Sender<AppEvent>) -> Self {
-        RepoSelectionScreen { event_sender, prs: StatefulList::new() }
+    pub fn new(event_sender: mpsc::Sender<AppEvent>, config: Rc<Config>) -> Self {
+        RepoSelectionScreen { event_sender, prs: StatefulList::new(), stale_since: None, load_state: LoadState::loading(), config }
     }
 
     pub fn set_pr_list(&mut self, prs: Vec<PrHeader>) {
-        self.prs.items.extend(prs);
+        let selected_number = self.prs.get_selected().map(|pr| pr.number);
+        self.prs.items = prs;
+        self.load_state = LoadState::Loaded;
+        self.event_sender.send(AppEvent::ScreenRepaint).unwrap();
         if !self.prs.items.is_empty() {
-            self.prs.select(0);
+            let selected_index = selected_number
+                .and_then(|n| self.prs.items.iter().position(|pr| pr.number == n))
+                .unwrap_or(0);
+            self.prs.select(selected_index);
+        }
+    }
+
+    // The list that is already shown is kept when loading it again fails or starts over
+    pub fn start_loading(&mut self) {
+        if !self.load_state.is_loaded() {
+            self.load_state = LoadState::loading();
             self.event_sender.send(AppEvent::ScreenRepaint).unwrap();
         }
     }
 
+    pub fn load_failed(&mut self, error: &str) {
+        if !self.load_state.is_loaded() {
+            self.load_state = LoadState::Failed(error.to_string());
+            self.event_sender.send(AppEvent::ScreenRepaint).unwrap();
+        }
+    }
+
+    pub fn set_stale_since(&mut self, stale_since: Option<DateTime<Local>>) {
+        self.stale_since = stale_since;
+        self.event_sender.send(AppEvent::ScreenRepaint).unwrap();
+    }
+
     fn update_selection(&mut self, delta: i32) {
         if delta == 0 || self.prs.items.is_empty() { return; }
 
-        if delta > 0 {
-            self.prs.next();
-        } else {
-            self.prs.previous();
+        for _ in 0..delta.abs() {
+            if delta > 0 {
+                self.prs.next();
+            } else {
+                self.prs.previous();
+            }
         }
 
         self.event_sender.send(AppEvent::ScreenRepaint).unwrap();
@@ -50,12 +84,12 @@ impl RepoSelectionScreen {
 
 impl<B: Backend> DrawableScreen<B> for RepoSelectionScreen  {
 
-    fn draw (&self, frame: &mut Frame<B>) {
+    fn draw (&self, frame: &mut Frame<B>, area: Rect) {
 
         let prs = &self.prs.items;
-        let size = frame.size();
-        let box_height = if !prs.is_empty() { prs.len().min(6) as u16 + 2 } else { 3 };
-        let margins_size = (size.height - box_height) / 2;
+        let size = area;
+        let box_height = if !prs.is_empty() { prs.len().min(6) as u16 + 2 } else { 5 };
+        let margins_size = size.height.saturating_sub(box_height) / 2;
         let popup_layout = Layout::default()
             .direction(Direction::Vertical)
             .constraints(
@@ -87,36 +121,50 @@ impl<B: Backend> DrawableScreen<B> for RepoSelectionScreen  {
                 .map(|pr| ListItem::new(format!("#{} {}", pr.number, pr.title)))
                 .collect();
 
+        let theme = &self.config.theme;
+        let mut title = vec![Span::styled("Select pull request", theme.title)];
+        if let Some(timestamp) = self.stale_since {
+            title.push(Span::styled(format!(" (stale since {})", timestamp.format("%Y-%m-%d %H:%M")), theme.dimmed));
+        }
+        let block = Block::default().borders(Borders::ALL).border_style(theme.border).title(Spans::from(title));
+        if prs.is_empty() {
+            let placeholder = self.load_state.placeholder("pull requests", theme)
+                .unwrap_or_else(|| Placeholder::empty("No open pull requests", theme));
+            frame.render_widget(placeholder.block(block), popup_layout);
+            return;
+        }
+
         let list = List::new(list_items)
-            .block(Block::default().borders(Borders::ALL).title("Select pull request"))
-            .highlight_style(Style::default().add_modifier(Modifier::BOLD));
+            .block(block)
+            .style(theme.text)
+            .highlight_style(theme.selection);
 
         frame.render_stateful_widget(list, popup_layout, self.prs.state.borrow_mut().deref_mut());
     }
 }
 
 impl InteractableScreen for RepoSelectionScreen {
-    fn validate_input(&self, input: Key) -> bool {
+    fn validate_input(&self, input: Action) -> bool {
         if self.prs.items.is_empty() {
             return false;
         }
 
-        match input {
-            Key::Char('j') | Key::Char('k') | Key::Char('\n') => true,
-            _ => false,
-        }
+        matches!(input, Action::NextItem | Action::PreviousItem | Action::FirstItem | Action::LastItem | Action::Select)
     }
 
-    fn process_input(&mut self, input: Key) {
+    fn process_input(&mut self, input: Action, count: usize) {
         match input {
-            Key::Char('j') => self.update_selection(1), 
-            Key::Char('k') => self.update_selection(-1),
-            Key::Char('\n') => 
+            Action::NextItem => self.update_selection(count as i32), 
+            Action::PreviousItem => self.update_selection(-(count as i32)),
+            Action::FirstItem => self.prs.first(),
+            Action::LastItem => self.prs.last(),
+            Action::Select => 
                 if let Some(chosen_repo) = self.prs.get_selected() {
                     self.event_sender.send(AppEvent::RepoChosen(chosen_repo.number)).unwrap();
                 },
             _ => (),
         }
+        self.event_sender.send(AppEvent::ScreenRepaint).unwrap();
     }
 
 }
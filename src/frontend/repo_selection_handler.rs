@@ -1,63 +1,119 @@
 use crate::app::events::AppEvent;
 use crate::backend::task::*;
-use crate::backend::pr::{self, PrHeader};
+use crate::backend::pr;
 use crate::backend::gh::GhClient;
+use crate::backend::cache::Cache;
 use crate::error::Error;
+use crate::config::Config;
 use json::JsonValue;
-use termion::event::Key;
 use tui::backend::Backend;
 use tui::Frame;
 
 use super::screen::*;
 use super::repo_selection::RepoSelectionScreen;
+use super::keymap::{Action, KeymapContext};
 
 use std::sync::mpsc;
+use std::rc::Rc;
 
-pub struct RepoSelectionHandler {
+pub struct RepoSelectionHandler<'a> {
     screen: RepoSelectionScreen,
     event_sender: mpsc::Sender<AppEvent>,
-    task_handle: TaskHandle<Result<JsonValue, Error>>,
+    task_handle: Option<TaskHandle<Result<JsonValue, Error>>>,
+    task_manager: TaskManager,
+    client: &'a GhClient,
+    cache: Cache,
+    offline: bool,
 }
 
-impl RepoSelectionHandler { 
-    pub fn new (event_sender: mpsc::Sender<AppEvent>, task_manager: &mut TaskManager, client: &mut GhClient) -> Self {
-        let mut request = client.pr_list().expect("Wasn't able to fetch prs");
-        let task_handle = task_manager.post(move || request.execute());
-        let screen = RepoSelectionScreen::new(event_sender.clone());
-        RepoSelectionHandler {screen, event_sender, task_handle}
+impl<'a> RepoSelectionHandler<'a> { 
+    pub fn new (event_sender: mpsc::Sender<AppEvent>, task_manager: TaskManager, client: &'a GhClient, cache: Cache, offline: bool, config: Rc<Config>) -> Self {
+        let mut screen = RepoSelectionScreen::new(event_sender.clone(), config);
+        match cache.load_pr_list().map(|entry| (pr::list_prs(&entry.data), entry.timestamp)) {
+            Some((Ok(prs), timestamp)) => {
+                screen.set_pr_list(prs);
+                screen.set_stale_since(Some(timestamp));
+            },
+            Some((Err(error), _)) => {
+                screen.load_failed(&error.to_string());
+                event_sender.send(AppEvent::Error(error)).unwrap();
+            },
+            None if offline => {
+                let error = Error::NotCached("pull request list".to_string());
+                screen.load_failed(&error.to_string());
+                event_sender.send(AppEvent::Error(error)).unwrap();
+            },
+            None => (),
+        }
+
+        let mut handler = RepoSelectionHandler {screen, event_sender, task_handle: None, task_manager, client, cache, offline};
+        handler.fetch();
+        handler
+    }
+
+    fn fetch(&mut self) {
+        if self.offline {
+            return;
+        }
+
+        self.screen.start_loading();
+        match self.client.pr_list() {
+            Ok(mut request) => {
+                let task = self.task_manager.post("pr_list", "Loading pull requests", move || request.execute());
+                self.task_handle = Some(task);
+            },
+            Err(error) => self.load_failed(error),
+        }
+    }
+
+    fn load_failed(&mut self, error: Error) {
+        self.screen.load_failed(&error.to_string());
+        self.event_sender.send(AppEvent::Error(error)).unwrap();
     }
 }
 
-impl<B: Backend> ScreenHandler<B> for RepoSelectionHandler {
-    fn update(&mut self) {
-        match self.task_handle.poll() {
-            Some(ok) => match ok {
-                Ok(json) => {
-                    let prs = pr::list_prs(json);
-                    self.screen.set_pr_list(prs)
+impl<'a, B: Backend> ScreenHandler<B> for RepoSelectionHandler<'a> {
+    fn update(&mut self, task: TaskId) {
+        if let Some(result) = self.task_handle.as_ref().filter(|h| h.id() == task).and_then(|h| h.poll()) {
+            self.task_handle = None;
+            let prs = result.and_then(|json| {
+                let prs = pr::list_prs(&json)?;
+                self.cache.store_pr_list(&json);
+                Ok(prs)
+            });
+            match prs {
+                Ok(prs) => {
+                    self.screen.set_pr_list(prs);
+                    self.screen.set_stale_since(None);
                 },
-                Err(error) => self.event_sender.send(AppEvent::Error(error.to_string())).unwrap(),
+                Err(error) => self.load_failed(error),
             }
-            None => (),
         }
     }
+
+    fn keymap_context(&self) -> KeymapContext {
+        KeymapContext::PrList
+    }
 }
 
-impl InteractableScreen for RepoSelectionHandler {
-    fn validate_input(&self, b: Key) -> bool {
-        self.screen.validate_input(b)
+impl<'a> InteractableScreen for RepoSelectionHandler<'a> {
+    fn validate_input(&self, input: Action) -> bool {
+        (input == Action::Refresh && !self.offline) || self.screen.validate_input(input)
     }
 
-    fn process_input(&mut self, b: Key) {
-        self.screen.process_input(b);
+    fn process_input(&mut self, input: Action, count: usize) {
+        match input {
+            Action::Refresh => self.fetch(),
+            _ => self.screen.process_input(input, count),
+        }
     }
 }
 
-impl<B: Backend> DrawableScreen<B>  for RepoSelectionHandler  {
-    fn draw (&self, frame: &mut Frame<B>) {
-        self.screen.draw(frame);
+impl<'a, B: Backend> DrawableScreen<B>  for RepoSelectionHandler<'a>  {
+    fn draw (&self, frame: &mut Frame<B>, area: tui::layout::Rect) {
+        self.screen.draw(frame, area);
     }
 }
 
-impl<B: Backend> ApplicationScreen<B>  for RepoSelectionHandler  {
+impl<'a, B: Backend> ApplicationScreen<B>  for RepoSelectionHandler<'a>  {
 }
@@ -2,6 +2,9 @@ use std::io::Write;
 use std::iter::FromIterator;
 use termion::cursor::Goto;
 use termion::event::Key;
+use super::keymap::{Action, KeymapContext};
+use crate::backend::task::TaskId;
+use crate::error::Error;
 use tui::backend::Backend;
 use tui::Frame;
 
@@ -19,20 +22,30 @@ impl Rect {
     }
 }
 
+pub type ScreenId = u64;
+
 pub trait DrawableScreen<B: Backend>{
-    fn draw(&self, frame: &mut Frame<B>);
+    fn draw(&self, frame: &mut Frame<B>, area: tui::layout::Rect);
 }
 
 pub trait InteractableScreen {
-    fn validate_input(&self, input: Key) -> bool;
-    fn process_input(&mut self, input: Key);
+    fn validate_input(&self, input: Action) -> bool;
+    fn process_input(&mut self, input: Action, count: usize);
+    // Keys that aren't bound to actions while the screen is in a text input context
+    fn process_text_input(&mut self, _key: Key) {}
+    fn process_paste(&mut self, _text: &str) {}
+    // Actions left out of the help, they don't apply to what the screen shows
+    fn hides_action(&self, _input: Action) -> bool { false }
 }
 
 pub trait ApplicationScreen<B: Backend> : DrawableScreen<B> + InteractableScreen { 
 }
 
 pub trait ScreenHandler<B: Backend> : ApplicationScreen<B> {
-    fn update (&mut self);
+    fn update (&mut self, task: TaskId);
+    fn keymap_context(&self) -> KeymapContext;
+    // Result of a program the screen ran in place of the UI
+    fn terminal_command_finished(&mut self, _result: Result<(), Error>) {}
 }
 
 pub struct Screen {
@@ -0,0 +1,192 @@
+use std::cell::Cell;
+use tui::{
+    buffer::Buffer,
+    layout::Rect,
+    style::Style,
+    text::Spans,
+    widgets::{Block, Paragraph, Widget, Wrap},
+};
+use unicode_width::UnicodeWidthStr;
+
+// Vertical scroll position of a content that is taller than its viewport.
+// Sizes are only known while drawing, so they are updated from there.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ScrollState {
+    pub offset: u16,
+    pub content_height: u16,
+    pub viewport_height: u16,
+}
+
+impl ScrollState {
+    pub fn max_offset(&self) -> u16 {
+        self.content_height.saturating_sub(self.viewport_height)
+    }
+
+    pub fn is_scrollable(&self) -> bool {
+        self.content_height > self.viewport_height
+    }
+
+    pub fn scroll_by(&mut self, delta: i32) {
+        let offset = (self.offset as i32 + delta).clamp(0, self.max_offset() as i32);
+        self.offset = offset as u16;
+    }
+
+    pub fn scroll_pages(&mut self, pages: i32) {
+        let page = self.viewport_height.saturating_sub(1).max(1) as i32;
+        self.scroll_by(pages * page);
+    }
+
+    pub fn scroll_to_top(&mut self) {
+        self.offset = 0;
+    }
+
+    pub fn scroll_to_bottom(&mut self) {
+        self.offset = self.max_offset();
+    }
+
+    pub fn set_size(&mut self, content_height: u16, viewport_height: u16) {
+        self.content_height = content_height;
+        self.viewport_height = viewport_height;
+        self.offset = self.offset.min(self.max_offset());
+    }
+}
+
+// Number of rows the lines take when word wrapped to the width,
+// following the way `Paragraph` wraps them
+pub fn wrapped_height(lines: &[Spans], width: u16) -> u16 {
+    lines.iter()
+        .map(|line| {
+            let text: String = line.0.iter().map(|span| span.content.as_ref()).collect();
+            wrapped_line_count(&text, width as usize)
+        })
+        .sum::<usize>()
+        .min(u16::MAX as usize) as u16
+}
+
+fn wrapped_line_count(line: &str, width: usize) -> usize {
+    if width == 0 {
+        return 0;
+    }
+
+    let mut count = 1;
+    let mut current = 0;
+    for word in line.split_inclusive(' ') {
+        let word_width = word.trim_end().width();
+        if current > 0 && current + word_width > width {
+            count += 1;
+            current = 0;
+        }
+        if word_width > width {
+            count += (word_width - 1) / width;
+            current = word_width % width;
+        }
+        current += word.width();
+    }
+    count
+}
+
+// Text wrapped and scrolled with a scrollbar on the border of its block.
+// The scroll state is updated with the size of the text when it's drawn.
+pub struct ScrollableText<'a> {
+    text: Vec<Spans<'a>>,
+    block: Block<'a>,
+    style: Style,
+    scrollbar_style: Style,
+    scroll: &'a Cell<ScrollState>,
+}
+
+impl<'a> ScrollableText<'a> {
+    pub fn new(text: Vec<Spans<'a>>, scroll: &'a Cell<ScrollState>) -> Self {
+        ScrollableText { text, block: Block::default(), style: Style::default(), scrollbar_style: Style::default(), scroll }
+    }
+
+    pub fn block(mut self, block: Block<'a>) -> Self {
+        self.block = block;
+        self
+    }
+
+    pub fn style(mut self, style: Style) -> Self {
+        self.style = style;
+        self
+    }
+
+    pub fn scrollbar_style(mut self, style: Style) -> Self {
+        self.scrollbar_style = style;
+        self
+    }
+}
+
+impl<'a> Widget for ScrollableText<'a> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let inner = self.block.inner(area);
+        let mut state = self.scroll.get();
+        state.set_size(wrapped_height(&self.text, inner.width), inner.height);
+        self.scroll.set(state);
+
+        Paragraph::new(self.text)
+            .style(self.style)
+            .wrap(Wrap { trim: false })
+            .scroll((state.offset, 0))
+            .block(self.block)
+            .render(area, buf);
+        Scrollbar::new(state, self.scrollbar_style).render(area, buf);
+    }
+}
+
+// Thumb drawn over the right border of a block
+pub struct Scrollbar {
+    state: ScrollState,
+    style: Style,
+}
+
+impl Scrollbar {
+    pub fn new(state: ScrollState, style: Style) -> Self {
+        Scrollbar { state, style }
+    }
+}
+
+impl Widget for Scrollbar {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        if !self.state.is_scrollable() || area.width < 1 || area.height < 3 {
+            return;
+        }
+
+        let track = (area.height - 2) as u32;
+        let content = self.state.content_height as u32;
+        let thumb_height = (track * self.state.viewport_height as u32 / content).clamp(1, track);
+        let thumb_start = (track - thumb_height) * self.state.offset as u32 / self.state.max_offset() as u32;
+        let x = area.right() - 1;
+        for row in thumb_start..thumb_start + thumb_height {
+            buf.get_mut(x, area.top() + 1 + row as u16).set_symbol("┃").set_style(self.style);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn scrolling_is_clamped() {
+        let mut scroll = ScrollState::default();
+        scroll.set_size(30, 10);
+        scroll.scroll_pages(1);
+        assert_eq!(scroll.offset, 9);
+        scroll.scroll_by(100);
+        assert_eq!(scroll.offset, 20);
+        scroll.set_size(15, 10);
+        assert_eq!(scroll.offset, 5);
+        scroll.scroll_by(-100);
+        assert_eq!(scroll.offset, 0);
+    }
+
+    #[test]
+    fn wrapping_counts_rows() {
+        assert_eq!(wrapped_line_count("", 10), 1);
+        assert_eq!(wrapped_line_count("short line", 10), 1);
+        assert_eq!(wrapped_line_count("short line here", 10), 2);
+        assert_eq!(wrapped_line_count("a bit longer line", 10), 3);
+        assert_eq!(wrapped_line_count("abcdefghijklmnopqrstuvwxy", 10), 3);
+    }
+}
@@ -0,0 +1,115 @@
+use crate::error::Error;
+use crate::backend::task::{TaskProgress, TaskState};
+use super::theme::Theme;
+use super::loading::SPINNER_FRAMES;
+
+use tui::{
+    buffer::Buffer,
+    layout::{Alignment, Rect},
+    text::{Span, Spans},
+    widgets::{Paragraph, Widget},
+};
+
+pub struct StatusLine {
+    errors: Vec<Error>,
+    tasks: Vec<TaskProgress>,
+    notice: Option<String>,
+    spinner_frame: usize,
+}
+
+impl StatusLine {
+    pub fn new() -> Self {
+        StatusLine { errors: vec![], tasks: vec![], notice: None, spinner_frame: 0 }
+    }
+
+    pub fn update_task(&mut self, progress: TaskProgress) {
+        self.tasks.retain(|t| t.id != progress.id);
+        if matches!(progress.state, TaskState::Queued | TaskState::Running) {
+            self.tasks.push(progress);
+        }
+    }
+
+    // Advances the spinner, returns whether there is anything to animate
+    pub fn tick(&mut self) -> bool {
+        if self.tasks.is_empty() {
+            return false;
+        }
+        self.spinner_frame = (self.spinner_frame + 1) % SPINNER_FRAMES.len();
+        true
+    }
+
+    pub fn push_error(&mut self, error: Error) {
+        self.errors.push(error);
+    }
+
+    pub fn set_notice(&mut self, notice: String) {
+        self.notice = Some(notice);
+    }
+
+    // Returns whether there was a notice
+    pub fn clear_notice(&mut self) -> bool {
+        self.notice.take().is_some()
+    }
+
+    pub fn has_errors(&self) -> bool {
+        !self.errors.is_empty()
+    }
+
+    pub fn dismiss_error(&mut self) {
+        self.errors.pop();
+    }
+
+    pub fn clear_errors(&mut self) {
+        self.errors.clear();
+    }
+
+    pub fn widget<'a>(&'a self, theme: &'a Theme, hints: StatusHints<'a>) -> StatusLineWidget<'a> {
+        StatusLineWidget { status: self, theme, hints }
+    }
+}
+
+// Keys bound to the actions the status line mentions
+pub struct StatusHints<'a> {
+    pub help: &'a str,
+    pub retry: &'a str,
+    pub dismiss: &'a str,
+}
+
+pub struct StatusLineWidget<'a> {
+    status: &'a StatusLine,
+    theme: &'a Theme,
+    hints: StatusHints<'a>,
+}
+
+impl<'a> Widget for StatusLineWidget<'a> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let theme = self.theme;
+        match self.status.errors.last() {
+            Some(error) => {
+                let mut spans = vec![Span::styled(error.to_string().replace('\n', " "), theme.error)];
+                if self.status.errors.len() > 1 {
+                    spans.push(Span::styled(format!(" (+{} more)", self.status.errors.len() - 1), theme.error));
+                }
+                spans.push(Span::styled(format!("  {} retry  {} dismiss", self.hints.retry, self.hints.dismiss), theme.dimmed));
+                Paragraph::new(Spans::from(spans)).render(area, buf);
+            },
+            None => {
+                let mut running = self.status.tasks.iter().filter(|t| t.state == TaskState::Running);
+                let queued = self.status.tasks.iter().filter(|t| t.state == TaskState::Queued).count();
+                if let Some(task) = running.next_back().or_else(|| self.status.tasks.last()) {
+                    let mut text = format!("{} {}", SPINNER_FRAMES[self.status.spinner_frame], task.label);
+                    if self.status.tasks.len() > 1 {
+                        text.push_str(&format!(" (+{} more, {} queued)", self.status.tasks.len() - 1, queued));
+                    }
+                    Paragraph::new(Span::styled(text, theme.dimmed)).render(area, buf);
+                } else if let Some(notice) = self.status.notice.as_ref() {
+                    Paragraph::new(Span::styled(notice.as_str(), theme.text)).render(area, buf);
+                }
+
+                Paragraph::new(Span::styled(format!("{} help", self.hints.help), theme.dimmed))
+                    .alignment(Alignment::Right)
+                    .render(area, buf);
+            },
+        }
+    }
+}
@@ -0,0 +1,625 @@
+use std::cell::Cell;
+
+use crate::backend::pr::References;
+use super::keymap::Action;
+use super::markdown::markdown_text;
+use super::screen::InteractableScreen;
+use super::theme::Theme;
+use super::util::centered_rect;
+
+use termion::event::Key;
+use unicode_width::UnicodeWidthChar;
+
+use tui::{
+    buffer::Buffer,
+    layout::{Constraint, Direction, Layout, Rect},
+    style::Modifier,
+    text::Span,
+    widgets::{Block, Borders, Clear, Paragraph, Widget, Wrap},
+};
+
+const TAB_WIDTH: usize = 4;
+const UNDO_LIMIT: usize = 200;
+const MAX_COMPLETIONS: usize = 8;
+
+// Multi-line text with a cursor, undo history and completion of @mentions and #references.
+// Screens hand it the actions it validates and the keys typed in the text input context
+pub struct TextEditor {
+    lines: Vec<String>,
+    cursor: Cursor,
+    undo_stack: Vec<Snapshot>,
+    redo_stack: Vec<Snapshot>,
+    // Consecutive edits of the same kind are undone together
+    last_edit: Option<EditKind>,
+    // Text removed by the kill keys, inserted again by paste
+    kill_buffer: String,
+    references: References,
+    completion: Option<Completion>,
+    show_preview: bool,
+    // First visible row of the wrapped text, moved by the view to keep the cursor visible
+    offset: Cell<usize>,
+    // Stays open while the text is being sent, so it isn't lost if that fails
+    pub is_sending: bool,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct Cursor {
+    row: usize,
+    column: usize,
+}
+
+struct Snapshot {
+    lines: Vec<String>,
+    cursor: Cursor,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum EditKind {
+    Insert,
+    Delete,
+    Kill,
+}
+
+// Candidates for the @ or # word before the cursor
+struct Completion {
+    // Column of the @ or #
+    start: usize,
+    // The text that replaces the word and how it's shown
+    candidates: Vec<(String, String)>,
+    selected: usize,
+}
+
+impl TextEditor {
+    // The cursor starts at the end of the text
+    pub fn new(text: &str) -> Self {
+        let lines: Vec<String> = text.split('\n').map(String::from).collect();
+        let row = lines.len() - 1;
+        let column = lines[row].chars().count();
+        TextEditor {
+            lines,
+            cursor: Cursor { row, column },
+            undo_stack: vec![],
+            redo_stack: vec![],
+            last_edit: None,
+            kill_buffer: String::new(),
+            references: References::default(),
+            completion: None,
+            show_preview: false,
+            offset: Cell::new(0),
+            is_sending: false,
+        }
+    }
+
+    pub fn references(mut self, references: References) -> Self {
+        self.references = references;
+        self
+    }
+
+    pub fn text(&self) -> String {
+        self.lines.join("\n")
+    }
+
+    fn line(&self) -> Vec<char> {
+        self.lines[self.cursor.row].chars().collect()
+    }
+
+    fn line_length(&self, row: usize) -> usize {
+        self.lines[row].chars().count()
+    }
+
+    // Byte offset of a character of the cursor's line
+    fn byte_offset(&self, column: usize) -> usize {
+        let line = &self.lines[self.cursor.row];
+        line.char_indices().nth(column).map_or(line.len(), |(offset, _)| offset)
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot { lines: self.lines.clone(), cursor: self.cursor }
+    }
+
+    fn checkpoint(&mut self, kind: EditKind) {
+        if self.last_edit != Some(kind) {
+            self.undo_stack.push(self.snapshot());
+            if self.undo_stack.len() > UNDO_LIMIT {
+                self.undo_stack.remove(0);
+            }
+        }
+        self.redo_stack.clear();
+        self.last_edit = Some(kind);
+    }
+
+    fn restore(&mut self, snapshot: Snapshot) {
+        self.lines = snapshot.lines;
+        self.cursor = snapshot.cursor;
+        self.last_edit = None;
+        self.completion = None;
+    }
+
+    fn undo(&mut self) {
+        if let Some(snapshot) = self.undo_stack.pop() {
+            self.redo_stack.push(self.snapshot());
+            self.restore(snapshot);
+        }
+    }
+
+    fn redo(&mut self) {
+        if let Some(snapshot) = self.redo_stack.pop() {
+            self.undo_stack.push(self.snapshot());
+            self.restore(snapshot);
+        }
+    }
+
+    // Each paste is undone on its own
+    fn paste(&mut self, text: &str) {
+        self.last_edit = None;
+        self.checkpoint(EditKind::Insert);
+        self.insert(text);
+        self.last_edit = None;
+    }
+
+    fn insert(&mut self, text: &str) {
+        for c in text.chars() {
+            if c == '\n' {
+                let offset = self.byte_offset(self.cursor.column);
+                let rest = self.lines[self.cursor.row].split_off(offset);
+                self.lines.insert(self.cursor.row + 1, rest);
+                self.cursor = Cursor { row: self.cursor.row + 1, column: 0 };
+            } else {
+                let offset = self.byte_offset(self.cursor.column);
+                self.lines[self.cursor.row].insert(offset, c);
+                self.cursor.column += 1;
+            }
+        }
+    }
+
+    // Removes the characters of the cursor's line between two columns and moves the cursor to the first
+    fn remove(&mut self, from: usize, to: usize) -> String {
+        let (start, end) = (self.byte_offset(from), self.byte_offset(to));
+        let removed = self.lines[self.cursor.row].drain(start..end).collect();
+        self.cursor.column = from;
+        removed
+    }
+
+    fn join_next_line(&mut self) {
+        let line = self.lines.remove(self.cursor.row + 1);
+        self.lines[self.cursor.row].push_str(&line);
+    }
+
+    fn kill(&mut self, from: usize, to: usize) {
+        if from < to {
+            self.checkpoint(EditKind::Kill);
+            self.kill_buffer = self.remove(from, to);
+        }
+    }
+
+    fn word_start(&self) -> usize {
+        let line = self.line();
+        let mut column = self.cursor.column;
+        while column > 0 && !is_word(line[column - 1]) {
+            column -= 1;
+        }
+        while column > 0 && is_word(line[column - 1]) {
+            column -= 1;
+        }
+        column
+    }
+
+    fn word_end(&self) -> usize {
+        let line = self.line();
+        let mut column = self.cursor.column;
+        while column < line.len() && !is_word(line[column]) {
+            column += 1;
+        }
+        while column < line.len() && is_word(line[column]) {
+            column += 1;
+        }
+        column
+    }
+
+    // Returns whether the text changed
+    fn edit(&mut self, key: Key) -> bool {
+        let Cursor { row, column } = self.cursor;
+        let length = self.line_length(row);
+        match key {
+            Key::Char('\t') => {
+                self.checkpoint(EditKind::Insert);
+                self.insert(&" ".repeat(TAB_WIDTH));
+            },
+            Key::Char(c) => {
+                self.checkpoint(EditKind::Insert);
+                self.insert(&c.to_string());
+            },
+            Key::Backspace if column > 0 => {
+                self.checkpoint(EditKind::Delete);
+                self.remove(column - 1, column);
+            },
+            Key::Backspace if row > 0 => {
+                self.checkpoint(EditKind::Delete);
+                self.cursor = Cursor { row: row - 1, column: self.line_length(row - 1) };
+                self.join_next_line();
+            },
+            Key::Delete if column < length => {
+                self.checkpoint(EditKind::Delete);
+                self.remove(column, column + 1);
+            },
+            Key::Delete if row + 1 < self.lines.len() => {
+                self.checkpoint(EditKind::Delete);
+                self.join_next_line();
+            },
+            Key::Ctrl('w') => self.kill(self.word_start(), column),
+            Key::Alt('d') => self.kill(column, self.word_end()),
+            Key::Ctrl('u') => self.kill(0, column),
+            Key::Ctrl('k') => self.kill(column, length),
+            _ => return false,
+        }
+        true
+    }
+
+    fn move_cursor(&mut self, key: Key) {
+        let Cursor { row, column } = self.cursor;
+        let length = self.line_length(row);
+        let lines = self.lines.len();
+        self.cursor = match key {
+            Key::Left if column > 0 => Cursor { row, column: column - 1 },
+            Key::Left if row > 0 => Cursor { row: row - 1, column: self.line_length(row - 1) },
+            Key::Right if column < length => Cursor { row, column: column + 1 },
+            Key::Right if row + 1 < lines => Cursor { row: row + 1, column: 0 },
+            Key::Up if row > 0 => Cursor { row: row - 1, column: column.min(self.line_length(row - 1)) },
+            Key::Down if row + 1 < lines => Cursor { row: row + 1, column: column.min(self.line_length(row + 1)) },
+            Key::Alt('b') if column == 0 && row > 0 => Cursor { row: row - 1, column: self.line_length(row - 1) },
+            Key::Alt('b') => Cursor { row, column: self.word_start() },
+            Key::Alt('f') if column == length && row + 1 < lines => Cursor { row: row + 1, column: 0 },
+            Key::Alt('f') => Cursor { row, column: self.word_end() },
+            Key::Home | Key::Ctrl('a') => Cursor { row, column: 0 },
+            Key::End => Cursor { row, column: length },
+            _ => return,
+        };
+        self.last_edit = None;
+    }
+
+    fn find_completion(&self) -> Option<Completion> {
+        let line = self.line();
+        let column = self.cursor.column;
+        let word_start = (0..column).rev().take_while(|i| is_word(line[*i])).last().unwrap_or(column);
+        let trigger = *line.get(word_start.checked_sub(1)?)?;
+        if word_start >= 2 && line[word_start - 2].is_alphanumeric() {
+            return None;
+        }
+
+        let prefix = line[word_start..column].iter().collect::<String>().to_lowercase();
+        let candidates: Vec<(String, String)> = match trigger {
+            '@' => self.references.users.iter()
+                .filter(|user| user.to_lowercase().starts_with(&prefix))
+                .map(|user| (format!("@{}", user), user.clone()))
+                .take(MAX_COMPLETIONS)
+                .collect(),
+            '#' => self.references.issues.iter()
+                .filter(|issue| issue.number.to_string().starts_with(&prefix) ||
+                        (!prefix.is_empty() && issue.title.to_lowercase().contains(&prefix)))
+                .map(|issue| (format!("#{}", issue.number), format!("#{} {}", issue.number, issue.title)))
+                .take(MAX_COMPLETIONS)
+                .collect(),
+            _ => return None,
+        };
+        if candidates.is_empty() {
+            None
+        } else {
+            Some(Completion { start: word_start - 1, candidates, selected: 0 })
+        }
+    }
+
+    fn accept_completion(&mut self) {
+        if let Some(completion) = self.completion.take() {
+            let text = &completion.candidates[completion.selected].0;
+            self.checkpoint(EditKind::Insert);
+            self.remove(completion.start, self.cursor.column);
+            self.insert(&format!("{} ", text));
+            // The next edit is undone on its own
+            self.last_edit = None;
+        }
+    }
+
+    // Rows of the text soft-wrapped to the width: the line and the range of its characters
+    fn wrapped_rows(&self, width: usize) -> Vec<(usize, usize, usize)> {
+        let mut rows = vec![];
+        for (index, line) in self.lines.iter().enumerate() {
+            let chars: Vec<char> = line.chars().collect();
+            rows.extend(wrap_line(&chars, width).into_iter().map(|(start, end)| (index, start, end)));
+        }
+        rows
+    }
+}
+
+fn is_word(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '-'
+}
+
+// Ranges of the characters that fit the width, broken after a space where possible
+fn wrap_line(line: &[char], width: usize) -> Vec<(usize, usize)> {
+    let mut rows = vec![];
+    let mut start = 0;
+    while start < line.len() {
+        let mut used = 0;
+        let mut end = start;
+        let mut last_space = None;
+        while end < line.len() {
+            let char_width = line[end].width().unwrap_or(0);
+            if used + char_width > width {
+                break;
+            }
+            used += char_width;
+            if line[end] == ' ' {
+                last_space = Some(end);
+            }
+            end += 1;
+        }
+        if end < line.len() {
+            end = match last_space {
+                Some(space) if space > start => space + 1,
+                _ => end.max(start + 1),
+            };
+        }
+        rows.push((start, end));
+        start = end;
+    }
+    if rows.is_empty() {
+        rows.push((0, 0));
+    }
+    rows
+}
+
+impl InteractableScreen for TextEditor {
+    fn validate_input(&self, input: Action) -> bool {
+        match input {
+            Action::Undo => !self.undo_stack.is_empty(),
+            Action::Redo => !self.redo_stack.is_empty(),
+            Action::Paste => !self.kill_buffer.is_empty(),
+            Action::TogglePreview => true,
+            // Closes the completion, the screen handles it otherwise
+            Action::Back => self.completion.is_some(),
+            _ => false,
+        }
+    }
+
+    fn process_input(&mut self, input: Action, _count: usize) {
+        if self.is_sending {
+            return;
+        }
+        match input {
+            Action::Undo => self.undo(),
+            Action::Redo => self.redo(),
+            Action::Paste => {
+                let text = self.kill_buffer.clone();
+                self.paste(&text);
+            },
+            Action::TogglePreview => self.show_preview = !self.show_preview,
+            Action::Back => self.completion = None,
+            _ => (),
+        }
+    }
+
+    // Text pasted into the terminal is taken as is, completions aren't accepted with its new lines
+    fn process_paste(&mut self, text: &str) {
+        if self.is_sending {
+            return;
+        }
+        self.paste(text);
+        self.completion = None;
+    }
+
+    fn process_text_input(&mut self, key: Key) {
+        if self.is_sending {
+            return;
+        }
+        if let Some(completion) = self.completion.as_mut() {
+            let count = completion.candidates.len();
+            match key {
+                Key::Down => return completion.selected = (completion.selected + 1) % count,
+                Key::Up => return completion.selected = (completion.selected + count - 1) % count,
+                Key::Char('\t') | Key::Char('\n') => return self.accept_completion(),
+                _ => (),
+            }
+        }
+
+        if self.edit(key) {
+            self.completion = self.find_completion();
+        } else {
+            self.move_cursor(key);
+            self.completion = None;
+        }
+    }
+}
+
+// Popup with the editor, the rendered text next to it if the preview is on,
+// and the completion candidates under the cursor
+pub struct TextEditorView<'a> {
+    editor: &'a TextEditor,
+    title: &'a str,
+    theme: &'a Theme,
+}
+
+impl<'a> TextEditorView<'a> {
+    pub fn new(editor: &'a TextEditor, title: &'a str, theme: &'a Theme) -> Self {
+        TextEditorView { editor, title, theme }
+    }
+
+    // Returns where the cursor is drawn
+    fn render_text(&self, area: Rect, buf: &mut Buffer) -> Option<(u16, u16)> {
+        let editor = self.editor;
+        let width = area.width as usize;
+        let height = area.height as usize;
+        let rows = editor.wrapped_rows(width);
+        let Cursor { row, column } = editor.cursor;
+        let cursor_row = rows.iter().rposition(|(line, start, _)| *line == row && *start <= column).unwrap_or(0);
+
+        let mut offset = editor.offset.get().min(cursor_row);
+        if cursor_row >= offset + height {
+            offset = cursor_row + 1 - height;
+        }
+        editor.offset.set(offset);
+
+        for (y, (line, start, end)) in (area.y..area.bottom()).zip(rows.iter().skip(offset)) {
+            let text: String = editor.lines[*line].chars().skip(*start).take(end - start).collect();
+            buf.set_stringn(area.x, y, text, width, self.theme.text);
+        }
+
+        let (_, start, _) = rows[cursor_row];
+        let x: usize = editor.lines[row].chars().skip(start).take(column - start).map(|c| c.width().unwrap_or(0)).sum();
+        let (x, y) = if x < width { (x, cursor_row - offset) } else { (0, cursor_row - offset + 1) };
+        (y < height).then(|| (area.x + x as u16, area.y + y as u16))
+    }
+
+    fn render_completion(&self, completion: &Completion, cursor: (u16, u16), area: Rect, buf: &mut Buffer) {
+        let label_width = completion.candidates.iter().map(|(_, label)| label.chars().count()).max().unwrap_or(0);
+        let width = (label_width as u16 + 2).min(area.width);
+        let height = (completion.candidates.len() as u16 + 2).min(area.height);
+        let x = cursor.0.min(area.right().saturating_sub(width));
+        let y = if cursor.1 + 1 + height <= area.bottom() {
+            cursor.1 + 1
+        } else {
+            cursor.1.saturating_sub(height).max(area.y)
+        };
+        let popup = Rect::new(x, y, width, height);
+        let block = Block::default().borders(Borders::ALL).border_style(self.theme.dimmed);
+        let inner = block.inner(popup);
+        Clear.render(popup, buf);
+        block.render(popup, buf);
+        for (index, (y, (_, label))) in (inner.y..inner.bottom()).zip(completion.candidates.iter()).enumerate() {
+            let style = if index == completion.selected { self.theme.selection } else { self.theme.text };
+            buf.set_stringn(inner.x, y, label, inner.width as usize, style);
+        }
+    }
+}
+
+impl<'a> Widget for TextEditorView<'a> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let editor = self.editor;
+        let popup = centered_rect(area, area.width * 4 / 5, area.height * 3 / 5);
+        let title = if editor.is_sending {
+            format!("{} · sending…", self.title)
+        } else {
+            format!("{} · <C-s> to send, <C-e> to open $EDITOR, <C-p> to preview, <Esc> to cancel", self.title)
+        };
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .border_style(self.theme.title)
+            .title(Span::styled(title, self.theme.title));
+        let inner = block.inner(popup);
+        Clear.render(popup, buf);
+        block.render(popup, buf);
+        if inner.width < 2 || inner.height == 0 {
+            return;
+        }
+
+        let text_area = if editor.show_preview {
+            let panes = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+                .split(inner);
+            let text = editor.text();
+            Paragraph::new(markdown_text(&text, self.theme))
+                .wrap(Wrap { trim: false })
+                .block(Block::default().borders(Borders::LEFT).border_style(self.theme.dimmed))
+                .render(panes[1], buf);
+            panes[0]
+        } else {
+            inner
+        };
+
+        let cursor = self.render_text(text_area, buf);
+        if let (Some(cursor), false) = (cursor, editor.is_sending) {
+            let cell = buf.get_mut(cursor.0, cursor.1);
+            cell.set_style(cell.style().add_modifier(Modifier::REVERSED));
+            if let Some(completion) = editor.completion.as_ref() {
+                self.render_completion(completion, cursor, area, buf);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::backend::pr::IssueReference;
+
+    fn type_keys(editor: &mut TextEditor, keys: &[Key]) {
+        for key in keys {
+            editor.process_text_input(*key);
+        }
+    }
+
+    fn type_text(editor: &mut TextEditor, text: &str) {
+        for c in text.chars() {
+            editor.process_text_input(Key::Char(c));
+        }
+    }
+
+    #[test]
+    fn editing_across_lines() {
+        let mut editor = TextEditor::new("héllo");
+        type_keys(&mut editor, &[Key::Left, Key::Left, Key::Char('\n'), Key::Char('x'), Key::Up, Key::End, Key::Delete, Key::Backspace]);
+        assert_eq!(editor.text(), "héxlo");
+        assert_eq!(editor.cursor, Cursor { row: 0, column: 2 });
+    }
+
+    #[test]
+    fn undo_groups_edits() {
+        let mut editor = TextEditor::new("");
+        type_text(&mut editor, "one two");
+        type_keys(&mut editor, &[Key::Ctrl('w')]);
+        type_text(&mut editor, "three");
+        assert_eq!(editor.text(), "one three");
+
+        editor.process_input(Action::Undo, 1);
+        assert_eq!(editor.text(), "one ");
+        editor.process_input(Action::Undo, 1);
+        assert_eq!(editor.text(), "one two");
+        editor.process_input(Action::Paste, 1);
+        assert_eq!(editor.text(), "one twotwo");
+        editor.process_input(Action::Undo, 1);
+        editor.process_input(Action::Undo, 1);
+        assert_eq!(editor.text(), "");
+        assert!(!editor.validate_input(Action::Undo));
+        editor.process_input(Action::Redo, 1);
+        assert_eq!(editor.text(), "one two");
+    }
+
+    #[test]
+    fn completes_references() {
+        let references = References {
+            users: vec!["alice".to_string(), "bob".to_string()],
+            issues: vec![IssueReference { number: 12, title: "Fix the build".to_string() }],
+        };
+        let mut editor = TextEditor::new("").references(references);
+        type_text(&mut editor, "cc @B");
+        assert!(editor.validate_input(Action::Back));
+        type_keys(&mut editor, &[Key::Char('\t')]);
+        type_text(&mut editor, "see #build");
+        type_keys(&mut editor, &[Key::Char('\n')]);
+        assert_eq!(editor.text(), "cc @bob see #12 ");
+
+        type_text(&mut editor, "a@b");
+        assert!(editor.completion.is_none());
+    }
+
+    #[test]
+    fn pasted_new_lines_are_inserted() {
+        let references = References { users: vec!["alice".to_string()], issues: vec![] };
+        let mut editor = TextEditor::new("").references(references);
+        type_text(&mut editor, "@a");
+        editor.process_paste("\nnext line");
+        assert_eq!(editor.text(), "@a\nnext line");
+        assert!(editor.completion.is_none());
+        editor.process_input(Action::Undo, 1);
+        assert_eq!(editor.text(), "@a");
+    }
+
+    #[test]
+    fn wraps_after_spaces() {
+        let line: Vec<char> = "one two three".chars().collect();
+        assert_eq!(wrap_line(&line, 8), vec![(0, 8), (8, 13)]);
+        let word: Vec<char> = "abcdef".chars().collect();
+        assert_eq!(wrap_line(&word, 4), vec![(0, 4), (4, 6)]);
+    }
+}
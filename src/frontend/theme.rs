@@ -0,0 +1,278 @@
+use tui::style::{Color, Modifier, Style};
+use toml::Value;
+use crate::backend::pr::PrReviewVerdict;
+
+macro_rules! theme_styles {
+    ($($field:ident),* $(,)?) => {
+        #[derive(Debug, Clone)]
+        pub struct Theme {
+            $(pub $field: Style,)*
+        }
+
+        impl Theme {
+            pub const STYLE_NAMES: &'static [&'static str] = &[$(stringify!($field),)*];
+
+            fn style_mut(&mut self, name: &str) -> Option<&mut Style> {
+                match name {
+                    $(stringify!($field) => Some(&mut self.$field),)*
+                    _ => None,
+                }
+            }
+
+            fn map_styles<F: Fn(Style) -> Style>(mut self, f: F) -> Self {
+                $(self.$field = f(self.$field);)*
+                self
+            }
+        }
+    };
+}
+
+theme_styles! {
+    text,
+    selection,
+    border,
+    title,
+    dimmed,
+    author,
+    timestamp,
+    approved,
+    changes_requested,
+    commented,
+    diff_added,
+    diff_removed,
+    diff_context,
+    diff_header,
+    code,
+    link,
+    error,
+}
+
+pub const THEME_NAMES: &[&str] = &["dark", "light", "16color"];
+
+impl Theme {
+    pub fn new(name: &str, overrides: &[(String, StyleSpec)], no_color: bool) -> Theme {
+        let mut theme = match name {
+            "light" => Theme::light(),
+            "16color" => Theme::basic(),
+            _ => Theme::dark(),
+        };
+
+        for (style_name, spec) in overrides {
+            if let Some(style) = theme.style_mut(style_name) {
+                *style = spec.apply(*style);
+            }
+        }
+
+        if no_color {
+            theme = theme.map_styles(|s| Style { fg: None, bg: None, ..s });
+        }
+        theme
+    }
+
+    pub fn has_style(name: &str) -> bool {
+        Theme::STYLE_NAMES.contains(&name)
+    }
+
+    pub fn verdict(&self, verdict: &PrReviewVerdict) -> Style {
+        match verdict {
+            PrReviewVerdict::Approve => self.approved,
+            PrReviewVerdict::ChangesRequested => self.changes_requested,
+            PrReviewVerdict::Comment => self.commented,
+        }
+    }
+
+    pub fn diff_line(&self, line: &str) -> Style {
+        if line.starts_with("@@") {
+            self.diff_header
+        } else if line.starts_with('+') {
+            self.diff_added
+        } else if line.starts_with('-') {
+            self.diff_removed
+        } else {
+            self.diff_context
+        }
+    }
+
+    fn dark() -> Theme {
+        Theme {
+            text: Style::default(),
+            selection: Style::default().bg(Color::Rgb(60, 60, 70)).add_modifier(Modifier::BOLD),
+            border: Style::default().fg(Color::Rgb(110, 110, 120)),
+            title: Style::default().fg(Color::Rgb(220, 220, 230)).add_modifier(Modifier::BOLD),
+            dimmed: Style::default().fg(Color::Rgb(130, 130, 140)),
+            author: Style::default().fg(Color::Rgb(120, 180, 250)).add_modifier(Modifier::BOLD),
+            timestamp: Style::default().fg(Color::Rgb(130, 130, 140)),
+            approved: Style::default().fg(Color::Rgb(90, 200, 110)),
+            changes_requested: Style::default().fg(Color::Rgb(240, 90, 90)),
+            commented: Style::default().fg(Color::Rgb(200, 200, 210)),
+            diff_added: Style::default().fg(Color::Rgb(90, 200, 110)),
+            diff_removed: Style::default().fg(Color::Rgb(240, 90, 90)),
+            diff_context: Style::default(),
+            diff_header: Style::default().fg(Color::Rgb(110, 170, 220)),
+            code: Style::default().fg(Color::Rgb(230, 180, 120)),
+            link: Style::default().fg(Color::Rgb(120, 180, 250)).add_modifier(Modifier::UNDERLINED),
+            error: Style::default().fg(Color::Rgb(240, 90, 90)).add_modifier(Modifier::BOLD),
+        }
+    }
+
+    fn light() -> Theme {
+        Theme {
+            text: Style::default(),
+            selection: Style::default().bg(Color::Rgb(215, 225, 240)).add_modifier(Modifier::BOLD),
+            border: Style::default().fg(Color::Rgb(150, 150, 160)),
+            title: Style::default().fg(Color::Rgb(30, 30, 40)).add_modifier(Modifier::BOLD),
+            dimmed: Style::default().fg(Color::Rgb(120, 120, 130)),
+            author: Style::default().fg(Color::Rgb(20, 90, 190)).add_modifier(Modifier::BOLD),
+            timestamp: Style::default().fg(Color::Rgb(120, 120, 130)),
+            approved: Style::default().fg(Color::Rgb(30, 130, 50)),
+            changes_requested: Style::default().fg(Color::Rgb(190, 30, 30)),
+            commented: Style::default().fg(Color::Rgb(60, 60, 70)),
+            diff_added: Style::default().fg(Color::Rgb(30, 130, 50)),
+            diff_removed: Style::default().fg(Color::Rgb(190, 30, 30)),
+            diff_context: Style::default(),
+            diff_header: Style::default().fg(Color::Rgb(20, 90, 190)),
+            code: Style::default().fg(Color::Rgb(150, 70, 20)),
+            link: Style::default().fg(Color::Rgb(20, 90, 190)).add_modifier(Modifier::UNDERLINED),
+            error: Style::default().fg(Color::Rgb(190, 30, 30)).add_modifier(Modifier::BOLD),
+        }
+    }
+
+    // Sticks to the 16 ANSI colors, so the terminal palette decides how it looks
+    fn basic() -> Theme {
+        Theme {
+            text: Style::default(),
+            selection: Style::default().add_modifier(Modifier::REVERSED),
+            border: Style::default(),
+            title: Style::default().add_modifier(Modifier::BOLD),
+            dimmed: Style::default().add_modifier(Modifier::DIM),
+            author: Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD),
+            timestamp: Style::default().add_modifier(Modifier::DIM),
+            approved: Style::default().fg(Color::Green),
+            changes_requested: Style::default().fg(Color::Red),
+            commented: Style::default(),
+            diff_added: Style::default().fg(Color::Green),
+            diff_removed: Style::default().fg(Color::Red),
+            diff_context: Style::default(),
+            diff_header: Style::default().fg(Color::Cyan),
+            code: Style::default().fg(Color::Yellow),
+            link: Style::default().fg(Color::Blue).add_modifier(Modifier::UNDERLINED),
+            error: Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+        }
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme::dark()
+    }
+}
+
+// Style settings read from the config, either a color name or
+// a table like `{ fg = "red", bg = "#202020", modifiers = ["bold"] }`
+#[derive(Debug, Clone, Default)]
+pub struct StyleSpec {
+    fg: Option<Color>,
+    bg: Option<Color>,
+    modifiers: Option<Modifier>,
+}
+
+impl StyleSpec {
+    pub fn parse(value: &Value) -> Result<StyleSpec, String> {
+        match value {
+            Value::String(color) => Ok(StyleSpec { fg: Some(parse_color(color)?), ..StyleSpec::default() }),
+            Value::Table(table) => {
+                let mut spec = StyleSpec::default();
+                for (key, value) in table {
+                    match key.as_str() {
+                        "fg" => spec.fg = Some(parse_color(value.as_str().ok_or("`fg` must be a string")?)?),
+                        "bg" => spec.bg = Some(parse_color(value.as_str().ok_or("`bg` must be a string")?)?),
+                        "modifiers" => {
+                            let names = value.as_array().ok_or("`modifiers` must be a list")?;
+                            let mut modifiers = Modifier::empty();
+                            for name in names {
+                                modifiers |= parse_modifier(name.as_str().unwrap_or_default())?;
+                            }
+                            spec.modifiers = Some(modifiers);
+                        },
+                        _ => return Err(format!("unknown key `{}`", key)),
+                    }
+                }
+                Ok(spec)
+            },
+            _ => Err("must be a color or a table".to_string()),
+        }
+    }
+
+    fn apply(&self, style: Style) -> Style {
+        Style {
+            fg: self.fg.or(style.fg),
+            bg: self.bg.or(style.bg),
+            add_modifier: self.modifiers.unwrap_or(style.add_modifier),
+            sub_modifier: style.sub_modifier,
+        }
+    }
+}
+
+fn parse_color(name: &str) -> Result<Color, String> {
+    let color = match name {
+        "reset" => Color::Reset,
+        "black" => Color::Black,
+        "red" => Color::Red,
+        "green" => Color::Green,
+        "yellow" => Color::Yellow,
+        "blue" => Color::Blue,
+        "magenta" => Color::Magenta,
+        "cyan" => Color::Cyan,
+        "gray" => Color::Gray,
+        "dark_gray" => Color::DarkGray,
+        "light_red" => Color::LightRed,
+        "light_green" => Color::LightGreen,
+        "light_yellow" => Color::LightYellow,
+        "light_blue" => Color::LightBlue,
+        "light_magenta" => Color::LightMagenta,
+        "light_cyan" => Color::LightCyan,
+        "white" => Color::White,
+        _ => {
+            let rgb = name.strip_prefix('#')
+                .filter(|hex| hex.len() == 6)
+                .and_then(|hex| u32::from_str_radix(hex, 16).ok());
+            match (rgb, name.parse::<u8>()) {
+                (Some(rgb), _) => Color::Rgb((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8),
+                (None, Ok(index)) => Color::Indexed(index),
+                _ => return Err(format!("unknown color `{}`", name)),
+            }
+        }
+    };
+    Ok(color)
+}
+
+fn parse_modifier(name: &str) -> Result<Modifier, String> {
+    let modifier = match name {
+        "bold" => Modifier::BOLD,
+        "dim" => Modifier::DIM,
+        "italic" => Modifier::ITALIC,
+        "underlined" => Modifier::UNDERLINED,
+        "reversed" => Modifier::REVERSED,
+        "crossed_out" => Modifier::CROSSED_OUT,
+        _ => return Err(format!("unknown modifier `{}`", name)),
+    };
+    Ok(modifier)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn overrides_and_no_color() {
+        let value: Value = toml::from_str("author = { fg = \"#ff8000\", modifiers = [\"italic\"] }").unwrap();
+        let spec = StyleSpec::parse(&value["author"]).unwrap();
+        let theme = Theme::new("16color", &[("author".to_string(), spec.clone())], false);
+        assert_eq!(theme.author, Style::default().fg(Color::Rgb(255, 128, 0)).add_modifier(Modifier::ITALIC));
+
+        let theme = Theme::new("16color", &[("author".to_string(), spec)], true);
+        assert_eq!(theme.author, Style::default().add_modifier(Modifier::ITALIC));
+        assert_eq!(theme.approved.fg, None);
+    }
+}
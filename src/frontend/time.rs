@@ -0,0 +1,47 @@
+use chrono::{DateTime, Duration, Local};
+
+// Timestamps are shown relative to now ("3h ago") unless absolute ones are asked for
+pub fn format_time(timestamp: &DateTime<Local>, is_absolute: bool) -> String {
+    if is_absolute {
+        timestamp.format("%Y-%m-%d %H:%M").to_string()
+    } else {
+        relative_time(timestamp, &Local::now())
+    }
+}
+
+fn relative_time(timestamp: &DateTime<Local>, now: &DateTime<Local>) -> String {
+    let elapsed = now.signed_duration_since(*timestamp);
+    // Timestamps from the future come from a skewed clock
+    if elapsed < Duration::minutes(1) {
+        "just now".to_string()
+    } else if elapsed < Duration::hours(1) {
+        format!("{}m ago", elapsed.num_minutes())
+    } else if elapsed < Duration::days(1) {
+        format!("{}h ago", elapsed.num_hours())
+    } else if elapsed < Duration::days(30) {
+        format!("{}d ago", elapsed.num_days())
+    } else if elapsed < Duration::days(365) {
+        format!("{}mo ago", elapsed.num_days() / 30)
+    } else {
+        format!("{}y ago", elapsed.num_days() / 365)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn relative_times() {
+        let now = Local::now();
+        let ago = |duration| relative_time(&(now - duration), &now);
+        assert_eq!(ago(Duration::seconds(-30)), "just now");
+        assert_eq!(ago(Duration::seconds(59)), "just now");
+        assert_eq!(ago(Duration::minutes(5)), "5m ago");
+        assert_eq!(ago(Duration::minutes(180)), "3h ago");
+        assert_eq!(ago(Duration::hours(49)), "2d ago");
+        assert_eq!(ago(Duration::days(75)), "2mo ago");
+        assert_eq!(ago(Duration::days(800)), "2y ago");
+    }
+}
@@ -0,0 +1,525 @@
+use tui::{
+    buffer::Buffer,
+    layout::Rect,
+    style::Style,
+    widgets::{Block, StatefulWidget, Widget},
+};
+
+pub type NodeId = usize;
+
+#[derive(Debug)]
+pub struct TreeNode<T> {
+    pub data: T,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+    depth: usize,
+    is_expanded: bool,
+}
+
+// A tree of arbitrary depth with a selection and expandable nodes. Children of
+// collapsed nodes are hidden, and the selection only moves between visible nodes.
+#[derive(Debug)]
+pub struct Tree<T> {
+    nodes: Vec<TreeNode<T>>,
+    roots: Vec<NodeId>,
+    selected: NodeId,
+    // First visible row, kept by the view to follow the selection
+    offset: usize,
+}
+
+impl<T> Default for Tree<T> {
+    fn default() -> Self {
+        Tree { nodes: vec![], roots: vec![], selected: 0, offset: 0 }
+    }
+}
+
+impl<T> Tree<T> {
+    pub fn add_root(&mut self, data: T) -> NodeId {
+        let id = self.push_node(data, None, 0);
+        self.roots.push(id);
+        id
+    }
+
+    pub fn add_child(&mut self, parent: NodeId, data: T) -> NodeId {
+        let depth = self.nodes[parent].depth + 1;
+        let id = self.push_node(data, Some(parent), depth);
+        self.nodes[parent].children.push(id);
+        id
+    }
+
+    fn push_node(&mut self, data: T, parent: Option<NodeId>, depth: usize) -> NodeId {
+        self.nodes.push(TreeNode { data, parent, children: vec![], depth, is_expanded: false });
+        self.nodes.len() - 1
+    }
+
+    pub fn set_expanded(&mut self, id: NodeId, is_expanded: bool) {
+        if let Some(node) = self.nodes.get_mut(id) {
+            node.is_expanded = is_expanded && !node.children.is_empty();
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn get(&self, id: NodeId) -> Option<&T> {
+        self.nodes.get(id).map(|n| &n.data)
+    }
+
+    pub fn parent(&self, id: NodeId) -> Option<NodeId> {
+        self.nodes.get(id).and_then(|n| n.parent)
+    }
+
+    pub fn depth(&self, id: NodeId) -> usize {
+        self.nodes.get(id).map_or(0, |n| n.depth)
+    }
+
+    pub fn selected(&self) -> Option<NodeId> {
+        if self.nodes.is_empty() { None } else { Some(self.selected) }
+    }
+
+    pub fn selected_data(&self) -> Option<&T> {
+        self.selected().and_then(|id| self.get(id))
+    }
+
+    // All the nodes depth first, regardless of their expansion
+    pub fn all_nodes(&self) -> impl Iterator<Item = NodeId> + '_ {
+        let mut stack: Vec<NodeId> = self.roots.iter().rev().copied().collect();
+        std::iter::from_fn(move || {
+            let id = stack.pop()?;
+            stack.extend(self.nodes[id].children.iter().rev());
+            Some(id)
+        })
+    }
+
+    // Nodes in the order they are shown
+    pub fn visible_nodes(&self) -> impl Iterator<Item = NodeId> + '_ {
+        std::iter::successors(self.roots.first().copied(), move |id| self.next_visible(*id))
+    }
+
+    fn next_visible(&self, id: NodeId) -> Option<NodeId> {
+        let node = &self.nodes[id];
+        if node.is_expanded {
+            if let Some(child) = node.children.first() {
+                return Some(*child);
+            }
+        }
+        std::iter::successors(Some(id), |id| self.parent(*id)).find_map(|id| self.sibling(id, 1))
+    }
+
+    fn previous_visible(&self, id: NodeId) -> Option<NodeId> {
+        match self.sibling(id, -1) {
+            Some(sibling) => Some(self.last_visible_descendant(sibling)),
+            None => self.parent(id),
+        }
+    }
+
+    fn last_visible_descendant(&self, mut id: NodeId) -> NodeId {
+        while let (true, Some(child)) = (self.nodes[id].is_expanded, self.nodes[id].children.last()) {
+            id = *child;
+        }
+        id
+    }
+
+    fn sibling(&self, id: NodeId, delta: isize) -> Option<NodeId> {
+        let siblings = match self.nodes[id].parent {
+            Some(parent) => &self.nodes[parent].children,
+            None => &self.roots,
+        };
+        let position = siblings.iter().position(|s| *s == id)? as isize + delta;
+        if position < 0 { None } else { siblings.get(position as usize).copied() }
+    }
+
+    // Selects the node, expanding its ancestors so it's visible
+    pub fn select(&mut self, id: NodeId) {
+        if id >= self.nodes.len() {
+            return;
+        }
+        let mut parent = self.nodes[id].parent;
+        while let Some(p) = parent {
+            self.nodes[p].is_expanded = true;
+            parent = self.nodes[p].parent;
+        }
+        self.selected = id;
+    }
+
+    // Moves stop at the last node, so a count larger than the tree costs no more than its size
+    pub fn select_next(&mut self, count: usize) {
+        for _ in 0..count {
+            match self.selected().and_then(|id| self.next_visible(id)) {
+                Some(next) => self.selected = next,
+                None => break,
+            }
+        }
+    }
+
+    pub fn select_previous(&mut self, count: usize) {
+        for _ in 0..count {
+            match self.selected().and_then(|id| self.previous_visible(id)) {
+                Some(previous) => self.selected = previous,
+                None => break,
+            }
+        }
+    }
+
+    pub fn select_first(&mut self) {
+        if let Some(first) = self.roots.first() {
+            self.selected = *first;
+        }
+    }
+
+    pub fn select_last(&mut self) {
+        if let Some(last) = self.roots.last() {
+            self.selected = self.last_visible_descendant(*last);
+        }
+    }
+
+    // Selects the count-th node matching the predicate, hidden ones included, or the farthest one
+    pub fn select_matching<P: Fn(&T) -> bool>(&mut self, forward: bool, count: usize, predicate: P) {
+        let selected = match self.selected() {
+            Some(selected) => selected,
+            None => return,
+        };
+        let order: Vec<NodeId> = self.all_nodes().collect();
+        let position = order.iter().position(|id| *id == selected).unwrap_or(0);
+        let candidates: Box<dyn Iterator<Item = &NodeId>> = if forward {
+            Box::new(order[position + 1..].iter())
+        } else {
+            Box::new(order[..position].iter().rev())
+        };
+        let found = candidates.filter(|id| predicate(&self.nodes[**id].data)).take(count).last();
+        if let Some(id) = found.copied() {
+            self.select(id);
+        }
+    }
+
+    // Collapsing a node that can't be collapsed moves the selection to its parent instead
+    pub fn set_expansion(&mut self, is_expanded: bool) {
+        let selected = match self.selected() {
+            Some(selected) => selected,
+            None => return,
+        };
+        let node = &mut self.nodes[selected];
+        if !node.children.is_empty() && node.is_expanded != is_expanded {
+            node.is_expanded = is_expanded;
+        } else if let (false, Some(parent)) = (is_expanded, node.parent) {
+            self.nodes[parent].is_expanded = false;
+            self.selected = parent;
+        }
+    }
+
+    pub fn toggle_expansion(&mut self) {
+        if let Some(selected) = self.selected() {
+            let node = &mut self.nodes[selected];
+            node.is_expanded = !node.children.is_empty() && !node.is_expanded;
+        }
+    }
+
+    pub fn set_expansion_all(&mut self, is_expanded: bool) {
+        for node in self.nodes.iter_mut().filter(|n| !n.children.is_empty()) {
+            node.is_expanded = is_expanded;
+        }
+        if !is_expanded {
+            while let Some(parent) = self.parent(self.selected) {
+                self.selected = parent;
+            }
+        }
+    }
+
+    // Carries the expansion and the selection over from the tree this one was rebuilt from,
+    // `map` finds the data of the old nodes in this tree. A selected node that is gone is
+    // replaced by the closest visible node after it, or before it if there are none after
+    pub fn restore_state<U, F: Fn(&U) -> Option<T>>(&mut self, old: &Tree<U>, map: F) where T: PartialEq {
+        let find = |tree: &Tree<T>, data: &T| tree.all_nodes().find(|id| tree.nodes[*id].data == *data);
+        for old_node in old.nodes.iter().filter(|n| !n.children.is_empty()) {
+            if let Some(id) = map(&old_node.data).and_then(|data| find(self, &data)) {
+                self.set_expanded(id, old_node.is_expanded);
+            }
+        }
+
+        if let Some(selected) = old.selected() {
+            let visible: Vec<NodeId> = old.visible_nodes().collect();
+            let position = visible.iter().position(|id| *id == selected).unwrap_or(0);
+            let selected = visible[position..].iter().chain(visible[..position].iter().rev())
+                .filter_map(|id| map(&old.nodes[*id].data))
+                .find_map(|data| find(self, &data));
+            if let Some(id) = selected {
+                self.select(id);
+            }
+        }
+        self.offset = old.offset;
+    }
+}
+
+// Pane of a tree view that receives the input: the tree or the content of its selected node
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum Focus {
+    Tree,
+    Content,
+}
+
+#[derive(Copy, Clone, Default)]
+pub struct Prefixes<'a> {
+    pub collapsed_symbol: &'a str,
+    pub expanded_symbol: &'a str,
+    pub leaf_symbol: &'a str,
+}
+
+impl<'a> Prefixes<'a> {
+    pub fn new (collapsed_symbol: &'a str, expanded_symbol: &'a str, leaf_symbol: &'a str) -> Self {
+        Prefixes { collapsed_symbol, expanded_symbol, leaf_symbol }
+    }
+}
+
+const INDENT_WIDTH: u16 = 2;
+
+// Draws the visible part of a tree. Indentation and expansion symbols are drawn
+// here, the labels are left to the `draw_label` function.
+pub struct TreeView<'a, T> {
+    block: Block<'a>,
+    style: Style,
+    highlighted_style: Style,
+    prefixes: Prefixes<'a>,
+    draw_label: &'a dyn Fn(&T, Rect, &mut Buffer, Style),
+}
+
+impl<'a, T> TreeView<'a, T> {
+    pub fn new(draw_label: &'a dyn Fn(&T, Rect, &mut Buffer, Style)) -> Self {
+        TreeView {
+            block: Block::default(),
+            style: Style::default(),
+            highlighted_style: Style::default(),
+            prefixes: Prefixes::default(),
+            draw_label,
+        }
+    }
+
+    pub fn block(mut self, block: Block<'a>) -> Self {
+        self.block = block;
+        self
+    }
+
+    pub fn style(mut self, style: Style) -> Self {
+        self.style = style;
+        self
+    }
+
+    pub fn highlighted_style(mut self, style: Style) -> Self {
+        self.highlighted_style = style;
+        self
+    }
+
+    pub fn prefixes(mut self, prefixes: Prefixes<'a>) -> Self {
+        self.prefixes = prefixes;
+        self
+    }
+}
+
+impl<'a, T> StatefulWidget for TreeView<'a, T> {
+    type State = Tree<T>;
+
+    fn render(self, area: Rect, buf: &mut Buffer, tree: &mut Self::State) {
+        buf.set_style(area, self.style);
+        let tree_area = self.block.inner(area);
+        self.block.render(area, buf);
+        if tree.is_empty() || tree_area.width < 1 || tree_area.height < 1 { return; }
+
+        // Only the rows inside the viewport are drawn, the ones above are just counted
+        let selected_row = tree.visible_nodes().take_while(|id| *id != tree.selected).count();
+        tree.offset = follow_selection(tree.offset, selected_row, tree_area.height as usize);
+
+        for (row, id) in tree.visible_nodes().skip(tree.offset).take(tree_area.height as usize).enumerate() {
+            let node = &tree.nodes[id];
+            let style = if id == tree.selected { self.highlighted_style } else { self.style };
+            let row_area = Rect::new(tree_area.x, tree_area.y + row as u16, tree_area.width, 1);
+            buf.set_style(row_area, style);
+
+            let symbol = match (node.children.is_empty(), node.is_expanded) {
+                (false, true) => self.prefixes.expanded_symbol,
+                (false, false) => self.prefixes.collapsed_symbol,
+                (true, _) if node.depth > 0 => self.prefixes.leaf_symbol,
+                (true, _) => " ",
+            };
+            let indent = (tree.depth(id) as u16).saturating_mul(INDENT_WIDTH).min(row_area.width);
+            let (x, _) = buf.set_stringn(row_area.x + indent, row_area.y, format!("{} ", symbol), (row_area.width - indent) as usize, style);
+
+            let label_area = Rect::new(x, row_area.y, row_area.right().saturating_sub(x), 1);
+            if label_area.width > 0 {
+                (self.draw_label)(&node.data, label_area, buf, style);
+            }
+        }
+    }
+}
+
+// Scrolls the viewport just enough for the selected row to stay inside it
+fn follow_selection(offset: usize, selected_row: usize, height: usize) -> usize {
+    if height == 0 {
+        offset
+    } else if selected_row < offset {
+        selected_row
+    } else if selected_row >= offset + height {
+        selected_row + 1 - height
+    } else {
+        offset
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use proptest::prelude::*;
+
+    #[derive(Debug, Clone)]
+    enum Op {
+        Next,
+        Previous,
+        First,
+        Last,
+        Expand,
+        Collapse,
+        Toggle,
+        ExpandAll,
+        CollapseAll,
+        Select(usize),
+        SelectMatching(bool, usize),
+    }
+
+    fn op() -> impl Strategy<Value = Op> {
+        prop_oneof![
+            Just(Op::Next),
+            Just(Op::Previous),
+            Just(Op::First),
+            Just(Op::Last),
+            Just(Op::Expand),
+            Just(Op::Collapse),
+            Just(Op::Toggle),
+            Just(Op::ExpandAll),
+            Just(Op::CollapseAll),
+            any::<usize>().prop_map(Op::Select),
+            (any::<bool>(), 2..5_usize).prop_map(|(f, m)| Op::SelectMatching(f, m)),
+        ]
+    }
+
+    // Each node is attached to one of the nodes before it, or becomes a root
+    fn tree() -> impl Strategy<Value = Tree<usize>> {
+        prop::collection::vec(any::<Option<prop::sample::Index>>(), 1..40).prop_map(|parents| {
+            let mut tree = Tree::default();
+            for (index, parent) in parents.into_iter().enumerate() {
+                match parent.filter(|_| index > 0) {
+                    Some(parent) => tree.add_child(parent.index(index), index),
+                    None => tree.add_root(index),
+                };
+            }
+            tree
+        })
+    }
+
+    fn is_visible(tree: &Tree<usize>, id: NodeId) -> bool {
+        std::iter::successors(tree.parent(id), |p| tree.parent(*p)).all(|p| tree.nodes[p].is_expanded)
+    }
+
+    fn apply(tree: &mut Tree<usize>, op: &Op) {
+        match op {
+            Op::Next => tree.select_next(1),
+            Op::Previous => tree.select_previous(1),
+            Op::First => tree.select_first(),
+            Op::Last => tree.select_last(),
+            Op::Expand => tree.set_expansion(true),
+            Op::Collapse => tree.set_expansion(false),
+            Op::Toggle => tree.toggle_expansion(),
+            Op::ExpandAll => tree.set_expansion_all(true),
+            Op::CollapseAll => tree.set_expansion_all(false),
+            Op::Select(id) => tree.select(id % tree.nodes.len()),
+            Op::SelectMatching(forward, modulo) => tree.select_matching(*forward, 1, |data| data % modulo == 0),
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn selection_stays_visible(mut tree in tree(), ops in prop::collection::vec(op(), 0..50)) {
+            for op in ops.iter() {
+                apply(&mut tree, op);
+                let selected = tree.selected().unwrap();
+                prop_assert!(is_visible(&tree, selected), "{:?} hid the selection", op);
+                prop_assert!(tree.visible_nodes().any(|id| id == selected));
+            }
+        }
+
+        #[test]
+        fn visible_nodes_follow_depth_first_order(mut tree in tree(), ops in prop::collection::vec(op(), 0..20)) {
+            ops.iter().for_each(|op| apply(&mut tree, op));
+            let visible: Vec<NodeId> = tree.visible_nodes().collect();
+            let expected: Vec<NodeId> = tree.all_nodes().filter(|id| is_visible(&tree, *id)).collect();
+            prop_assert_eq!(visible, expected);
+        }
+
+        #[test]
+        fn next_and_previous_are_inverse(mut tree in tree(), ops in prop::collection::vec(op(), 0..20)) {
+            ops.iter().for_each(|op| apply(&mut tree, op));
+            let selected = tree.selected();
+            tree.select_next(1);
+            if tree.selected() != selected {
+                tree.select_previous(1);
+                prop_assert_eq!(tree.selected(), selected);
+            } else {
+                prop_assert_eq!(tree.visible_nodes().last(), selected);
+            }
+        }
+
+        #[test]
+        fn rebuilt_tree_keeps_state(mut tree in tree(), ops in prop::collection::vec(op(), 0..20)) {
+            ops.iter().for_each(|op| apply(&mut tree, op));
+            let mut rebuilt = Tree::default();
+            for node in tree.nodes.iter() {
+                match node.parent {
+                    Some(parent) => rebuilt.add_child(parent, node.data),
+                    None => rebuilt.add_root(node.data),
+                };
+            }
+            rebuilt.restore_state(&tree, |data| Some(*data));
+            prop_assert_eq!(rebuilt.selected(), tree.selected());
+            prop_assert_eq!(rebuilt.visible_nodes().collect::<Vec<_>>(), tree.visible_nodes().collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn removed_selection_moves_to_next_node() {
+        let mut tree = Tree::default();
+        for data in [1, 2, 3] {
+            tree.add_root(data);
+        }
+        tree.select(1);
+        let mut rebuilt = Tree::default();
+        for data in [1, 3] {
+            rebuilt.add_root(data);
+        }
+        rebuilt.restore_state(&tree, |data| Some(*data).filter(|d| *d != 2));
+        assert_eq!(rebuilt.selected_data(), Some(&3));
+    }
+
+    #[test]
+    fn repeated_moves_stop_at_the_ends() {
+        let mut tree = Tree::default();
+        for data in 0..6 {
+            tree.add_root(data);
+        }
+        tree.select_next(usize::MAX);
+        assert_eq!(tree.selected_data(), Some(&5));
+        tree.select_previous(2);
+        assert_eq!(tree.selected_data(), Some(&3));
+        tree.select_matching(false, 2, |data| data % 2 == 0);
+        assert_eq!(tree.selected_data(), Some(&0));
+        tree.select_matching(true, usize::MAX, |data| data % 2 == 0);
+        assert_eq!(tree.selected_data(), Some(&4));
+    }
+
+    #[test]
+    fn viewport_follows_selection() {
+        assert_eq!(follow_selection(0, 5, 10), 0);
+        assert_eq!(follow_selection(0, 10, 10), 1);
+        assert_eq!(follow_selection(20, 25, 10), 20);
+        assert_eq!(follow_selection(20, 3, 10), 3);
+        assert_eq!(follow_selection(0, 1000, 10), 991);
+    }
+}
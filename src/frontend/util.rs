@@ -71,6 +71,18 @@ impl<T> StatefulList<T> {
         self.state.borrow_mut().select(Some(i));
     }
 
+    pub fn first(&mut self) {
+        if !self.items.is_empty() {
+            self.select(0);
+        }
+    }
+
+    pub fn last(&mut self) {
+        if !self.items.is_empty() {
+            self.select(self.items.len() - 1);
+        }
+    }
+
     pub fn get_selected(&self) -> Option<&T> {
         self.state.borrow().selected().and_then(|i| self.items.get(i))
     }
@@ -83,3 +95,10 @@ impl<T> StatefulList<T> {
         self.state.borrow_mut().select(None);
     }
 }
+
+// Area of the given size in the middle of another one, clipped to it
+pub fn centered_rect(area: tui::layout::Rect, width: u16, height: u16) -> tui::layout::Rect {
+    let width = width.min(area.width);
+    let height = height.min(area.height);
+    tui::layout::Rect::new(area.x + (area.width - width) / 2, area.y + (area.height - height) / 2, width, height)
+}
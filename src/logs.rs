@@ -1,14 +1,217 @@
-use std::fs;
-use std::io::{Result, Write};
+use std::fmt::Display;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
 
-use chrono::{Local, Timelike};
+use chrono::Local;
+use regex::Regex;
 
-pub fn start_logs() -> Result<()> {
-    fs::write("log.txt", "")
+const LOG_FILE: &str = "ghterm.log";
+const MAX_LOG_SIZE: u64 = 1024 * 1024;
+const MAX_ROTATED_FILES: usize = 3;
+
+// Values of these fields can contain user content, so only their size gets logged
+const REDACTED_FIELDS: &[&str] = &["body", "diff", "query", "response", "token"];
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Level {
+    pub const NAMES: &'static [&'static str] = &["error", "warn", "info", "debug", "trace"];
+
+    pub fn from_name(name: &str) -> Option<Level> {
+        let level = match name.to_lowercase().as_str() {
+            "error" => Level::Error,
+            "warn" => Level::Warn,
+            "info" => Level::Info,
+            "debug" => Level::Debug,
+            "trace" => Level::Trace,
+            _ => return None,
+        };
+        Some(level)
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+            Level::Trace => "TRACE",
+        }
+    }
+}
+
+struct Logger {
+    level: Level,
+    path: PathBuf,
+    file: File,
+    size: u64,
+}
+
+static LOGGER: OnceLock<Mutex<Logger>> = OnceLock::new();
+
+// Until this is called, and in tests, all the messages are dropped
+pub fn start_logs(level: Level) -> io::Result<PathBuf> {
+    let path = xdg::BaseDirectories::with_prefix("ghterm")?.place_state_file(LOG_FILE)?;
+    let logger = Logger::open(path.clone(), level)?;
+    let _ = LOGGER.set(Mutex::new(logger));
+    Ok(path)
+}
+
+pub fn enabled(level: Level) -> bool {
+    LOGGER.get()
+        .and_then(|logger| logger.lock().ok().map(|l| level <= l.level))
+        .unwrap_or(false)
+}
+
+pub fn write(level: Level, target: &str, message: &str, fields: &[(&str, &dyn Display)]) {
+    if let Some(mut logger) = LOGGER.get().and_then(|l| l.lock().ok()) {
+        if level <= logger.level {
+            let line = format_line(level, target, message, fields);
+            logger.write_line(&line);
+        }
+    }
+}
+
+impl Logger {
+    fn open(path: PathBuf, level: Level) -> io::Result<Self> {
+        if fs::metadata(&path).map(|m| m.len() >= MAX_LOG_SIZE).unwrap_or(false) {
+            rotate(&path, MAX_ROTATED_FILES);
+        }
+        let file = fs::OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(Logger { level, path, file, size })
+    }
+
+    fn write_line(&mut self, line: &str) {
+        if self.size + line.len() as u64 > MAX_LOG_SIZE {
+            rotate(&self.path, MAX_ROTATED_FILES);
+            match fs::OpenOptions::new().create(true).append(true).open(&self.path) {
+                Ok(file) => {
+                    self.file = file;
+                    self.size = 0;
+                },
+                Err(_) => return,
+            }
+        }
+
+        if self.file.write_all(line.as_bytes()).is_ok() {
+            self.size += line.len() as u64;
+        }
+    }
+}
+
+// Shifts `ghterm.log` to `ghterm.log.1`, `ghterm.log.1` to `ghterm.log.2` and so on,
+// dropping the oldest file
+fn rotate(path: &Path, max_files: usize) {
+    let rotated = |index: usize| {
+        let mut name = path.as_os_str().to_owned();
+        name.push(format!(".{}", index));
+        PathBuf::from(name)
+    };
+
+    let _ = fs::remove_file(rotated(max_files));
+    for index in (1..max_files).rev() {
+        let _ = fs::rename(rotated(index), rotated(index + 1));
+    }
+    let _ = fs::rename(path, rotated(1));
 }
 
-pub fn log (message: &str) {
-    let time = Local::now();
-    let mut file = fs::OpenOptions::new().append(true).open("log.txt").unwrap();
-    write!(file, "[{:02}:{:02}:{:02}] {} \n", time.hour(), time.minute(), time.second(), message).unwrap();
+fn format_line(level: Level, target: &str, message: &str, fields: &[(&str, &dyn Display)]) -> String {
+    let mut line = format!("{} {:<5} {}: {}", Local::now().format("%Y-%m-%dT%H:%M:%S%.3f%:z"), level.label(), target, message);
+    for (key, value) in fields {
+        let value = value.to_string();
+        if REDACTED_FIELDS.contains(key) {
+            line.push_str(&format!(" {}=<{} bytes>", key, value.len()));
+        } else if value.is_empty() || value.contains(|c: char| c.is_whitespace() || c == '"' || c == '=') {
+            line.push_str(&format!(" {}={:?}", key, value));
+        } else {
+            line.push_str(&format!(" {}={}", key, value));
+        }
+    }
+
+    let mut line = redact(&line);
+    line.push('\n');
+    line
+}
+
+// Hides anything looking like a GitHub token or an authorization header
+pub fn redact(text: &str) -> String {
+    static TOKEN: OnceLock<Regex> = OnceLock::new();
+    let token = TOKEN.get_or_init(|| {
+        Regex::new(r"(gh[pousr]_[A-Za-z0-9]{20,}|github_pat_[A-Za-z0-9_]{20,}|(?i:bearer|token)\s+[A-Za-z0-9_\-.]{20,})").unwrap()
+    });
+    token.replace_all(text, "<redacted>").into_owned()
+}
+
+macro_rules! log_event {
+    ($level:expr, $message:expr $(, $key:ident = $value:expr)* $(,)?) => {
+        if $crate::logs::enabled($level) {
+            $crate::logs::write($level, module_path!(), &$message, &[$((stringify!($key), &$value as &dyn std::fmt::Display)),*]);
+        }
+    };
+}
+
+macro_rules! error {
+    ($($args:tt)*) => { $crate::logs::log_event!($crate::logs::Level::Error, $($args)*) };
+}
+
+macro_rules! warning {
+    ($($args:tt)*) => { $crate::logs::log_event!($crate::logs::Level::Warn, $($args)*) };
+}
+
+macro_rules! info {
+    ($($args:tt)*) => { $crate::logs::log_event!($crate::logs::Level::Info, $($args)*) };
+}
+
+macro_rules! debug {
+    ($($args:tt)*) => { $crate::logs::log_event!($crate::logs::Level::Debug, $($args)*) };
+}
+
+macro_rules! trace {
+    ($($args:tt)*) => { $crate::logs::log_event!($crate::logs::Level::Trace, $($args)*) };
+}
+
+pub(crate) use {log_event, error, warning, info, debug, trace};
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn fields_are_redacted() {
+        let line = format_line(Level::Info, "ghterm", "Fetched", &[
+            ("number", &42),
+            ("title", &"Fix a bug"),
+            ("body", &"some secret text"),
+            ("stderr", &"auth failed for ghp_0123456789abcdefghijABCDEF"),
+        ]);
+        assert!(line.ends_with(" INFO  ghterm: Fetched number=42 title=\"Fix a bug\" body=<16 bytes> stderr=\"auth failed for <redacted>\"\n"));
+    }
+
+    #[test]
+    fn rotation_keeps_limited_history() {
+        let dir = std::env::temp_dir().join(format!("ghterm_logs_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(LOG_FILE);
+        for run in 0..4 {
+            fs::write(&path, run.to_string()).unwrap();
+            rotate(&path, 2);
+        }
+
+        assert!(!path.exists());
+        assert_eq!(fs::read_to_string(dir.join("ghterm.log.1")).unwrap(), "3");
+        assert_eq!(fs::read_to_string(dir.join("ghterm.log.2")).unwrap(), "2");
+        assert!(!dir.join("ghterm.log.3").exists());
+    }
 }
@@ -3,26 +3,29 @@ mod frontend;
 mod app;
 mod logs;
 mod error;
+mod config;
+mod terminal;
 
 extern crate args;
 extern crate getopts;
 extern crate xdg;
 
 use getopts::Occur;
+use std::rc::Rc;
 
 use app::App;
 use args::Args;
 use backend::gh::{self, GhClient};
+use backend::git;
+use backend::cache::Cache;
+use backend::diff::DiffSource;
 use error::Error;
+use config::Config;
+use terminal::TerminalGuard;
 
-use tui::backend::{Backend, CrosstermBackend};
+use tui::backend::CrosstermBackend;
 use tui::Terminal;
 
-use crossterm::{
-    execute,
-    terminal::{enable_raw_mode, disable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
-};
-
 #[derive(Debug)]
 struct RepoParams {
     owner: String,
@@ -31,14 +34,7 @@ struct RepoParams {
 }
 
 fn main() {
-    logs::start_logs().unwrap();
-
-    let mut description = Args::new("ghterm", "A terminal application for manipulating GitHub pull requests");
-    description.flag("h", "help", "Prints help message");
-    description.option("r", "repo", "Name of the repository", "REPO", Occur::Optional, Some(":repo".to_string()));
-    description.option("o", "owner", "Owner of the repository", "OWNER", Occur::Optional, Some(":owner".to_string()));
-    description.option("n", "number", "Number of the PR to show", "NUMBER", Occur::Optional, None);
-
+    let mut description = arguments();
     description.parse(std::env::args_os()).unwrap();
 
     if description.value_of("help").unwrap() {
@@ -46,48 +42,120 @@ fn main() {
         return;
     }
 
+
+    let log_level: String = description.value_of("log-level").unwrap();
+    let log_level = match logs::Level::from_name(&log_level) {
+        Some(level) => level,
+        None => {
+            eprintln!("Unknown log level `{}`, expected one of: {}", log_level, logs::Level::NAMES.join(", "));
+            std::process::exit(1);
+        }
+    };
+    if let Err(e) = logs::start_logs(log_level) {
+        eprintln!("Failed to open the log file: {}", e);
+    }
+    logs::info!("Started", version = env!("CARGO_PKG_VERSION"));
+    terminal::install_panic_hook();
+
     match run(&description) {
         Ok(_) => (),
         Err(error) => {
+            logs::error!("Exited with an error", error = error);
             eprintln!("{}", error);
             std::process::exit(1);
         }
     }
 }
 
+fn arguments() -> Args {
+    let mut description = Args::new("ghterm", "A terminal application for manipulating GitHub pull requests");
+    description.flag("h", "help", "Prints help message");
+    description.option("r", "repo", "Name of the repository", "REPO", Occur::Optional, Some(":repo".to_string()));
+    description.option("o", "owner", "Owner of the repository", "OWNER", Occur::Optional, Some(":owner".to_string()));
+    description.option("n", "number", "Number of the PR to show", "NUMBER", Occur::Optional, None);
+    description.flag("", "offline", "Shows only the data cached during previous runs");
+    description.flag("", "local", "Takes the diff of the PR from the local repository");
+    description.option("", "range", "Reviews a range of commits of the local repository, e.g. main...feature", "RANGE", Occur::Optional, None);
+    description.option("", "log-level", "Verbosity of the log: error, warn, info, debug or trace", "LEVEL", Occur::Optional, Some("info".to_string()));
+    description
+}
+
 fn run(description: &Args) -> Result<(), Error> {
-    match gh::check_health() {
-        Ok(res) => match res {
-            false => return Err(error::Error::RefusedToAuthenticate),
-            true => ()
-        },
-        Err(e) => return Err(e),
+    let offline: bool = description.value_of("offline").unwrap();
+    if !offline {
+        match gh::check_health() {
+            Ok(res) => if !res {
+                return Err(error::Error::RefusedToAuthenticate);
+            },
+            Err(e) => return Err(e),
+        }
     }
 
-    enable_raw_mode()?;
-    let mut stdout = std::io::stdout();
-    execute!(stdout, EnterAlternateScreen)?;
-    let backend = CrosstermBackend::new(stdout);
-    let mut terminal = Terminal::new(backend)?;
+    let diff_source = get_diff_source(description)?;
+    let repo_params = get_repo_params(description, || if offline { git::github_repo() } else { gh::current_repo() })?;
+    let config = Config::load(&format!("{}/{}", repo_params.owner, repo_params.repo))?;
+    let cache = Cache::new(&repo_params.owner, &repo_params.repo);
+    let gh_client = GhClient::new(repo_params.owner, repo_params.repo, &config)?;
+    if !offline {
+        gh_client.validate(repo_params.pr_num)?;
+    }
 
-    let repo_params = get_repo_params(&description);
-    let gh_client = GhClient::new(repo_params.owner, repo_params.repo)?;
-    gh_client.validate(repo_params.pr_num)?;
-    let app = App::new(&mut terminal, gh_client);
-    let res = app.run(repo_params.pr_num);
+    let _guard = TerminalGuard::new()?;
+    let backend = CrosstermBackend::new(std::io::stdout());
+    let mut terminal = Terminal::new(backend)?;
 
-    disable_raw_mode()?;
-    execute!(
-        terminal.backend_mut(),
-        LeaveAlternateScreen,
-    )?;
+    let app = App::new(&mut terminal, gh_client, cache, offline, diff_source, Rc::new(config));
+    app.run(repo_params.pr_num)
+}
 
-    res
+fn get_diff_source(args: &Args) -> Result<DiffSource, Error> {
+    let local: bool = args.value_of("local").unwrap();
+    let range: Option<String> = args.optional_value_of("range").unwrap();
+    match (local, range) {
+        (true, Some(_)) => Err(Error::Other("--local and --range can't be used together".to_string())),
+        (true, None) => Ok(DiffSource::Local),
+        (false, Some(range)) => Ok(DiffSource::Range(range)),
+        (false, None) => Ok(DiffSource::Api),
+    }
 }
 
-fn get_repo_params(args: &Args) -> RepoParams {
-    let owner = args.value_of("owner").unwrap();
-    let repo = args.value_of("repo").unwrap();
+// gh fills in the `:owner` and `:repo` placeholders itself, the cache and the config need the actual names
+fn get_repo_params<F>(args: &Args, current_repo: F) -> Result<RepoParams, Error>
+    where F: FnOnce() -> Result<(String, String), Error> {
+
+    let owner: String = args.value_of("owner").unwrap();
+    let repo: String = args.value_of("repo").unwrap();
     let pr_num = args.optional_value_of("number").unwrap();
-    RepoParams {owner, repo, pr_num}
+    let (owner, repo) = if owner == ":owner" || repo == ":repo" {
+        let (current_owner, current_repo) = current_repo()?;
+        (if owner == ":owner" { current_owner } else { owner }, if repo == ":repo" { current_repo } else { repo })
+    } else {
+        (owner, repo)
+    };
+    Ok(RepoParams {owner, repo, pr_num})
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn placeholders_are_resolved() {
+        let mut description = arguments();
+        description.parse(["ghterm"]).unwrap();
+        let repo_params = get_repo_params(&description, || Ok(("Owner".to_string(), "name".to_string()))).unwrap();
+        assert_eq!((repo_params.owner.as_str(), repo_params.repo.as_str()), ("Owner", "name"));
+
+        let path = std::env::temp_dir().join(format!("ghterm_config_{}.toml", std::process::id()));
+        std::fs::write(&path, "page_size = 30\n[repos.\"owner/name\"]\npage_size = 50\n").unwrap();
+        let config = Config::load_from(&path, &format!("{}/{}", repo_params.owner, repo_params.repo)).unwrap();
+        assert_eq!(config.page_size, 50);
+        let _ = std::fs::remove_file(&path);
+
+        let mut description = arguments();
+        description.parse(["ghterm", "-o", "someone"]).unwrap();
+        let repo_params = get_repo_params(&description, || Ok(("Owner".to_string(), "name".to_string()))).unwrap();
+        assert_eq!((repo_params.owner.as_str(), repo_params.repo.as_str()), ("someone", "name"));
+    }
 }
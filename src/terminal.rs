@@ -0,0 +1,132 @@
+use std::fs;
+use std::io::Write;
+use std::panic::{self, PanicHookInfo};
+use std::path::PathBuf;
+use std::process::Command;
+use std::backtrace::Backtrace;
+
+use chrono::Local;
+use crossterm::{
+    cursor::Show,
+    execute,
+    terminal::{enable_raw_mode, disable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+};
+
+use crate::backend::task;
+use crate::error::Error;
+
+// Keeps the terminal in raw mode on the alternate screen while alive.
+// Dropping it brings the terminal back, including when unwinding from a panic.
+pub struct TerminalGuard;
+
+impl TerminalGuard {
+    pub fn new() -> Result<Self, Error> {
+        enable_raw_mode()?;
+        let guard = TerminalGuard;
+        execute!(std::io::stdout(), EnterAlternateScreen)?;
+        Ok(guard)
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        restore();
+    }
+}
+
+pub fn restore() {
+    let _ = disable_raw_mode();
+    let _ = execute!(std::io::stdout(), LeaveAlternateScreen, Show);
+}
+
+// Gives the terminal to a program, e.g. an editor, and takes it back once the program exits
+pub fn run_in_terminal(cmd: &mut Command) -> Result<(), Error> {
+    restore();
+    let status = cmd.status();
+    enable_raw_mode()?;
+    execute!(std::io::stdout(), EnterAlternateScreen)?;
+
+    let program = cmd.get_program().to_string_lossy().to_string();
+    match status {
+        Ok(status) if status.success() => Ok(()),
+        Ok(status) => Err(Error::Other(format!("{} exited with {}", program, status))),
+        Err(error) => Err(Error::Other(format!("Failed to run {}: {}", program, error))),
+    }
+}
+
+// OSC 52 has the terminal put the text on the clipboard, which also works over SSH
+pub fn copy_to_clipboard(text: &str) -> Result<(), Error> {
+    let mut stdout = std::io::stdout();
+    write!(stdout, "\x1b]52;c;{}\x07", base64(text.as_bytes()))?;
+    stdout.flush()?;
+    Ok(())
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter().enumerate().fold(0u32, |group, (i, byte)| group | (*byte as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(group >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+// Panics leave the terminal unusable, so it's restored before the panic message gets printed.
+// Panics of the tasks are reported as their failures and only get a crash report, the
+// program can't go on without any other thread, so it exits.
+pub fn install_panic_hook() {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        if task::is_worker_thread() {
+            let path = write_crash_report(info);
+            crate::logs::error!("Task panicked", crash_report = format!("{:?}", path));
+            return;
+        }
+
+        restore();
+        default_hook(info);
+        match write_crash_report(info) {
+            Some(path) => eprintln!("Crash report was written to {}", path.display()),
+            None => eprintln!("Failed to write a crash report"),
+        }
+        if std::thread::current().name() != Some("main") {
+            std::process::exit(101);
+        }
+    }));
+}
+
+fn write_crash_report(info: &PanicHookInfo) -> Option<PathBuf> {
+    let time = Local::now();
+    let path = xdg::BaseDirectories::with_prefix("ghterm").ok()?
+        .place_state_file(format!("crash-{}.txt", time.format("%Y%m%d-%H%M%S")))
+        .ok()?;
+
+    let mut file = fs::File::create(&path).ok()?;
+    let thread = std::thread::current();
+    writeln!(file, "ghterm {} crashed at {}", env!("CARGO_PKG_VERSION"), time.to_rfc3339()).ok()?;
+    writeln!(file, "thread '{}' {}", thread.name().unwrap_or("<unnamed>"), info).ok()?;
+    writeln!(file, "\n{}", Backtrace::force_capture()).ok()?;
+    Some(path)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn base64_padding() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"https://github.com/o/r/pull/1#issuecomment-2"), "aHR0cHM6Ly9naXRodWIuY29tL28vci9wdWxsLzEjaXNzdWVjb21tZW50LTI=");
+    }
+}